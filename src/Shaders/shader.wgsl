@@ -1,16 +1,44 @@
 // vertex shader
 
 struct Uniforms {   
-    model_mat : mat4x4<f32>;  
     view_project_mat : mat4x4<f32>;             
-    normal_mat : mat4x4<f32>;            
 };
 [[binding(0), group(0)]] var<uniform> uniforms : Uniforms;
 
+// per-object data, selected with a dynamic offset
+struct ObjectUniforms {
+    model_mat : mat4x4<f32>;
+    normal_mat : mat4x4<f32>;
+    ambient_intensity: f32;
+    diffuse_intensity :f32;
+    specular_intensity: f32;
+    specular_shininess: f32;
+    is_two_side: i32;
+    is_pbr: i32;
+    metallic: f32;
+    roughness: f32;
+    texture_repeat: vec2<f32>;
+    is_textured: i32;
+    opacity: f32;
+    // overlay drawn from the colormap position of the vertices
+    band_colors: array<vec4<f32>, 32>;
+    // four contour positions per entry
+    contour_positions: array<vec4<f32>, 8>;
+    contour_color: vec4<f32>;
+    num_bands: i32;
+    num_contours: i32;
+    contour_width: f32;
+    // whether positions outside [0, 1] keep the vertex color
+    out_of_range: i32;
+};
+[[binding(0), group(1)]] var<uniform> object_uniforms : ObjectUniforms;
+
 struct Input {
     [[location(0)]] pos : vec4<f32>;
     [[location(1)]] normal : vec4<f32>;
     [[location(2)]] color : vec4<f32>;
+    [[location(3)]] uv : vec2<f32>;
+    [[location(4)]] scalar : f32;
 };
 
 struct Output {
@@ -18,15 +46,19 @@ struct Output {
     [[location(0)]] v_position : vec4<f32>;
     [[location(1)]] v_normal : vec4<f32>;
     [[location(2)]] v_color : vec4<f32>;
+    [[location(3)]] v_uv : vec2<f32>;
+    [[location(4)]] v_scalar : f32;
 };
 
 [[stage(vertex)]]
 fn vs_main(in: Input) -> Output {    
     var output: Output;            
-    let m_position:vec4<f32> = uniforms.model_mat * in.pos; 
+    let m_position:vec4<f32> = object_uniforms.model_mat * in.pos; 
     output.v_position = m_position;                  
-    output.v_normal =  uniforms.normal_mat * in.normal;
+    output.v_normal =  object_uniforms.normal_mat * in.normal;
     output.v_color =  in.color;
+    output.v_uv = in.uv;
+    output.v_scalar = in.scalar;
     output.position = uniforms.view_project_mat * m_position;
     return output;
 }
@@ -34,39 +66,316 @@ fn vs_main(in: Input) -> Output {
 // fragment shader
 
 struct FragUniforms {
-    light_position : vec4<f32>;   
     eye_position : vec4<f32>;
+    wire_color : vec4<f32>;
 };
 [[binding(1), group(0)]] var<uniform> frag_uniforms : FragUniforms;
 
-struct LightUniforms {  
+struct Light {
+    position : vec4<f32>;
+    direction : vec4<f32>;
+    color : vec4<f32>;
     specular_color : vec4<f32>;
-    ambient_intensity: f32;
-    diffuse_intensity :f32;
-    specular_intensity: f32;
-    specular_shininess: f32;
-    is_two_side: i32;
+    attenuation : vec4<f32>;
+    kind : i32;
+    cos_inner : f32;
+    cos_outer : f32;
+    padding : f32;
+};
+
+struct LightUniforms {  
+    lights : array<Light, 8>;
+    num_lights : i32;
+    // index of the light casting shadows, -1 for none
+    shadow_light : i32;
 };
 [[binding(2), group(0)]] var<uniform> light_uniforms : LightUniforms;
 
-[[stage(fragment)]]
-fn fs_main(in:Output) -> [[location(0)]] vec4<f32> {
-    let N:vec3<f32> = normalize(in.v_normal.xyz);                
-    let L:vec3<f32> = normalize(frag_uniforms.light_position.xyz - in.v_position.xyz);
-    let V:vec3<f32> = normalize(frag_uniforms.eye_position.xyz - in.v_position.xyz);
+struct ShadowUniforms {
+    light_view_project_mat : mat4x4<f32>;
+    depth_bias : f32;
+    texel_size : f32;
+};
+[[binding(3), group(0)]] var<uniform> shadow_uniforms : ShadowUniforms;
+[[binding(4), group(0)]] var shadow_map : texture_depth_2d;
+[[binding(5), group(0)]] var shadow_sampler : sampler_comparison;
+
+// fraction of light reaching the point, averaged over 3x3 shadow map texels (PCF)
+fn shadow_factor(position: vec3<f32>) -> f32 {
+    let p:vec4<f32> = shadow_uniforms.light_view_project_mat * vec4<f32>(position, 1.0);
+    if(p.w <= 0.0) {
+        return 1.0;
+    }
+    let ndc:vec3<f32> = p.xyz / p.w;
+    // points outside the light frustum are lit
+    if(abs(ndc.x) > 1.0 || abs(ndc.y) > 1.0 || ndc.z > 1.0) {
+        return 1.0;
+    }
+    let uv:vec2<f32> = vec2<f32>(ndc.x * 0.5 + 0.5, -ndc.y * 0.5 + 0.5);
+    let depth:f32 = ndc.z - shadow_uniforms.depth_bias;
+    var lit:f32 = 0.0;
+    for (var x: i32 = -1; x <= 1; x = x + 1) {
+        for (var y: i32 = -1; y <= 1; y = y + 1) {
+            let offset:vec2<f32> = vec2<f32>(f32(x), f32(y)) * shadow_uniforms.texel_size;
+            lit = lit + textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, depth);
+        }
+    }
+    return lit / 9.0;
+}
+
+[[binding(0), group(2)]] var surface_texture : texture_2d<f32>;
+[[binding(1), group(2)]] var surface_sampler : sampler;
+
+let LIGHT_DIRECTIONAL : i32 = 0;
+let LIGHT_SPOT : i32 = 2;
+let PI : f32 = 3.14159265;
+
+// blinn-phong reflection of one light
+fn phong(N: vec3<f32>, L: vec3<f32>, V: vec3<f32>, color: vec3<f32>, light: Light) -> vec3<f32> {
     let H:vec3<f32> = normalize(L + V);
-    
+
     // front side
-    var diffuse:f32 = light_uniforms.diffuse_intensity * max(dot(N, L), 0.0);
-    var specular: f32 = light_uniforms.specular_intensity * pow(max(dot(N, H),0.0), light_uniforms.specular_shininess);
+    var diffuse:f32 = object_uniforms.diffuse_intensity * max(dot(N, L), 0.0);
+    var specular: f32 = object_uniforms.specular_intensity * pow(max(dot(N, H),0.0), object_uniforms.specular_shininess);
 
     // back side
-    if(light_uniforms.is_two_side == 1) {
-        diffuse = diffuse + light_uniforms.diffuse_intensity * max(dot(-N, L), 0.0);
-        specular = specular + light_uniforms.specular_intensity * pow(max(dot(-N, H),0.0), light_uniforms.specular_shininess);
-    }    
-   
-    let ambient:f32 = light_uniforms.ambient_intensity;               
-    let final_color:vec3<f32> = in.v_color.xyz*(ambient + diffuse) + light_uniforms.specular_color.xyz * specular; 
-    return vec4<f32>(final_color, 1.0);
-}
\ No newline at end of file
+    if(object_uniforms.is_two_side == 1) {
+        diffuse = diffuse + object_uniforms.diffuse_intensity * max(dot(-N, L), 0.0);
+        specular = specular + object_uniforms.specular_intensity * pow(max(dot(-N, H),0.0), object_uniforms.specular_shininess);
+    }
+    return color * light.color.xyz * diffuse + light.specular_color.xyz * specular;
+}
+
+// cook-torrance reflection of one light with the GGX distribution, Smith geometry term
+// and Schlick fresnel
+fn pbr(N: vec3<f32>, L: vec3<f32>, V: vec3<f32>, base_color: vec3<f32>, light: Light) -> vec3<f32> {
+    let H:vec3<f32> = normalize(L + V);
+    let NdotL:f32 = max(dot(N, L), 0.0);
+    let NdotV:f32 = max(dot(N, V), 1e-4);
+    let NdotH:f32 = max(dot(N, H), 0.0);
+    let roughness:f32 = max(object_uniforms.roughness, 0.04);
+    let metallic:f32 = object_uniforms.metallic;
+
+    let a2:f32 = roughness * roughness * roughness * roughness;
+    let d:f32 = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    let D:f32 = a2 / (PI * d * d);
+
+    let k:f32 = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let G:f32 = NdotV / (NdotV * (1.0 - k) + k) * NdotL / (NdotL * (1.0 - k) + k);
+
+    let F0:vec3<f32> = mix(vec3<f32>(0.04), base_color, vec3<f32>(metallic));
+    let F:vec3<f32> = F0 + (vec3<f32>(1.0) - F0) * pow(1.0 - max(dot(H, V), 0.0), 5.0);
+
+    let specular:vec3<f32> = D * G * F / (4.0 * NdotV * max(NdotL, 1e-4));
+    let kd:vec3<f32> = (vec3<f32>(1.0) - F) * (1.0 - metallic);
+    // lights are scaled by pi, so a white light on a white matte surface matches phong with diffuse 1
+    return (kd * base_color / PI + specular) * light.color.xyz * PI * NdotL;
+}
+
+// color of the band containing colormap position t, or the vertex color outside the colormap
+fn band_color(t: f32, vertex_color: vec3<f32>) -> vec3<f32> {
+    let n:i32 = object_uniforms.num_bands;
+    // NaN fails both comparisons, so undefined scalars keep the vertex color too
+    if(!(t >= 0.0 && t <= 1.0) && (object_uniforms.out_of_range == 1 || t != t)) {
+        return vertex_color;
+    }
+    let band:i32 = clamp(i32(floor(t * f32(n))), 0, n - 1);
+    return object_uniforms.band_colors[band].xyz;
+}
+
+// coverage of the contour lines at colormap position t, which changes by dt per pixel
+fn contour_coverage(t: f32, dt: f32) -> f32 {
+    var coverage:f32 = 0.0;
+    let half_width:f32 = 0.5 * object_uniforms.contour_width;
+    for (var i: i32 = 0; i < object_uniforms.num_contours; i = i + 1) {
+        let level:f32 = object_uniforms.contour_positions[i / 4][i % 4];
+        let d:f32 = abs(t - level) / max(dt, 1e-6);
+        coverage = max(coverage, 1.0 - smoothStep(half_width - 0.5, half_width + 0.5, d));
+    }
+    return coverage;
+}
+
+// lit color of the surface and its opacity
+fn shade(in:Output) -> vec4<f32> {
+    let N:vec3<f32> = normalize(in.v_normal.xyz);                
+    let V:vec3<f32> = normalize(frag_uniforms.eye_position.xyz - in.v_position.xyz);
+
+    // sampled outside of any branch, the implicit derivatives need uniform control flow
+    let texel:vec4<f32> = textureSample(surface_texture, surface_sampler, in.v_uv * object_uniforms.texture_repeat);
+    let dt:f32 = fwidth(in.v_scalar);
+    var color:vec3<f32> = in.v_color.xyz;
+    var alpha:f32 = in.v_color.w * object_uniforms.opacity;
+    if(object_uniforms.num_bands > 0) {
+        color = band_color(in.v_scalar, color);
+    }
+    if(object_uniforms.is_textured == 1) {
+        color = texel.xyz;
+        alpha = alpha * texel.w;
+    }
+    if(object_uniforms.num_contours > 0) {
+        color = mix(color, object_uniforms.contour_color.xyz, contour_coverage(in.v_scalar, dt));
+    }
+
+    let ambient:f32 = object_uniforms.ambient_intensity;               
+    var final_color:vec3<f32> = color * ambient;
+
+    // blinn-phong sum over all lights
+    for (var i: i32 = 0; i < light_uniforms.num_lights; i = i + 1) {
+        let light = light_uniforms.lights[i];
+        var L:vec3<f32> = -light.direction.xyz;
+        var attenuation:f32 = 1.0;
+        if(light.kind != LIGHT_DIRECTIONAL) {
+            let to_light:vec3<f32> = light.position.xyz - in.v_position.xyz;
+            let d:f32 = length(to_light);
+            L = to_light / max(d, 1e-6);
+            attenuation = 1.0 / max(light.attenuation.x + light.attenuation.y * d + light.attenuation.z * d * d, 1e-6);
+            if(light.kind == LIGHT_SPOT) {
+                attenuation = attenuation * smoothStep(light.cos_outer, light.cos_inner, dot(-L, light.direction.xyz));
+            }
+        }
+        if(i == light_uniforms.shadow_light) {
+            attenuation = attenuation * shadow_factor(in.v_position.xyz);
+        }
+
+        if(object_uniforms.is_pbr == 1) {
+            // two-sided surfaces show the side facing the viewer
+            var n:vec3<f32> = N;
+            if(object_uniforms.is_two_side == 1 && dot(N, V) < 0.0) {
+                n = -N;
+            }
+            final_color = final_color + attenuation * pbr(n, L, V, color, light);
+        } else {
+            final_color = final_color + attenuation * phong(N, L, V, color, light);
+        }
+    }
+    return vec4<f32>(final_color, alpha);
+}
+
+[[stage(fragment)]]
+fn fs_main(in:Output) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(shade(in).xyz, 1.0);
+}
+
+// weighted blended order-independent transparency (McGuire and Bavoil 2013)
+
+struct AccumOutput {
+    [[location(0)]] accum : vec4<f32>;
+    [[location(1)]] revealage : f32;
+};
+
+// adds the premultiplied color, weighted towards nearer and more opaque fragments,
+// and leaves the alpha for the revealage to multiply with
+[[stage(fragment)]]
+fn fs_accum(in:Output) -> AccumOutput {
+    let color:vec4<f32> = shade(in);
+    let a:f32 = clamp(color.w, 0.0, 1.0);
+    let z:f32 = in.position.z;
+    let w:f32 = clamp(pow(min(1.0, a * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - z * 0.9, 3.0), 1e-2, 3e3);
+    var output: AccumOutput;
+    output.accum = vec4<f32>(color.xyz * a, a) * w;
+    output.revealage = a;
+    return output;
+}
+
+[[binding(6), group(0)]] var oit_accum : texture_2d<f32>;
+[[binding(7), group(0)]] var oit_revealage : texture_2d<f32>;
+
+// one triangle covering the screen
+[[stage(vertex)]]
+fn vs_composite([[builtin(vertex_index)]] index: u32) -> [[builtin(position)]] vec4<f32> {
+    let p:vec2<f32> = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(p * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// weighted average color of the translucent fragments, covering 1 - revealage of the pixel
+[[stage(fragment)]]
+fn fs_composite([[builtin(position)]] position: vec4<f32>) -> [[location(0)]] vec4<f32> {
+    let coords:vec2<i32> = vec2<i32>(position.xy);
+    let revealage:f32 = textureLoad(oit_revealage, coords, 0).x;
+    if(revealage >= 1.0) {
+        discard;
+    }
+    let accum:vec4<f32> = textureLoad(oit_accum, coords, 0);
+    return vec4<f32>(accum.xyz / clamp(accum.w, 1e-5, 5e4), 1.0 - revealage);
+}
+
+// images drawn over the frame pixel for pixel, e.g. the colorbar
+
+struct ScreenInput {
+    [[location(0)]] pos : vec2<f32>;
+    [[location(1)]] texel : vec2<f32>;
+};
+
+struct ScreenOutput {
+    [[builtin(position)]] position : vec4<f32>;
+    [[location(0)]] texel : vec2<f32>;
+};
+
+[[binding(8), group(0)]] var screen_image : texture_2d<f32>;
+
+[[stage(vertex)]]
+fn vs_screen(in: ScreenInput) -> ScreenOutput {
+    var output: ScreenOutput;
+    output.position = vec4<f32>(in.pos, 0.0, 1.0);
+    output.texel = in.texel;
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_screen(in: ScreenOutput) -> [[location(0)]] vec4<f32> {
+    return textureLoad(screen_image, vec2<i32>(floor(in.texel)), 0);
+}
+
+// shadow pass, depth only
+
+[[stage(vertex)]]
+fn vs_shadow(in: Input) -> [[builtin(position)]] vec4<f32> {
+    return shadow_uniforms.light_view_project_mat * object_uniforms.model_mat * in.pos;
+}
+
+// wireframe
+
+struct WireOutput {
+    [[builtin(position)]] position : vec4<f32>;
+    [[location(0)]] barycentric : vec3<f32>;
+};
+
+// edges rasterized as lines
+[[stage(vertex)]]
+fn vs_wire(in: Input) -> WireOutput {
+    var output: WireOutput;
+    output.position = uniforms.view_project_mat * object_uniforms.model_mat * in.pos;
+    output.barycentric = vec3<f32>(0.0);
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_wire(in: WireOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(frag_uniforms.wire_color.xyz, 1.0);
+}
+
+// fallback without line rasterization: filled triangles that only keep the pixels near an edge
+struct WireInput {
+    [[location(0)]] pos : vec4<f32>;
+    [[location(1)]] barycentric : vec4<f32>;
+};
+
+[[stage(vertex)]]
+fn vs_wire_barycentric(in: WireInput) -> WireOutput {
+    var output: WireOutput;
+    output.position = uniforms.view_project_mat * object_uniforms.model_mat * in.pos;
+    output.barycentric = in.barycentric.xyz;
+    return output;
+}
+
+[[stage(fragment)]]
+fn fs_wire_barycentric(in: WireOutput) -> [[location(0)]] vec4<f32> {
+    // distance to the nearest edge in pixels, smoothed over about one pixel
+    let width:vec3<f32> = fwidth(in.barycentric);
+    let a:vec3<f32> = smoothStep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+    let edge:f32 = min(min(a.x, a.y), a.z);
+    if(edge >= 1.0) {
+        discard;
+    }
+    return vec4<f32>(frag_uniforms.wire_color.xyz, 1.0 - edge);
+}
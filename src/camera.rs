@@ -0,0 +1,122 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use std::f32::consts::FRAC_PI_2;
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+};
+
+// keep the pitch away from the poles so look_at never degenerates
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const MIN_DISTANCE: f32 = 0.1;
+const MAX_DISTANCE: f32 = 100.0;
+
+/// Camera orbiting around a target point.
+/// Left drag orbits, right drag pans and the mouse wheel zooms.
+pub struct OrbitCamera {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub rotate_speed: f32,
+    pub pan_speed: f32,
+    pub zoom_speed: f32,
+    is_rotating: bool,
+    is_panning: bool,
+    last_cursor: Option<PhysicalPosition<f64>>,
+}
+
+impl OrbitCamera {
+    /// Creates a camera at `position` looking at `target`.
+    pub fn new(position: Point3<f32>, target: Point3<f32>) -> Self {
+        let offset = position - target;
+        let distance = offset.magnitude().max(MIN_DISTANCE);
+        let yaw = offset.x.atan2(offset.z);
+        let pitch = (offset.y / distance).asin().clamp(-MAX_PITCH, MAX_PITCH);
+        Self {
+            target,
+            distance,
+            yaw,
+            pitch,
+            rotate_speed: 0.005,
+            pan_speed: 0.0015,
+            zoom_speed: 0.1,
+            is_rotating: false,
+            is_panning: false,
+            last_cursor: None,
+        }
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        let offset = Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        self.target + offset * self.distance
+    }
+
+    pub fn view_mat(&self) -> Matrix4<f32> {
+        Matrix4::look_at_rh(self.position(), self.target, Vector3::unit_y())
+    }
+
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw -= dx * self.rotate_speed;
+        self.pitch = (self.pitch + dy * self.rotate_speed).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves the target in the view plane, scaled by the distance so panning feels the same at any zoom.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let forward = (self.target - self.position()).normalize();
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let up = right.cross(forward);
+        let scale = self.pan_speed * self.distance;
+        self.target += (-right * dx + up * dy) * scale;
+    }
+
+    /// Positive steps move the camera towards the target.
+    pub fn zoom(&mut self, steps: f32) {
+        self.distance =
+            (self.distance * (1.0 - self.zoom_speed).powf(steps)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    /// Updates the camera from mouse events. Returns `true` if the event was consumed.
+    pub fn process_event(&mut self, event: &WindowEvent) -> bool {
+        match event {
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.is_rotating = pressed,
+                    MouseButton::Right | MouseButton::Middle => self.is_panning = pressed,
+                    _ => return false,
+                }
+                true
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(last) = self.last_cursor {
+                    let dx = (position.x - last.x) as f32;
+                    let dy = (position.y - last.y) as f32;
+                    if self.is_rotating {
+                        self.rotate(dx, dy);
+                    } else if self.is_panning {
+                        self.pan(dx, dy);
+                    }
+                }
+                self.last_cursor = Some(*position);
+                self.is_rotating || self.is_panning
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.last_cursor = None;
+                false
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let steps = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(p) => p.y as f32 / 50.0,
+                };
+                self.zoom(steps);
+                true
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use clap::Parser;
+use immersions_control_engine::{
+    colormap,
+    registry::SurfaceRegistry,
+    scalar,
+    scene::{ColorbarPosition, RenderMode, Scene, SurfaceDesc},
+    surface_data::Seam,
+};
+use std::path::PathBuf;
+
+/// Immersion's Control Engine: renders parametric surfaces.
+#[derive(Parser)]
+#[command(name = "ice", version, allow_negative_numbers = true)]
+pub struct Cli {
+    /// Name of the surface to render, or its position in `--list`. Defaults to the torus.
+    pub surface: Option<String>,
+
+    /// Expression for x(u, v), used instead of a named surface together with `--y` and `--z`.
+    #[arg(long, conflicts_with = "surface", allow_hyphen_values = true)]
+    pub x: Option<String>,
+
+    /// Expression for y(u, v).
+    #[arg(long, conflicts_with = "surface", allow_hyphen_values = true)]
+    pub y: Option<String>,
+
+    /// Expression for z(u, v).
+    #[arg(long, conflicts_with = "surface", allow_hyphen_values = true)]
+    pub z: Option<String>,
+
+    /// Names of the parameters used in the expressions, assigned in the order of `--params`,
+    /// which needs a value for each.
+    #[arg(long, value_delimiter = ',', value_name = "NAME,..")]
+    pub param_names: Option<Vec<String>>,
+
+    /// Show a mesh loaded from an .obj, .stl or .ply file instead of a parametric surface.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["surface", "x", "scene"])]
+    pub mesh: Option<PathBuf>,
+
+    /// Load objects, camera, lights, background and animation from a .toml, .ron or .json scene file.
+    #[arg(long, value_name = "FILE", conflicts_with = "surface")]
+    pub scene: Option<PathBuf>,
+
+    /// Draw the objects shaded, as wireframe or with their edges over the shaded surface.
+    /// Overrides the mode of the scene.
+    #[arg(long, value_name = "MODE")]
+    pub render_mode: Option<RenderModeArg>,
+
+    /// Samples per pixel for anti-aliasing: 1 or 4. Overrides the samples of the scene.
+    #[arg(long, value_name = "N")]
+    pub samples: Option<u32>,
+
+    /// Wrap a texture around the surface: `checker`, `uv_grid` or an image file.
+    #[arg(long, value_name = "NAME|FILE", conflicts_with = "scene")]
+    pub texture: Option<String>,
+
+    /// Put a ground plane below the objects that receives their shadows.
+    #[arg(long)]
+    pub ground: bool,
+
+    /// Turn the shadows off.
+    #[arg(long)]
+    pub no_shadows: bool,
+
+    /// Show a legend of the colormap with tick labels, C toggles it in the window.
+    #[arg(long)]
+    pub colorbar: bool,
+
+    /// Title of the colorbar, by default the name of the scalar. Empty for none.
+    #[arg(long, value_name = "TEXT")]
+    pub colorbar_title: Option<String>,
+
+    /// Window edge the colorbar sits at.
+    #[arg(long, value_name = "POS")]
+    pub colorbar_position: Option<ColorbarPositionArg>,
+
+    /// List the available surfaces and colormaps, then exit.
+    #[arg(long)]
+    pub list: bool,
+
+    /// Render offscreen into this image file instead of opening a window.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Write the surface mesh to this file (.obj, .stl, .ply, .gltf or .glb) instead of opening a window.
+    #[arg(long, value_name = "FILE")]
+    pub export: Option<PathBuf>,
+
+    /// Write ASCII instead of binary STL.
+    #[arg(long, requires = "export")]
+    pub stl_ascii: bool,
+
+    /// Refuse to export a mesh with holes or non-manifold edges.
+    #[arg(long, requires = "export")]
+    pub check_watertight: bool,
+
+    /// Width of the offscreen image.
+    #[arg(long, default_value_t = 1280)]
+    pub width: u32,
+
+    /// Height of the offscreen image.
+    #[arg(long, default_value_t = 720)]
+    pub height: u32,
+
+    /// Use the software adapter for offscreen rendering even if a GPU is present.
+    #[arg(long)]
+    pub fallback_adapter: bool,
+
+    #[arg(long)]
+    pub umin: Option<f32>,
+    #[arg(long)]
+    pub umax: Option<f32>,
+    #[arg(long)]
+    pub vmin: Option<f32>,
+    #[arg(long)]
+    pub vmax: Option<f32>,
+    #[arg(long)]
+    pub u_segments: Option<usize>,
+    #[arg(long)]
+    pub v_segments: Option<usize>,
+    /// How the surface closes up where u wraps around.
+    #[arg(long, value_name = "SEAM")]
+    pub u_seam: Option<SeamArg>,
+    /// How the surface closes up where v wraps around.
+    #[arg(long, value_name = "SEAM")]
+    pub v_seam: Option<SeamArg>,
+    #[arg(long)]
+    pub xmin: Option<f32>,
+    #[arg(long)]
+    pub xmax: Option<f32>,
+    #[arg(long)]
+    pub zmin: Option<f32>,
+    #[arg(long)]
+    pub zmax: Option<f32>,
+    #[arg(long)]
+    pub x_segments: Option<usize>,
+    #[arg(long)]
+    pub z_segments: Option<usize>,
+    #[arg(long)]
+    pub scale: Option<f32>,
+    #[arg(long)]
+    pub aspect: Option<f32>,
+
+    /// Colormap used to color the surface: a name from `--list`, or a `.csv` or `.json` file.
+    #[arg(long)]
+    pub colormap: Option<String>,
+
+    /// Run the colormap from its high end to its low end.
+    #[arg(long)]
+    pub reverse_colormap: bool,
+
+    /// Axis the colormap follows: x, y or z.
+    #[arg(long, value_name = "AXIS")]
+    pub colormap_direction: Option<String>,
+
+    /// Quantity the colormap follows: x, y, z, u, v, distance, normal_angle, gaussian_curvature,
+    /// mean_curvature, max_curvature, min_curvature or an expression of u, v, x, y, z, nx, ny, nz.
+    #[arg(
+        long,
+        value_name = "SCALAR",
+        allow_hyphen_values = true,
+        conflicts_with = "colormap_direction"
+    )]
+    pub scalar: Option<String>,
+
+    /// Text file with one scalar per vertex for the colormap to follow.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["colormap_direction", "scalar"])]
+    pub scalar_data: Option<PathBuf>,
+
+    /// Scalars at the ends of the colormap, e.g. `-1,1`. Defaults to the extent of the values.
+    #[arg(long, value_parser = parse_pair, value_name = "MIN,MAX", allow_hyphen_values = true)]
+    pub scalar_range: Option<[f32; 2]>,
+
+    /// Spread the colormap logarithmically over the scalars.
+    #[arg(long)]
+    pub log_scale: bool,
+
+    /// Color of scalars outside `--scalar-range`, e.g. `0.5,0.5,0.5`.
+    #[arg(long, value_parser = parse_triple, value_name = "R,G,B")]
+    pub out_of_range_color: Option<[f32; 3]>,
+
+    /// Split the colormap into this many bands of uniform color.
+    #[arg(long, value_name = "N")]
+    pub bands: Option<usize>,
+
+    /// Draw contour lines where the scalar takes these values, e.g. `-2,0,2`.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "L1,L2,..",
+        allow_hyphen_values = true
+    )]
+    pub contours: Option<Vec<f32>>,
+
+    /// Draw this many contour lines spaced evenly over the colormap.
+    #[arg(long, value_name = "N")]
+    pub contour_count: Option<usize>,
+
+    /// Color of the contour lines, black by default.
+    #[arg(long, value_parser = parse_triple, value_name = "R,G,B")]
+    pub contour_color: Option<[f32; 3]>,
+
+    /// Width of the contour lines in pixels.
+    #[arg(long, value_name = "PIXELS")]
+    pub contour_width: Option<f32>,
+
+    /// Draw the surface in a single color instead of a colormap, e.g. `1,0,0`.
+    #[arg(long, value_delimiter = ',', num_args = 3, value_name = "R,G,B")]
+    pub color: Option<Vec<f32>>,
+
+    /// Opacity at the low and high end of the colormap, interpolated linearly in between,
+    /// e.g. `0.2,1` to fade out the low end.
+    #[arg(long, value_parser = parse_pair, value_name = "LOW,HIGH")]
+    pub alpha_ramp: Option<[f32; 2]>,
+
+    /// Up to five surface parameters, e.g. `--params 1.0,0.3` for the torus radii.
+    #[arg(long, value_delimiter = ',', value_name = "P0,P1,..")]
+    pub params: Option<Vec<f32>>,
+}
+
+impl Cli {
+    /// Prints the registered surfaces, the known colormaps and the scalars they can follow.
+    pub fn print_list(registry: &SurfaceRegistry) {
+        println!("surfaces:");
+        for (i, entry) in registry.entries().iter().enumerate() {
+            println!("  {:>2}  {:<14} {}", i, entry.name, entry.description);
+        }
+        println!("colormaps:");
+        println!("  {}", colormap::COLORMAP_NAMES.join(", "));
+        println!("scalars:");
+        println!("  {}, or an expression", scalar::SCALAR_NAMES.join(", "));
+    }
+
+    /// The selected preset together with the overrides given on the command line.
+    pub fn surface_desc(&self) -> SurfaceDesc {
+        SurfaceDesc {
+            preset: match (&self.surface, &self.x, &self.y, &self.z, &self.mesh) {
+                (Some(surface), ..) => surface.clone(),
+                (None, None, None, None, None) => "torus".to_string(),
+                _ => String::new(),
+            },
+            x: self.x.clone(),
+            y: self.y.clone(),
+            z: self.z.clone(),
+            param_names: self.param_names.clone(),
+            mesh: self.mesh.clone(),
+            umin: self.umin,
+            umax: self.umax,
+            vmin: self.vmin,
+            vmax: self.vmax,
+            u_segments: self.u_segments,
+            v_segments: self.v_segments,
+            u_seam: self.u_seam.map(Seam::from),
+            v_seam: self.v_seam.map(Seam::from),
+            xmin: self.xmin,
+            xmax: self.xmax,
+            zmin: self.zmin,
+            zmax: self.zmax,
+            x_segments: self.x_segments,
+            z_segments: self.z_segments,
+            scale: self.scale,
+            aspect: self.aspect,
+            colormap: self.colormap.clone(),
+            reverse_colormap: self.reverse_colormap.then_some(true),
+            colormap_direction: self.colormap_direction.clone(),
+            scalar: self.scalar.clone(),
+            scalar_data: self.scalar_data.clone(),
+            scalar_range: self.scalar_range,
+            log_scale: self.log_scale.then_some(true),
+            out_of_range_color: self.out_of_range_color,
+            bands: self.bands,
+            contours: self.contours.clone(),
+            contour_count: self.contour_count,
+            contour_color: self.contour_color,
+            contour_width: self.contour_width,
+            color: self.color.as_ref().map(|c| [c[0], c[1], c[2]]),
+            alpha_ramp: self.alpha_ramp,
+            params: self.params.clone(),
+        }
+    }
+
+    /// Builds the scene either from `--scene` or from the selected surface.
+    pub fn scene(&self, registry: &SurfaceRegistry) -> anyhow::Result<Scene> {
+        let desc = self.surface_desc();
+        let mut scene = match &self.scene {
+            Some(path) => {
+                if desc.has_overrides() {
+                    anyhow::bail!("surface options cannot be combined with --scene");
+                }
+                Scene::load(path, registry)?
+            }
+            None => Scene::from_surface(desc.build(registry)?),
+        };
+        if let Some(mode) = self.render_mode {
+            scene.render.mode = mode.into();
+        }
+        if let Some(texture) = &self.texture {
+            for object in &mut scene.objects {
+                object.set_texture(texture)?;
+            }
+        }
+        if self.ground {
+            scene.ground.enabled = true;
+        }
+        if self.no_shadows {
+            scene.shadows.enabled = false;
+        }
+        if self.colorbar {
+            scene.colorbar.enabled = true;
+        }
+        if let Some(title) = &self.colorbar_title {
+            scene.colorbar.title = Some(title.clone());
+        }
+        if let Some(position) = self.colorbar_position {
+            scene.colorbar.position = position.into();
+        }
+        if let Some(samples) = self.samples {
+            scene.render.samples = samples;
+            scene.render.validate()?;
+        }
+        Ok(scene)
+    }
+}
+
+/// Command-line names of the `RenderMode`s.
+#[derive(Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum RenderModeArg {
+    /// Lit surfaces.
+    Shaded,
+    /// Only the triangle edges, including the ones at the back.
+    Wireframe,
+    /// The triangle edges drawn over the lit surfaces, which hide the edges behind them.
+    HiddenLine,
+}
+
+impl From<RenderModeArg> for RenderMode {
+    fn from(mode: RenderModeArg) -> Self {
+        match mode {
+            RenderModeArg::Shaded => RenderMode::Shaded,
+            RenderModeArg::Wireframe => RenderMode::Wireframe,
+            RenderModeArg::HiddenLine => RenderMode::HiddenLine,
+        }
+    }
+}
+
+/// Command-line names of the `Seam`s.
+#[derive(Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum SeamArg {
+    /// The edges are boundaries of the surface.
+    Open,
+    /// The surface closes up.
+    Periodic,
+    /// The surface closes up with the other parameter reversed, as in a Moebius strip.
+    Flipped,
+}
+
+impl From<SeamArg> for Seam {
+    fn from(seam: SeamArg) -> Self {
+        match seam {
+            SeamArg::Open => Seam::Open,
+            SeamArg::Periodic => Seam::Periodic,
+            SeamArg::Flipped => Seam::Flipped,
+        }
+    }
+}
+
+/// Command-line names of the `ColorbarPosition`s.
+#[derive(Clone, Copy, clap::ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum ColorbarPositionArg {
+    Right,
+    Left,
+    Top,
+    Bottom,
+}
+
+impl From<ColorbarPositionArg> for ColorbarPosition {
+    fn from(position: ColorbarPositionArg) -> Self {
+        match position {
+            ColorbarPositionArg::Right => ColorbarPosition::Right,
+            ColorbarPositionArg::Left => ColorbarPosition::Left,
+            ColorbarPositionArg::Top => ColorbarPosition::Top,
+            ColorbarPositionArg::Bottom => ColorbarPosition::Bottom,
+        }
+    }
+}
+
+/// Parses two comma separated numbers, e.g. `0.2,1`.
+fn parse_pair(s: &str) -> Result<[f32; 2], String> {
+    parse_values(s)
+}
+
+fn parse_triple(s: &str) -> Result<[f32; 3], String> {
+    parse_values(s)
+}
+
+/// Parses exactly `N` comma separated numbers.
+fn parse_values<const N: usize>(s: &str) -> Result<[f32; N], String> {
+    let values = s
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("'{}': {}", v, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = values.len();
+    values
+        .try_into()
+        .map_err(|_| format!("expected {} values, got {}", N, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene(args: &[&str]) -> anyhow::Result<Scene> {
+        let cli = Cli::try_parse_from(std::iter::once("ice").chain(args.iter().copied()))?;
+        cli.scene(&SurfaceRegistry::default())
+    }
+
+    fn error(args: &[&str]) -> String {
+        match scene(args) {
+            Ok(_) => panic!("expected {:?} to fail", args),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn surfaces_by_name_or_index() {
+        assert_eq!(
+            Cli::try_parse_from(["ice"]).unwrap().surface_desc().preset,
+            "torus"
+        );
+        assert!(scene(&["klein_bottle"]).is_ok());
+        assert_eq!(scene(&["1", "--u-segments", "8"]).unwrap().objects.len(), 1);
+    }
+
+    #[test]
+    fn unknown_surfaces_are_errors() {
+        let names = SurfaceRegistry::default().names().join(", ");
+        assert_eq!(
+            error(&["tours"]),
+            format!("unknown surface 'tours', expected one of: {}", names)
+        );
+        assert!(error(&["7"]).ends_with(&names));
+        assert_eq!(
+            error(&["sphere", "--v-segments", "0"]),
+            "segment counts must be at least 1, got 32x0"
+        );
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_pair("0.2, 1"), Ok([0.2, 1.0]));
+        assert_eq!(
+            parse_pair("1,2,3"),
+            Err("expected 2 values, got 3".to_string())
+        );
+        assert!(parse_triple("1,x,3").unwrap_err().starts_with("'x': "));
+    }
+}
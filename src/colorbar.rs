@@ -0,0 +1,348 @@
+use crate::{
+    colormap::Colormap,
+    scalar::ColorScale,
+    scene::{ColorbarPosition, ColorbarSettings},
+    text::{draw_text, text_size, GLYPH_HEIGHT},
+};
+use image::{Rgba, RgbaImage};
+
+/// Distance of the colorbar from the window edge in pixels.
+const MARGIN: u32 = 16;
+
+/// What the colors of a mesh stand for: the colormap and how the scalars were spread over it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorKey {
+    pub colormap: Colormap,
+    pub scale: ColorScale,
+    /// Scalars at the ends of the colormap, as resolved for the mesh.
+    pub range: [f32; 2],
+    /// Name of the scalar, the default title of the colorbar.
+    pub label: String,
+}
+
+/// Round values within `range` for about `count` tick labels: multiples of 1, 2 or 5 times
+/// a power of ten, or powers of ten on a log scale spanning at least two of them. A range
+/// of a single value gets just that tick, an undefined one none.
+pub fn ticks(range: [f32; 2], log: bool, count: usize) -> Vec<f32> {
+    let [min, max] = range;
+    if !(min.is_finite() && max.is_finite() && min <= max) {
+        return Vec::new();
+    } else if min == max {
+        return vec![min];
+    }
+    if log && min > 0.0 {
+        let (first, last) = (min.log10().ceil() as i32, max.log10().floor() as i32);
+        if last > first {
+            let stride = ((last - first + 1) as usize).div_ceil(count.max(2)).max(1);
+            return (first..=last)
+                .step_by(stride)
+                .map(|k| 10f32.powi(k))
+                .collect();
+        }
+    }
+    let step = tick_step(range, count);
+    let (first, last) = ((min / step).ceil() as i64, (max / step).floor() as i64);
+    (first..=last)
+        .map(|k| {
+            let value = k as f32 * step;
+            // multiples of a step like 0.1 pick up rounding errors around zero
+            if value.abs() < step * 1e-3 {
+                0.0
+            } else {
+                value
+            }
+        })
+        .collect()
+}
+
+/// Distance between the linear ticks of `range`: 1, 2, 2.5 or 5 times a power of ten,
+/// whichever gives the number of ticks closest to `count`, preferring fewer.
+fn tick_step([min, max]: [f32; 2], count: usize) -> f32 {
+    let raw = (max - min) / (count.max(2) - 1) as f32;
+    let magnitude = 10f32.powf(raw.log10().floor());
+    let tick_count = |step: f32| (max / step).floor() - (min / step).ceil() + 1.0;
+    [0.1, 1.0, 10.0]
+        .iter()
+        .flat_map(|m| [1.0, 2.0, 2.5, 5.0].map(|n| n * m * magnitude))
+        .min_by(|a, b| {
+            let miss = |step: f32| (tick_count(step) - count as f32).abs();
+            miss(*a).total_cmp(&miss(*b)).then(b.total_cmp(a))
+        })
+        .unwrap_or(raw)
+}
+
+/// Label of a tick with just enough digits to tell it from its neighbours `step` away.
+/// The labels of ticks up to `largest` in magnitude are written in scientific notation
+/// if that is very large or small.
+pub fn format_tick(value: f32, step: f32, largest: f32) -> String {
+    if value == 0.0 {
+        return "0".to_string();
+    }
+    let (magnitude, step) = (value.abs(), step.abs());
+    // digits after the leading one that a multiple of the step needs, e.g. 1 for 2.5
+    let digits = |exponent: f32| {
+        (0..6)
+            .find(|&d| {
+                let scaled = step / 10f32.powf(exponent - d as f32);
+                (scaled - scaled.round()).abs() < 1e-3 * scaled
+            })
+            .unwrap_or(6)
+    };
+    let step_exponent = step.log10().floor();
+    if !(1e-3..1e5).contains(&largest) {
+        let exponent = magnitude.log10().floor();
+        let precision = (exponent - step_exponent).max(0.0) as usize + digits(step_exponent);
+        format!("{:.*e}", precision, value)
+    } else {
+        let decimals = (digits(step_exponent) as f32 - step_exponent).max(0.0) as usize;
+        format!("{:.*}", decimals, value)
+    }
+}
+
+/// Labels of the ticks of `key`, paired with their positions on the colormap.
+fn tick_labels(key: &ColorKey, count: usize) -> Vec<(f32, String)> {
+    let values = ticks(key.range, key.scale.log, count);
+    let step = match values.as_slice() {
+        [a, b, ..] if !key.scale.log => b - a,
+        _ => 0.0,
+    };
+    let largest = values.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+    values
+        .iter()
+        .map(|&value| {
+            // decades are told apart by their own digits
+            let label = if step > 0.0 {
+                format_tick(value, step, largest)
+            } else {
+                format_tick(value, value, value)
+            };
+            // the lone tick of a single value range sits in the middle, like its color
+            let t = key.scale.position(key.range, value);
+            (if t.is_nan() { 0.5 } else { t }, label)
+        })
+        .filter(|(t, _)| (-1e-4..=1.0 + 1e-4).contains(t))
+        .collect()
+}
+
+/// Draws the colorbar of `key` with its ticks, labels and title on a transparent background.
+/// `window` is the size of the frame, which the length of the bar follows; the text is drawn
+/// in `text_color`.
+pub fn colorbar_image(
+    key: &ColorKey,
+    settings: &ColorbarSettings,
+    window: (u32, u32),
+    text_color: [f32; 3],
+) -> RgbaImage {
+    let s = settings.text_scale;
+    let ink = Rgba([
+        srgb_byte(text_color[0]),
+        srgb_byte(text_color[1]),
+        srgb_byte(text_color[2]),
+        255,
+    ]);
+    let glyph_height = GLYPH_HEIGHT * s;
+    let (thickness, tick_length, gap) = (8 * s, 2 * s, 2 * s);
+    let title = settings.title.clone().unwrap_or_else(|| key.label.clone());
+    let (title_width, title_height) = text_size(&title, s);
+    let title_space = if title.is_empty() {
+        0
+    } else {
+        title_height + 2 * gap
+    };
+    let labels = tick_labels(key, settings.ticks);
+    let label_width = labels
+        .iter()
+        .map(|(_, label)| text_size(label, s).0)
+        .max()
+        .unwrap_or(0);
+    let vertical = matches!(
+        settings.position,
+        ColorbarPosition::Left | ColorbarPosition::Right
+    );
+    let window_length = if vertical { window.1 } else { window.0 };
+    let length = ((settings.length * window_length as f32) as u32).max(32);
+
+    // the bar with its outline starts at `bar`, the labels stick out by half their size
+    let (size, bar) = if vertical {
+        let width = (thickness + 2 + tick_length + gap + label_width).max(title_width);
+        let height = title_space + glyph_height + length + 2;
+        ((width, height), (0, title_space + glyph_height / 2))
+    } else {
+        let width = (length + 2 + label_width).max(title_width);
+        let height = title_space + thickness + 2 + tick_length + gap + glyph_height;
+        ((width, height), ((width - length - 2) / 2, title_space))
+    };
+    let mut image = RgbaImage::new(size.0, size.1);
+
+    if !title.is_empty() {
+        let x = if vertical {
+            0
+        } else {
+            (size.0 - title_width) / 2
+        };
+        draw_text(&mut image, x as i32, gap as i32, &title, ink, s);
+    }
+
+    // outline, then the colors from the low end at the bottom or left to the high end
+    let (bar_width, bar_height) = if vertical {
+        (thickness + 2, length + 2)
+    } else {
+        (length + 2, thickness + 2)
+    };
+    fill_rect(&mut image, bar.0, bar.1, bar_width, bar_height, ink);
+    for i in 0..length {
+        let t = (i as f32 + 0.5) / length as f32;
+        let [r, g, b] = key.scale.color(&key.colormap, t);
+        let color = Rgba([srgb_byte(r), srgb_byte(g), srgb_byte(b), 255]);
+        if vertical {
+            fill_rect(
+                &mut image,
+                bar.0 + 1,
+                bar.1 + length - i,
+                thickness,
+                1,
+                color,
+            );
+        } else {
+            fill_rect(&mut image, bar.0 + 1 + i, bar.1 + 1, 1, thickness, color);
+        }
+    }
+
+    for (t, label) in &labels {
+        let offset = (t.clamp(0.0, 1.0) * (length - 1) as f32).round() as u32;
+        let (label_width, label_height) = text_size(label, s);
+        if vertical {
+            let y = bar.1 + length - offset;
+            fill_rect(&mut image, bar.0 + bar_width, y, tick_length, s.max(1), ink);
+            let x = bar.0 + bar_width + tick_length + gap;
+            draw_text(
+                &mut image,
+                x as i32,
+                y as i32 - (label_height / 2) as i32,
+                label,
+                ink,
+                s,
+            );
+        } else {
+            let x = bar.0 + 1 + offset;
+            fill_rect(
+                &mut image,
+                x,
+                bar.1 + bar_height,
+                s.max(1),
+                tick_length,
+                ink,
+            );
+            let y = bar.1 + bar_height + tick_length + gap;
+            draw_text(
+                &mut image,
+                x as i32 - (label_width / 2) as i32,
+                y as i32,
+                label,
+                ink,
+                s,
+            );
+        }
+    }
+    image
+}
+
+/// Top left corner of a colorbar image of `size` placed in `window`.
+pub fn colorbar_origin(
+    position: ColorbarPosition,
+    size: (u32, u32),
+    window: (u32, u32),
+) -> (u32, u32) {
+    let centered = (
+        window.0.saturating_sub(size.0) / 2,
+        window.1.saturating_sub(size.1) / 2,
+    );
+    let far = (
+        window.0.saturating_sub(size.0 + MARGIN),
+        window.1.saturating_sub(size.1 + MARGIN),
+    );
+    match position {
+        ColorbarPosition::Right => (far.0, centered.1),
+        ColorbarPosition::Left => (MARGIN.min(far.0), centered.1),
+        ColorbarPosition::Top => (centered.0, MARGIN.min(far.1)),
+        ColorbarPosition::Bottom => (centered.0, far.1),
+    }
+}
+
+/// Black or white, whichever stands out more against `background`.
+pub fn contrasting_color(background: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = background;
+    if 0.2126 * r + 0.7152 * g + 0.0722 * b > 0.18 {
+        [0.0, 0.0, 0.0]
+    } else {
+        [1.0, 1.0, 1.0]
+    }
+}
+
+fn fill_rect(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32, color: Rgba<u8>) {
+    for py in y..(y + height).min(image.height()) {
+        for px in x..(x + width).min(image.width()) {
+            image.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// The image is uploaded as an sRGB texture, so linear colors are encoded to come out
+/// the same as the vertex colors of the surface.
+fn srgb_byte(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_ticks() {
+        assert_eq!(ticks([0.0, 1.0], false, 5), [0.0, 0.25, 0.5, 0.75, 1.0]);
+        // seven steps of 0.1 miss five as much as three of 0.25, and fewer win
+        assert_eq!(ticks([-0.3, 0.3], false, 5), [-0.25, 0.0, 0.25]);
+        assert_eq!(ticks([3.0, 97.0], false, 5), [20.0, 40.0, 60.0, 80.0]);
+    }
+
+    #[test]
+    fn log_ticks() {
+        assert_eq!(ticks([1.0, 1000.0], true, 5), [1.0, 10.0, 100.0, 1000.0]);
+        assert_eq!(ticks([1.0, 1e6], true, 3), [1.0, 1e3, 1e6]);
+        // less than two decades falls back to linear ticks
+        assert_eq!(ticks([2.0, 9.0], true, 4), [2.0, 4.0, 6.0, 8.0]);
+    }
+
+    #[test]
+    fn degenerate_ranges() {
+        assert_eq!(ticks([1.0, 1.0], false, 5), [1.0]);
+        assert_eq!(ticks([5.0, 5.0], true, 5), [5.0]);
+        assert!(ticks([f32::NAN, 1.0], false, 5).is_empty());
+        assert!(ticks([0.0, f32::INFINITY], false, 5).is_empty());
+        assert!(ticks([1.0, 0.0], false, 5).is_empty());
+        let key = ColorKey {
+            colormap: Colormap::default(),
+            scale: ColorScale::default(),
+            range: [1.0, 1.0],
+            label: String::new(),
+        };
+        assert_eq!(tick_labels(&key, 5), [(0.5, "1".to_string())]);
+    }
+
+    #[test]
+    fn tick_labels_have_just_enough_digits() {
+        assert_eq!(format_tick(0.0, 0.25, 1.0), "0");
+        assert_eq!(format_tick(0.5, 0.25, 1.0), "0.50");
+        assert_eq!(format_tick(-0.2, 0.1, 0.2), "-0.2");
+        assert_eq!(format_tick(60.0, 20.0, 80.0), "60");
+        assert_eq!(format_tick(1000.0, 1000.0, 1000.0), "1000");
+        assert_eq!(format_tick(2e6, 1e6, 5e6), "2e6");
+        assert_eq!(format_tick(2.5e-5, 2.5e-5, 1e-4), "2.5e-5");
+    }
+}
@@ -1,185 +1,661 @@
-#![allow(dead_code)]
-pub fn color_interp(colormap_name: &str, min: f32, max: f32, mut t: f32) -> [f32; 3] {
-    if t < min {
-        t = min;
+use anyhow::Context;
+use std::{fs, path::Path};
+
+/// Built-in colormaps, usable by name wherever a colormap is expected.
+pub const COLORMAP_NAMES: [&str; 21] = [
+    "jet", "hsv", "hot", "cool", "spring", "summer", "autumn", "winter", "bone", "copper", "greys",
+    "viridis", "plasma", "inferno", "magma", "cividis", "turbo", "coolwarm", "bwr", "rdbu", "brbg",
+];
+
+/// Returns `true` if `source` looks like a file path, with a directory or an extension, rather
+/// than a colormap name.
+pub fn is_colormap_path(source: &str) -> bool {
+    let path = Path::new(source);
+    path.extension().is_some() || path.components().count() > 1
+}
+
+/// Color ramp through stops at increasing positions from 0 to 1, interpolated linearly in between.
+/// Two stops at the same position make a sharp step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colormap {
+    stops: Vec<(f32, [f32; 3])>,
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Colormap::uniform(&JET)
     }
-    if t > max {
-        t = max;
+}
+
+impl Colormap {
+    /// Colormap through `stops` given as `(position, color)`. The positions must not decrease
+    /// and are rescaled so that the first is 0 and the last is 1.
+    pub fn new(mut stops: Vec<(f32, [f32; 3])>) -> anyhow::Result<Self> {
+        if stops.len() < 2 {
+            anyhow::bail!("a colormap needs at least two stops, got {}", stops.len());
+        }
+        for (position, color) in &stops {
+            if !position.is_finite() {
+                anyhow::bail!("colormap stop positions must be finite, got {}", position);
+            }
+            if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+                anyhow::bail!(
+                    "colormap color components must be within [0, 1], got {:?}",
+                    color
+                );
+            }
+        }
+        if stops.windows(2).any(|w| w[1].0 < w[0].0) {
+            anyhow::bail!("colormap stop positions must not decrease");
+        }
+        let (first, last) = (stops[0].0, stops[stops.len() - 1].0);
+        if last <= first {
+            anyhow::bail!("colormap stops must span a range, all are at {}", first);
+        }
+        for (position, _) in &mut stops {
+            *position = (*position - first) / (last - first);
+        }
+        Ok(Colormap { stops })
     }
-    let tn = (t - min) / (max - min);
-    let colors = colormap_data(colormap_name);
-    let indx = (10.0 * tn).floor() as usize;
 
-    if indx as f32 == 10.0 * tn {
-        colors[indx]
-    } else {
-        let tn1 = (tn - 0.1 * indx as f32) * 10.0; // rescale
-        let a = colors[indx];
-        let b = colors[indx + 1];
-        let color_r = a[0] + (b[0] - a[0]) * tn1;
-        let color_g = a[1] + (b[1] - a[1]) * tn1;
-        let color_b = a[2] + (b[2] - a[2]) * tn1;
-        [color_r, color_g, color_b]
+    /// Colormap through `colors` spaced evenly.
+    pub fn uniform(colors: &[[f32; 3]]) -> Self {
+        let last = (colors.len() - 1).max(1) as f32;
+        Colormap {
+            stops: colors
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (i as f32 / last, *c))
+                .collect(),
+        }
+    }
+
+    /// One of the `COLORMAP_NAMES`.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let colors: &[[f32; 3]] = match name {
+            "jet" => &JET,
+            "hsv" => &HSV,
+            "hot" => &HOT,
+            "cool" => &COOL,
+            "spring" => &SPRING,
+            "summer" => &SUMMER,
+            "autumn" => &AUTUMN,
+            "winter" => &WINTER,
+            "bone" => &BONE,
+            "copper" => &COPPER,
+            "greys" => &GREYS,
+            "viridis" => &VIRIDIS,
+            "plasma" => &PLASMA,
+            "inferno" => &INFERNO,
+            "magma" => &MAGMA,
+            "cividis" => &CIVIDIS,
+            "turbo" => &TURBO,
+            "coolwarm" => &COOLWARM,
+            "bwr" => &BWR,
+            "rdbu" => &RDBU,
+            "brbg" => &BRBG,
+            _ => return None,
+        };
+        Some(Colormap::uniform(colors))
+    }
+
+    /// Loads one of the `COLORMAP_NAMES`, or a `.csv` or `.json` file read by `load`.
+    pub fn from_source(source: &str) -> anyhow::Result<Self> {
+        if let Some(colormap) = Colormap::builtin(source) {
+            return Ok(colormap);
+        }
+        let path = Path::new(source);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv" | "json") => Colormap::load(path),
+            _ => anyhow::bail!(
+                "unknown colormap '{}', expected one of: {}, or a .csv or .json file",
+                source,
+                COLORMAP_NAMES.join(", ")
+            ),
+        }
+    }
+
+    /// Reads a colormap from a `.csv` file with one stop per line, or from a `.json` file holding
+    /// an array of stops. A stop is `[r, g, b]` for evenly spaced colors or `[position, r, g, b]`.
+    /// Components above 1 are read as 0..255. In CSV files `#` starts a comment and a header
+    /// line is skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read colormap {}", path.display()))?;
+        let rows = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str::<Vec<Vec<f32>>>(&text)
+                .with_context(|| format!("failed to parse colormap {}", path.display()))?,
+            _ => parse_csv(&text).with_context(|| format!("in colormap {}", path.display()))?,
+        };
+        colormap_from_rows(rows).with_context(|| format!("invalid colormap {}", path.display()))
+    }
+
+    /// The same colors from the high end to the low end.
+    pub fn reversed(&self) -> Self {
+        Colormap {
+            stops: self
+                .stops
+                .iter()
+                .rev()
+                .map(|(p, c)| (1.0 - p, *c))
+                .collect(),
+        }
+    }
+
+    /// Stops as `(position, color)`, the positions going from 0 to 1.
+    pub fn stops(&self) -> &[(f32, [f32; 3])] {
+        &self.stops
     }
+
+    /// Color at position `t`, clamped to [0, 1].
+    pub fn color(&self, t: f32) -> [f32; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        // first stop at or beyond t; the last stop is at 1
+        let i = self.stops.iter().position(|(p, _)| *p >= t).unwrap_or(0);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+        let ((p0, a), (p1, b)) = (self.stops[i - 1], self.stops[i]);
+        let s = (t - p0) / (p1 - p0);
+        [0, 1, 2].map(|k| a[k] + (b[k] - a[k]) * s)
+    }
+}
+
+/// Colors `value` with `colormap` spread from `min` to `max`, clamping values outside.
+pub fn color_interp(colormap: &Colormap, min: f32, max: f32, value: f32) -> [f32; 3] {
+    colormap.color((value - min) / (max - min))
+}
+
+fn parse_csv(text: &str) -> anyhow::Result<Vec<Vec<f32>>> {
+    let mut rows = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let row: Result<Vec<f32>, _> = line.split(',').map(|v| v.trim().parse::<f32>()).collect();
+        match row {
+            Ok(row) => rows.push(row),
+            // a header
+            Err(_) if rows.is_empty() && line.chars().any(|c| c.is_alphabetic()) => {}
+            Err(e) => anyhow::bail!("line {}: {}", i + 1, e),
+        }
+    }
+    Ok(rows)
 }
 
-pub fn colormap_data(colormap_name: &str) -> [[f32; 3]; 11] {
-    let colors = match colormap_name {
-        "hsv" => [
-            [1.0, 0.0, 0.0],
-            [1.0, 0.5, 0.0],
-            [0.97, 1.0, 0.01],
-            [0.0, 0.99, 0.04],
-            [0.0, 0.98, 0.52],
-            [0.0, 0.98, 1.0],
-            [0.01, 0.49, 1.0],
-            [0.03, 0.0, 0.99],
-            [1.0, 0.0, 0.96],
-            [1.0, 0.0, 0.49],
-            [1.0, 0.0, 0.02],
-        ],
-
-        "hot" => [
-            [0.0, 0.0, 0.0],
-            [0.3, 0.0, 0.0],
-            [0.6, 0.0, 0.0],
-            [0.9, 0.0, 0.0],
-            [0.93, 0.27, 0.0],
-            [0.97, 0.55, 0.0],
-            [1.0, 0.82, 0.0],
-            [1.0, 0.87, 0.25],
-            [1.0, 0.91, 0.5],
-            [1.0, 0.96, 0.75],
-            [1.0, 1.0, 1.0],
-        ],
-
-        "cool" => [
-            [0.49, 0.0, 0.7],
-            [0.45, 0.0, 0.85],
-            [0.42, 0.15, 0.89],
-            [0.38, 0.29, 0.93],
-            [0.27, 0.57, 0.91],
-            [0.0, 0.8, 0.77],
-            [0.0, 0.97, 0.57],
-            [0.0, 0.98, 0.46],
-            [0.0, 1.0, 0.35],
-            [0.16, 1.0, 0.03],
-            [0.58, 1.0, 0.0],
-        ],
-
-        "spring" => [
-            [1.0, 0.0, 1.0],
-            [1.0, 0.1, 0.9],
-            [1.0, 0.2, 0.8],
-            [1.0, 0.3, 0.7],
-            [1.0, 0.4, 0.6],
-            [1.0, 0.5, 0.5],
-            [1.0, 0.6, 0.4],
-            [1.0, 0.7, 0.3],
-            [1.0, 0.8, 0.2],
-            [1.0, 0.9, 0.1],
-            [1.0, 1.0, 0.0],
-        ],
-
-        "summer" => [
-            [0.0, 0.5, 0.4],
-            [0.1, 0.55, 0.4],
-            [0.2, 0.6, 0.4],
-            [0.3, 0.65, 0.4],
-            [0.4, 0.7, 0.4],
-            [0.5, 0.75, 0.4],
-            [0.6, 0.8, 0.4],
-            [0.7, 0.85, 0.4],
-            [0.8, 0.9, 0.4],
-            [0.9, 0.95, 0.4],
-            [1.0, 1.0, 0.4],
-        ],
-
-        "autumn" => [
-            [1.0, 0.0, 0.0],
-            [1.0, 0.1, 0.0],
-            [1.0, 0.2, 0.0],
-            [1.0, 0.3, 0.0],
-            [1.0, 0.4, 0.0],
-            [1.0, 0.5, 0.0],
-            [1.0, 0.6, 0.0],
-            [1.0, 0.7, 0.0],
-            [1.0, 0.8, 0.0],
-            [1.0, 0.9, 0.0],
-            [1.0, 1.0, 0.0],
-        ],
-
-        "winter" => [
-            [0.0, 0.0, 1.0],
-            [0.0, 0.1, 0.95],
-            [0.0, 0.2, 0.9],
-            [0.0, 0.3, 0.85],
-            [0.0, 0.4, 0.8],
-            [0.0, 0.5, 0.75],
-            [0.0, 0.6, 0.7],
-            [0.0, 0.7, 0.65],
-            [0.0, 0.8, 0.6],
-            [0.0, 0.9, 0.55],
-            [0.0, 1.0, 0.5],
-        ],
-
-        "bone" => [
-            [0.0, 0.0, 0.0],
-            [0.08, 0.08, 0.11],
-            [0.16, 0.16, 0.23],
-            [0.25, 0.25, 0.34],
-            [0.33, 0.33, 0.45],
-            [0.41, 0.44, 0.54],
-            [0.5, 0.56, 0.62],
-            [0.58, 0.67, 0.7],
-            [0.66, 0.78, 0.78],
-            [0.83, 0.89, 0.89],
-            [1.0, 1.0, 1.0],
-        ],
-
-        "cooper" => [
-            [0.0, 0.0, 0.0],
-            [0.13, 0.08, 0.05],
-            [0.25, 0.16, 0.1],
-            [0.38, 0.24, 0.15],
-            [0.5, 0.31, 0.2],
-            [0.62, 0.39, 0.25],
-            [0.75, 0.47, 0.3],
-            [0.87, 0.55, 0.35],
-            [1.0, 0.63, 0.4],
-            [1.0, 0.71, 0.45],
-            [1.0, 0.78, 0.5],
-        ],
-
-        "greys" => [
-            [0.0, 0.0, 0.0],
-            [0.1, 0.1, 0.1],
-            [0.2, 0.2, 0.2],
-            [0.3, 0.3, 0.3],
-            [0.4, 0.4, 0.4],
-            [0.5, 0.5, 0.5],
-            [0.6, 0.6, 0.6],
-            [0.7, 0.7, 0.7],
-            [0.8, 0.8, 0.8],
-            [0.9, 0.9, 0.9],
-            [1.0, 1.0, 1.0],
-        ],
-
-        // "jet" as default
-        _ => [
-            [0.0, 0.0, 0.51],
-            [0.0, 0.24, 0.67],
-            [0.01, 0.49, 0.78],
-            [0.01, 0.75, 0.89],
-            [0.02, 1.0, 1.0],
-            [0.51, 1.0, 0.5],
-            [1.0, 1.0, 0.0],
-            [0.99, 0.67, 0.0],
-            [0.99, 0.33, 0.0],
-            [0.98, 0.0, 0.0],
-            [0.5, 0.0, 0.0],
-        ],
+fn colormap_from_rows(rows: Vec<Vec<f32>>) -> anyhow::Result<Colormap> {
+    let columns = rows.first().map_or(0, |r| r.len());
+    if !(columns == 3 || columns == 4) || rows.iter().any(|r| r.len() != columns) {
+        anyhow::bail!("every stop must be [r, g, b] or [position, r, g, b]");
+    }
+    // 0..255 components are recognized by any of them exceeding 1
+    let colors = rows.iter().map(|r| &r[columns - 3..]);
+    let factor = if colors.flatten().any(|c| *c > 1.0) {
+        1.0 / 255.0
+    } else {
+        1.0
     };
+    let last = (rows.len() - 1) as f32;
+    let stops = rows
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let position = if columns == 4 { r[0] } else { i as f32 / last };
+            let c = &r[columns - 3..];
+            (position, [c[0] * factor, c[1] * factor, c[2] * factor])
+        })
+        .collect();
+    Colormap::new(stops)
+}
+
+const JET: [[f32; 3]; 11] = [
+    [0.0, 0.0, 0.51],
+    [0.0, 0.24, 0.67],
+    [0.01, 0.49, 0.78],
+    [0.01, 0.75, 0.89],
+    [0.02, 1.0, 1.0],
+    [0.51, 1.0, 0.5],
+    [1.0, 1.0, 0.0],
+    [0.99, 0.67, 0.0],
+    [0.99, 0.33, 0.0],
+    [0.98, 0.0, 0.0],
+    [0.5, 0.0, 0.0],
+];
+
+const HSV: [[f32; 3]; 11] = [
+    [1.0, 0.0, 0.0],
+    [1.0, 0.5, 0.0],
+    [0.97, 1.0, 0.01],
+    [0.0, 0.99, 0.04],
+    [0.0, 0.98, 0.52],
+    [0.0, 0.98, 1.0],
+    [0.01, 0.49, 1.0],
+    [0.03, 0.0, 0.99],
+    [1.0, 0.0, 0.96],
+    [1.0, 0.0, 0.49],
+    [1.0, 0.0, 0.02],
+];
+
+const HOT: [[f32; 3]; 11] = [
+    [0.0, 0.0, 0.0],
+    [0.3, 0.0, 0.0],
+    [0.6, 0.0, 0.0],
+    [0.9, 0.0, 0.0],
+    [0.93, 0.27, 0.0],
+    [0.97, 0.55, 0.0],
+    [1.0, 0.82, 0.0],
+    [1.0, 0.87, 0.25],
+    [1.0, 0.91, 0.5],
+    [1.0, 0.96, 0.75],
+    [1.0, 1.0, 1.0],
+];
+
+const COOL: [[f32; 3]; 11] = [
+    [0.49, 0.0, 0.7],
+    [0.45, 0.0, 0.85],
+    [0.42, 0.15, 0.89],
+    [0.38, 0.29, 0.93],
+    [0.27, 0.57, 0.91],
+    [0.0, 0.8, 0.77],
+    [0.0, 0.97, 0.57],
+    [0.0, 0.98, 0.46],
+    [0.0, 1.0, 0.35],
+    [0.16, 1.0, 0.03],
+    [0.58, 1.0, 0.0],
+];
+
+const SPRING: [[f32; 3]; 11] = [
+    [1.0, 0.0, 1.0],
+    [1.0, 0.1, 0.9],
+    [1.0, 0.2, 0.8],
+    [1.0, 0.3, 0.7],
+    [1.0, 0.4, 0.6],
+    [1.0, 0.5, 0.5],
+    [1.0, 0.6, 0.4],
+    [1.0, 0.7, 0.3],
+    [1.0, 0.8, 0.2],
+    [1.0, 0.9, 0.1],
+    [1.0, 1.0, 0.0],
+];
+
+const SUMMER: [[f32; 3]; 11] = [
+    [0.0, 0.5, 0.4],
+    [0.1, 0.55, 0.4],
+    [0.2, 0.6, 0.4],
+    [0.3, 0.65, 0.4],
+    [0.4, 0.7, 0.4],
+    [0.5, 0.75, 0.4],
+    [0.6, 0.8, 0.4],
+    [0.7, 0.85, 0.4],
+    [0.8, 0.9, 0.4],
+    [0.9, 0.95, 0.4],
+    [1.0, 1.0, 0.4],
+];
+
+const AUTUMN: [[f32; 3]; 11] = [
+    [1.0, 0.0, 0.0],
+    [1.0, 0.1, 0.0],
+    [1.0, 0.2, 0.0],
+    [1.0, 0.3, 0.0],
+    [1.0, 0.4, 0.0],
+    [1.0, 0.5, 0.0],
+    [1.0, 0.6, 0.0],
+    [1.0, 0.7, 0.0],
+    [1.0, 0.8, 0.0],
+    [1.0, 0.9, 0.0],
+    [1.0, 1.0, 0.0],
+];
+
+const WINTER: [[f32; 3]; 11] = [
+    [0.0, 0.0, 1.0],
+    [0.0, 0.1, 0.95],
+    [0.0, 0.2, 0.9],
+    [0.0, 0.3, 0.85],
+    [0.0, 0.4, 0.8],
+    [0.0, 0.5, 0.75],
+    [0.0, 0.6, 0.7],
+    [0.0, 0.7, 0.65],
+    [0.0, 0.8, 0.6],
+    [0.0, 0.9, 0.55],
+    [0.0, 1.0, 0.5],
+];
+
+const BONE: [[f32; 3]; 11] = [
+    [0.0, 0.0, 0.0],
+    [0.08, 0.08, 0.11],
+    [0.16, 0.16, 0.23],
+    [0.25, 0.25, 0.34],
+    [0.33, 0.33, 0.45],
+    [0.41, 0.44, 0.54],
+    [0.5, 0.56, 0.62],
+    [0.58, 0.67, 0.7],
+    [0.66, 0.78, 0.78],
+    [0.83, 0.89, 0.89],
+    [1.0, 1.0, 1.0],
+];
 
-    colors
+const COPPER: [[f32; 3]; 11] = [
+    [0.0, 0.0, 0.0],
+    [0.13, 0.08, 0.05],
+    [0.25, 0.16, 0.1],
+    [0.38, 0.24, 0.15],
+    [0.5, 0.31, 0.2],
+    [0.62, 0.39, 0.25],
+    [0.75, 0.47, 0.3],
+    [0.87, 0.55, 0.35],
+    [1.0, 0.63, 0.4],
+    [1.0, 0.71, 0.45],
+    [1.0, 0.78, 0.5],
+];
+
+const GREYS: [[f32; 3]; 11] = [
+    [0.0, 0.0, 0.0],
+    [0.1, 0.1, 0.1],
+    [0.2, 0.2, 0.2],
+    [0.3, 0.3, 0.3],
+    [0.4, 0.4, 0.4],
+    [0.5, 0.5, 0.5],
+    [0.6, 0.6, 0.6],
+    [0.7, 0.7, 0.7],
+    [0.8, 0.8, 0.8],
+    [0.9, 0.9, 0.9],
+    [1.0, 1.0, 1.0],
+];
+
+// perceptually uniform maps of matplotlib, sampled from its 256 color tables: viridis, plasma,
+// inferno and magma at ten even steps, cividis at nine
+
+const VIRIDIS: [[f32; 3]; 10] = [
+    [0.2667, 0.0039, 0.3294],
+    [0.2824, 0.1569, 0.4706],
+    [0.2431, 0.2902, 0.5373],
+    [0.1922, 0.4078, 0.5569],
+    [0.149, 0.5098, 0.5569],
+    [0.1216, 0.6196, 0.5373],
+    [0.2078, 0.7176, 0.4745],
+    [0.4275, 0.8039, 0.349],
+    [0.7059, 0.8706, 0.1725],
+    [0.9922, 0.9059, 0.1451],
+];
+
+const PLASMA: [[f32; 3]; 10] = [
+    [0.051, 0.0314, 0.5294],
+    [0.2784, 0.0118, 0.6235],
+    [0.451, 0.0039, 0.6588],
+    [0.6118, 0.0902, 0.6196],
+    [0.7412, 0.2157, 0.5255],
+    [0.8471, 0.3412, 0.4196],
+    [0.9294, 0.4745, 0.3255],
+    [0.9804, 0.6196, 0.2314],
+    [0.9922, 0.7882, 0.149],
+    [0.9412, 0.9765, 0.1294],
+];
+
+const INFERNO: [[f32; 3]; 10] = [
+    [0.0, 0.0, 0.0157],
+    [0.1059, 0.0471, 0.2588],
+    [0.2941, 0.0471, 0.4196],
+    [0.4706, 0.1098, 0.4275],
+    [0.6471, 0.1725, 0.3765],
+    [0.8118, 0.2667, 0.2745],
+    [0.9294, 0.4118, 0.1451],
+    [0.9843, 0.6039, 0.0235],
+    [0.9686, 0.8157, 0.2353],
+    [0.9882, 1.0, 0.6431],
+];
+
+const MAGMA: [[f32; 3]; 10] = [
+    [0.0, 0.0, 0.0157],
+    [0.0941, 0.0588, 0.2431],
+    [0.2706, 0.0627, 0.4667],
+    [0.4471, 0.1216, 0.5059],
+    [0.6235, 0.1843, 0.498],
+    [0.8039, 0.251, 0.4431],
+    [0.9451, 0.3765, 0.3647],
+    [0.9922, 0.5843, 0.4039],
+    [0.9961, 0.7882, 0.5529],
+    [0.9882, 0.9922, 0.749],
+];
+
+const CIVIDIS: [[f32; 3]; 9] = [
+    [0.0, 0.1351, 0.3048],
+    [0.0, 0.2124, 0.4432],
+    [0.2276, 0.3119, 0.4254],
+    [0.3712, 0.3967, 0.4371],
+    [0.4867, 0.4838, 0.4717],
+    [0.6084, 0.5757, 0.4734],
+    [0.7369, 0.6727, 0.4478],
+    [0.8695, 0.7786, 0.3854],
+    [0.9957, 0.9093, 0.2178],
+];
+
+// sampled from the polynomial approximation published with turbo
+const TURBO: [[f32; 3]; 33] = [
+    [0.1357, 0.0914, 0.1067],
+    [0.2422, 0.1643, 0.4459],
+    [0.2875, 0.2441, 0.6857],
+    [0.291, 0.3283, 0.8436],
+    [0.2686, 0.4148, 0.9348],
+    [0.2338, 0.5012, 0.9729],
+    [0.197, 0.5855, 0.9699],
+    [0.1666, 0.6659, 0.9362],
+    [0.1483, 0.7405, 0.8807],
+    [0.1462, 0.8078, 0.811],
+    [0.1624, 0.8664, 0.7332],
+    [0.1974, 0.915, 0.6525],
+    [0.2504, 0.9528, 0.5729],
+    [0.3193, 0.9788, 0.4974],
+    [0.4012, 0.9925, 0.4282],
+    [0.4924, 0.9936, 0.3665],
+    [0.5885, 0.9819, 0.3132],
+    [0.6851, 0.9575, 0.2681],
+    [0.7775, 0.9209, 0.231],
+    [0.8611, 0.8727, 0.2011],
+    [0.9316, 0.8139, 0.1771],
+    [0.9852, 0.7457, 0.1578],
+    [1.0, 0.6697, 0.1418],
+    [1.0, 0.5876, 0.1276],
+    [1.0, 0.5017, 0.114],
+    [0.9878, 0.4145, 0.0998],
+    [0.9349, 0.3288, 0.0842],
+    [0.8658, 0.2477, 0.0666],
+    [0.7864, 0.1749, 0.0473],
+    [0.7048, 0.1143, 0.0267],
+    [0.6315, 0.0701, 0.0062],
+    [0.5799, 0.0471, 0.0],
+    [0.5659, 0.0504, 0.0],
+];
+
+// diverging maps, neutral in the middle
+
+const COOLWARM: [[f32; 3]; 9] = [
+    [0.23, 0.299, 0.754],
+    [0.384, 0.51, 0.918],
+    [0.553, 0.69, 0.996],
+    [0.722, 0.816, 0.976],
+    [0.865, 0.865, 0.865],
+    [0.961, 0.769, 0.678],
+    [0.957, 0.604, 0.482],
+    [0.871, 0.376, 0.302],
+    [0.706, 0.016, 0.15],
+];
+
+const BWR: [[f32; 3]; 3] = [[0.0, 0.0, 1.0], [1.0, 1.0, 1.0], [1.0, 0.0, 0.0]];
+
+const RDBU: [[f32; 3]; 11] = [
+    [0.4039, 0.0, 0.1216],
+    [0.698, 0.0941, 0.1686],
+    [0.8392, 0.3765, 0.302],
+    [0.9569, 0.6471, 0.5098],
+    [0.9922, 0.8588, 0.7804],
+    [0.9686, 0.9686, 0.9686],
+    [0.8196, 0.898, 0.9412],
+    [0.5725, 0.7725, 0.8706],
+    [0.2627, 0.5765, 0.7647],
+    [0.1294, 0.4, 0.6745],
+    [0.0196, 0.1882, 0.3804],
+];
+
+const BRBG: [[f32; 3]; 11] = [
+    [0.3294, 0.1882, 0.0196],
+    [0.549, 0.3176, 0.0392],
+    [0.749, 0.5059, 0.1765],
+    [0.8745, 0.7608, 0.4902],
+    [0.9647, 0.9098, 0.7647],
+    [0.9608, 0.9608, 0.9608],
+    [0.7804, 0.9176, 0.898],
+    [0.502, 0.8039, 0.7569],
+    [0.2078, 0.5922, 0.5608],
+    [0.0039, 0.4, 0.3686],
+    [0.0, 0.2353, 0.1882],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [f32; 3] = [1.0, 0.0, 0.0];
+    const BLUE: [f32; 3] = [0.0, 0.0, 1.0];
+
+    #[test]
+    fn new_rescales_the_stops() {
+        let colormap = Colormap::new(vec![(2.0, RED), (3.0, [0.5; 3]), (6.0, BLUE)]).unwrap();
+        assert_eq!(
+            colormap.stops(),
+            &[(0.0, RED), (0.25, [0.5; 3]), (1.0, BLUE)]
+        );
+        assert_eq!(colormap.color(0.125), [0.75, 0.25, 0.25]);
+        assert_eq!(colormap.color(-1.0), RED);
+        assert_eq!(colormap.color(2.0), BLUE);
+    }
+
+    #[test]
+    fn new_rejects_bad_stops() {
+        let unsorted = Colormap::new(vec![(0.0, RED), (1.0, BLUE), (0.5, RED)]);
+        assert!(unsorted
+            .unwrap_err()
+            .to_string()
+            .contains("must not decrease"));
+        let bright = Colormap::new(vec![(0.0, RED), (1.0, [0.0, 0.0, 2.0])]);
+        assert!(bright.unwrap_err().to_string().contains("within [0, 1]"));
+        assert!(Colormap::new(vec![(0.0, RED), (f32::NAN, BLUE)]).is_err());
+        assert!(Colormap::new(vec![(0.5, RED), (0.5, BLUE)]).is_err());
+        assert!(Colormap::new(vec![(0.0, RED)]).is_err());
+    }
+
+    #[test]
+    fn reversed_round_trips() {
+        let colormap = Colormap::new(vec![(0.0, RED), (0.25, [0.5; 3]), (1.0, BLUE)]).unwrap();
+        let reversed = colormap.reversed();
+        assert_eq!(reversed.stops()[0], (0.0, BLUE));
+        assert_eq!(reversed.color(0.75), [0.5; 3]);
+        assert_eq!(reversed.reversed(), colormap);
+    }
+
+    /// Loads `text` from a colormap file named `name`.
+    fn load(name: &str, text: &str) -> anyhow::Result<Colormap> {
+        let path = std::env::temp_dir().join(format!("ice-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let colormap = Colormap::from_source(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        colormap
+    }
+
+    fn error(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ice-{}-{}", std::process::id(), name));
+        let message = format!("{:#}", load(name, text).unwrap_err());
+        message.replace(&path.display().to_string(), "FILE")
+    }
+
+    #[test]
+    fn csv_with_header() {
+        let colormap = load(
+            "header.csv",
+            "red, green, blue\n1, 0, 0\n0.5, 0.5, 0.5 # grey\n0, 0, 1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            colormap.stops(),
+            &[(0.0, RED), (0.5, [0.5; 3]), (1.0, BLUE)]
+        );
+    }
+
+    #[test]
+    fn csv_with_bytes() {
+        let colormap = load("bytes.csv", "255,0,0\n0,51,255\n").unwrap();
+        let [r, g, b] = colormap.stops()[1].1;
+        assert_eq!(colormap.stops()[0].1, RED);
+        assert!(r == 0.0 && (g - 0.2).abs() < 1e-6 && b == 1.0);
+    }
+
+    #[test]
+    fn csv_with_positions() {
+        let colormap = load("positions.csv", "10,1,0,0\n15,0.5,0.5,0.5\n50,0,0,1\n").unwrap();
+        assert_eq!(
+            colormap.stops(),
+            &[(0.0, RED), (0.125, [0.5; 3]), (1.0, BLUE)]
+        );
+        assert_eq!(colormap.color(0.0625), [0.75, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn json_stops() {
+        let colormap = load("stops.json", "[[1, 0, 0], [0, 0, 1]]").unwrap();
+        assert_eq!(colormap.stops(), &[(0.0, RED), (1.0, BLUE)]);
+        let colormap = load(
+            "positions.json",
+            "[[0, 255, 0, 0], [0.75, 0, 0, 255], [1, 0, 0, 255]]",
+        )
+        .unwrap();
+        assert_eq!(colormap.stops(), &[(0.0, RED), (0.75, BLUE), (1.0, BLUE)]);
+    }
+
+    #[test]
+    fn malformed_files() {
+        assert_eq!(
+            error("short.csv", "1,0,0\n0,1\n"),
+            "invalid colormap FILE: every stop must be [r, g, b] or [position, r, g, b]"
+        );
+        assert_eq!(
+            error("word.csv", "1,0,0\nblue,0,1\n"),
+            "in colormap FILE: line 2: invalid float literal"
+        );
+        assert_eq!(
+            error("one.csv", "r,g,b\n1,0,0\n"),
+            "invalid colormap FILE: a colormap needs at least two stops, got 1"
+        );
+        assert_eq!(
+            error("unsorted.csv", "0,1,0,0\n1,0,0,1\n0.5,1,0,0\n"),
+            "invalid colormap FILE: colormap stop positions must not decrease"
+        );
+        assert!(error("broken.json", "[[1, 0, 0], [0, 0")
+            .starts_with("failed to parse colormap FILE: "));
+        let message = error("map.txt", "1,0,0\n0,0,1\n");
+        assert!(
+            message.starts_with("unknown colormap 'FILE', expected one of: jet"),
+            "{}",
+            message
+        );
+        assert!(message.ends_with("or a .csv or .json file"));
+        let message = format!("{:#}", Colormap::from_source("missing.csv").unwrap_err());
+        assert!(
+            message.starts_with("failed to read colormap missing.csv: "),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn every_name_is_builtin() {
+        for name in COLORMAP_NAMES {
+            assert!(Colormap::builtin(name).is_some(), "{}", name);
+            assert!(!is_colormap_path(name));
+        }
+        assert!(is_colormap_path("map.csv"));
+        assert!(is_colormap_path("maps/diverging"));
+        let error = Colormap::from_source("viridjs").unwrap_err().to_string();
+        assert!(error.starts_with("unknown colormap 'viridjs', expected one of: jet, hsv"));
+    }
 }
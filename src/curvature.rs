@@ -0,0 +1,169 @@
+use cgmath::{InnerSpace, Vector3};
+
+/// Curvature used to color a surface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curvature {
+    /// Product of the principal curvatures: positive on caps, negative on saddles.
+    Gaussian,
+    /// Average of the principal curvatures.
+    Mean,
+    /// Larger principal curvature.
+    Max,
+    /// Smaller principal curvature.
+    Min,
+}
+
+/// Curvatures at one point of a surface, signed with respect to its normal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curvatures {
+    pub gaussian: f64,
+    pub mean: f64,
+    /// Principal curvatures, `k1 >= k2`.
+    pub k1: f64,
+    pub k2: f64,
+}
+
+impl Curvatures {
+    /// Computes the curvatures from the first partial derivatives `fu`, `fv`, the second partial
+    /// derivatives `fuu`, `fuv`, `fvv` and the unit normal `n`. `None` where the first fundamental
+    /// form is singular, e.g. at the poles of a sphere.
+    pub fn from_derivatives(
+        [fu, fv]: [Vector3<f64>; 2],
+        [fuu, fuv, fvv]: [Vector3<f64>; 3],
+        n: Vector3<f64>,
+    ) -> Option<Self> {
+        // first fundamental form
+        let (e, f, g) = (fu.dot(fu), fu.dot(fv), fv.dot(fv));
+        // second fundamental form
+        let (l, m, nn) = (fuu.dot(n), fuv.dot(n), fvv.dot(n));
+        let det = e * g - f * f;
+        if det <= 1e-12 * e * g {
+            return None;
+        }
+        let gaussian = (l * nn - m * m) / det;
+        let mean = (e * nn - 2.0 * f * m + g * l) / (2.0 * det);
+        // rounding can push the discriminant slightly below zero at umbilic points
+        let d = (mean * mean - gaussian).max(0.0).sqrt();
+        let curvatures = Curvatures {
+            gaussian,
+            mean,
+            k1: mean + d,
+            k2: mean - d,
+        };
+        [gaussian, mean, d]
+            .iter()
+            .all(|c| c.is_finite())
+            .then_some(curvatures)
+    }
+
+    pub fn get(&self, curvature: Curvature) -> f64 {
+        match curvature {
+            Curvature::Gaussian => self.gaussian,
+            Curvature::Mean => self.mean,
+            Curvature::Max => self.k1,
+            Curvature::Min => self.k2,
+        }
+    }
+}
+
+/// Range covering all but the most extreme 2% of the finite values on either side, so a few
+/// points of huge curvature, as near singular points, do not wash out the colors of the rest.
+/// Values outside the range are clamped by the colormap.
+pub fn robust_range(values: &[f32]) -> (f32, f32) {
+    let mut sorted: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if sorted.is_empty() {
+        return (0.0, 1.0);
+    }
+    sorted.sort_by(f32::total_cmp);
+    let last = sorted.len() - 1;
+    let min = sorted[last * 2 / 100];
+    let max = sorted[last - last * 2 / 100];
+    if max > min {
+        (min, max)
+    } else {
+        // constant curvature, e.g. on a sphere
+        (min - 0.5, max + 0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{math_func, surface_data::ParametricSurface};
+    use std::{f32::consts::PI, sync::Arc};
+
+    fn assert_close(a: f64, b: f64, tolerance: f64) {
+        assert!((a - b).abs() <= tolerance, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn sphere_from_derivatives() {
+        let (r, u, v) = (2.0f64, 0.8f64, 1.3f64);
+        let p = Vector3::new(u.sin() * v.cos(), u.cos(), -u.sin() * v.sin()) * r;
+        let fu = Vector3::new(u.cos() * v.cos(), -u.sin(), -u.cos() * v.sin()) * r;
+        let fv = Vector3::new(-u.sin() * v.sin(), 0.0, -u.sin() * v.cos()) * r;
+        let fuv = Vector3::new(-u.cos() * v.sin(), 0.0, -u.cos() * v.cos()) * r;
+        let fvv = Vector3::new(-u.sin() * v.cos(), 0.0, u.sin() * v.sin()) * r;
+        let outward = p / r;
+        let c = Curvatures::from_derivatives([fu, fv], [-p, fuv, fvv], outward).unwrap();
+        assert_close(c.gaussian, 1.0 / (r * r), 1e-12);
+        assert_close(c.mean, -1.0 / r, 1e-12);
+        assert_close(c.k1, c.k2, 1e-6);
+
+        // the mean curvature changes sign with the normal, the gaussian does not
+        let inward = Curvatures::from_derivatives([fu, fv], [-p, fuv, fvv], -outward).unwrap();
+        assert_close(inward.gaussian, 1.0 / (r * r), 1e-12);
+        assert_close(inward.mean, 1.0 / r, 1e-12);
+
+        // parallel derivatives leave the first fundamental form singular
+        assert!(Curvatures::from_derivatives([fu, fu * 2.0], [-p, fuv, fvv], outward).is_none());
+    }
+
+    #[test]
+    fn sphere_surface() {
+        let r = 2.0;
+        let ps = ParametricSurface {
+            f: Arc::new(math_func::sphere),
+            derivatives: Some(Arc::new(math_func::sphere_derivatives)),
+            umax: PI,
+            scale: 1.0,
+            params: [r, 0.0, 0.0, 0.0, 0.0],
+            ..Default::default()
+        };
+        // the poles fall back to points a little inside the domain
+        for (u, v) in [(0.5, 0.0), (1.5, 4.0), (0.0, 1.0), (PI, 2.0)] {
+            let c = ps.curvatures(u, v).unwrap();
+            assert_close(c.gaussian, 1.0 / (r * r) as f64, 1e-4);
+            assert_close(c.mean.abs(), 1.0 / r as f64, 1e-4);
+        }
+    }
+
+    #[test]
+    fn torus_surface_by_finite_differences() {
+        let (big_r, r) = (1.0f64, 0.3f64);
+        let ps = ParametricSurface {
+            derivatives: None,
+            scale: 1.0,
+            params: [big_r as f32, r as f32, 0.0, 0.0, 0.0],
+            ..Default::default()
+        };
+        for v in [0.0f32, 1.0, 2.5, PI] {
+            let c = ps.curvatures(0.7, v).unwrap();
+            let cos = (v as f64).cos();
+            let gaussian = cos / (r * (big_r + r * cos));
+            let mean = (big_r + 2.0 * r * cos) / (2.0 * r * (big_r + r * cos));
+            assert_close(c.gaussian, gaussian, 1e-2 * gaussian.abs().max(1.0));
+            assert_close(c.mean.abs(), mean, 1e-2 * mean);
+        }
+    }
+
+    #[test]
+    fn robust_range_ignores_outliers() {
+        let mut values: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        values.extend([1e9, f32::NAN, f32::INFINITY]);
+        let (min, max) = robust_range(&values);
+        assert!(min >= 0.0 && max < 100.0, "{} {}", min, max);
+        assert_eq!(robust_range(&[0.25; 10]), (-0.25, 0.75));
+        assert_eq!(robust_range(&[f32::NAN]), (0.0, 1.0));
+    }
+}
@@ -7,8 +7,10 @@ pub async fn get_device(adapter: Arc<Adapter>) -> (Arc<Device>, Arc<Queue>) {
         .request_device(
             &DeviceDescriptor {
                 label: Some("Logical Device"),
-                features: Features::empty(),
-                limits: Limits::default(),
+                // line rasterization is optional, the wireframe falls back to a shader without it
+                features: adapter.features() & Features::POLYGON_MODE_LINE,
+                // software and GL adapters only guarantee the downlevel limits
+                limits: Limits::downlevel_defaults().using_resolution(adapter.limits()),
             },
             None,
         )
@@ -16,3 +18,38 @@ pub async fn get_device(adapter: Arc<Adapter>) -> (Arc<Device>, Arc<Queue>) {
         .expect("Failed to create device!");
     (Arc::new(device), Arc::new(queue))
 }
+
+/// Sample counts the adapter can render `format` with. wgpu has no finer query and
+/// its render passes only accept one or four samples, which it guarantees for
+/// every format usable as render attachment.
+pub fn supported_sample_counts(adapter: &Adapter, format: TextureFormat) -> Vec<u32> {
+    let features = adapter.get_texture_format_features(format);
+    if features
+        .allowed_usages
+        .contains(TextureUsages::RENDER_ATTACHMENT)
+    {
+        vec![1, 4]
+    } else {
+        vec![1]
+    }
+}
+
+/// Fails if the adapter cannot render `format` with `sample_count` samples.
+pub fn check_sample_count(
+    adapter: &Adapter,
+    format: TextureFormat,
+    sample_count: u32,
+) -> anyhow::Result<u32> {
+    let supported = supported_sample_counts(adapter, format);
+    if supported.contains(&sample_count) {
+        return Ok(sample_count);
+    }
+    let supported: Vec<_> = supported.iter().map(u32::to_string).collect();
+    anyhow::bail!(
+        "{} samples are not supported by adapter '{}' for {:?}, supported: {}",
+        sample_count,
+        adapter.get_info().name,
+        format,
+        supported.join(", ")
+    )
+}
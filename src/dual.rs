@@ -0,0 +1,290 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Value of a function of `(u, v)` together with its partial derivatives,
+/// for forward-mode automatic differentiation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual {
+    pub value: f64,
+    pub du: f64,
+    pub dv: f64,
+}
+
+impl Dual {
+    pub fn constant(value: f64) -> Self {
+        Dual {
+            value,
+            du: 0.0,
+            dv: 0.0,
+        }
+    }
+
+    /// The parameter `u` itself, whose derivative along u is one.
+    pub fn u(value: f64) -> Self {
+        Dual {
+            value,
+            du: 1.0,
+            dv: 0.0,
+        }
+    }
+
+    /// The parameter `v` itself, whose derivative along v is one.
+    pub fn v(value: f64) -> Self {
+        Dual {
+            value,
+            du: 0.0,
+            dv: 1.0,
+        }
+    }
+
+    /// Applies a function with value `value` and derivative `derivative` at `self.value`
+    /// (chain rule).
+    fn chain(self, value: f64, derivative: f64) -> Self {
+        Dual {
+            value,
+            du: derivative * self.du,
+            dv: derivative * self.dv,
+        }
+    }
+
+    pub fn sin(self) -> Self {
+        self.chain(self.value.sin(), self.value.cos())
+    }
+
+    pub fn cos(self) -> Self {
+        self.chain(self.value.cos(), -self.value.sin())
+    }
+
+    pub fn tan(self) -> Self {
+        let t = self.value.tan();
+        self.chain(t, 1.0 + t * t)
+    }
+
+    pub fn asin(self) -> Self {
+        self.chain(
+            self.value.asin(),
+            1.0 / (1.0 - self.value * self.value).sqrt(),
+        )
+    }
+
+    pub fn acos(self) -> Self {
+        self.chain(
+            self.value.acos(),
+            -1.0 / (1.0 - self.value * self.value).sqrt(),
+        )
+    }
+
+    pub fn atan(self) -> Self {
+        self.chain(self.value.atan(), 1.0 / (1.0 + self.value * self.value))
+    }
+
+    pub fn sinh(self) -> Self {
+        self.chain(self.value.sinh(), self.value.cosh())
+    }
+
+    pub fn cosh(self) -> Self {
+        self.chain(self.value.cosh(), self.value.sinh())
+    }
+
+    pub fn tanh(self) -> Self {
+        let t = self.value.tanh();
+        self.chain(t, 1.0 - t * t)
+    }
+
+    pub fn exp(self) -> Self {
+        let e = self.value.exp();
+        self.chain(e, e)
+    }
+
+    pub fn ln(self) -> Self {
+        self.chain(self.value.ln(), 1.0 / self.value)
+    }
+
+    pub fn log10(self) -> Self {
+        self.chain(
+            self.value.log10(),
+            1.0 / (self.value * std::f64::consts::LN_10),
+        )
+    }
+
+    pub fn sqrt(self) -> Self {
+        let s = self.value.sqrt();
+        self.chain(s, 0.5 / s)
+    }
+
+    pub fn abs(self) -> Self {
+        self.chain(self.value.abs(), sign(self.value))
+    }
+
+    pub fn atan2(self, x: Dual) -> Self {
+        let r2 = self.value * self.value + x.value * x.value;
+        Dual {
+            value: self.value.atan2(x.value),
+            du: (x.value * self.du - self.value * x.du) / r2,
+            dv: (x.value * self.dv - self.value * x.dv) / r2,
+        }
+    }
+
+    pub fn powf(self, exponent: Dual) -> Self {
+        let value = self.value.powf(exponent.value);
+        // d(a^b) = b a^(b-1) da + a^b ln(a) db; the second term vanishes for constant exponents,
+        // which keeps negative bases with integer exponents finite
+        let da = exponent.value * self.value.powf(exponent.value - 1.0);
+        let db = if exponent.du == 0.0 && exponent.dv == 0.0 {
+            0.0
+        } else {
+            value * self.value.ln()
+        };
+        Dual {
+            value,
+            du: da * self.du + db * exponent.du,
+            dv: da * self.dv + db * exponent.dv,
+        }
+    }
+
+    pub fn min(self, other: Dual) -> Self {
+        if other.value < self.value {
+            other
+        } else {
+            self
+        }
+    }
+
+    pub fn max(self, other: Dual) -> Self {
+        if other.value > self.value {
+            other
+        } else {
+            self
+        }
+    }
+}
+
+/// Sign with `sign(0) = 0`, matching the `sign` expression function.
+pub fn sign(a: f64) -> f64 {
+    if a == 0.0 {
+        0.0
+    } else {
+        a.signum()
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+    fn add(self, b: Dual) -> Dual {
+        Dual {
+            value: self.value + b.value,
+            du: self.du + b.du,
+            dv: self.dv + b.dv,
+        }
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+    fn sub(self, b: Dual) -> Dual {
+        Dual {
+            value: self.value - b.value,
+            du: self.du - b.du,
+            dv: self.dv - b.dv,
+        }
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+    fn mul(self, b: Dual) -> Dual {
+        Dual {
+            value: self.value * b.value,
+            du: self.du * b.value + self.value * b.du,
+            dv: self.dv * b.value + self.value * b.dv,
+        }
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+    fn div(self, b: Dual) -> Dual {
+        let b2 = b.value * b.value;
+        Dual {
+            value: self.value / b.value,
+            du: (self.du * b.value - self.value * b.du) / b2,
+            dv: (self.dv * b.value - self.value * b.dv) / b2,
+        }
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+    fn neg(self) -> Dual {
+        Dual {
+            value: -self.value,
+            du: -self.du,
+            dv: -self.dv,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() <= 1e-12 * b.abs().max(1.0), "{} != {}", a, b);
+    }
+
+    #[test]
+    fn elementary_functions() {
+        let x = 0.7;
+        let s = Dual::u(x).sin();
+        assert_close(s.value, x.sin());
+        assert_close(s.du, x.cos());
+        assert_eq!(s.dv, 0.0);
+
+        let e = Dual::v(x).exp();
+        assert_close(e.dv, x.exp());
+        assert_eq!(e.du, 0.0);
+
+        let p = Dual::u(x).powf(Dual::constant(3.0));
+        assert_close(p.value, x.powi(3));
+        assert_close(p.du, 3.0 * x * x);
+        // negative bases stay finite with constant exponents
+        let q = Dual::u(-2.0).powf(Dual::constant(2.0));
+        assert_close(q.du, -4.0);
+
+        // d(x^x)/dx = x^x (ln x + 1)
+        let xx = Dual::u(x).powf(Dual::u(x));
+        assert_close(xx.du, x.powf(x) * (x.ln() + 1.0));
+
+        assert_close(Dual::u(x).ln().du, 1.0 / x);
+        assert_close(Dual::u(x).sqrt().du, 0.5 / x.sqrt());
+        assert_close(Dual::u(x).tan().du, 1.0 / (x.cos() * x.cos()));
+        assert_close(
+            Dual::u(x).atan2(Dual::constant(1.0)).du,
+            1.0 / (1.0 + x * x),
+        );
+    }
+
+    #[test]
+    fn arithmetic_follows_the_product_and_quotient_rules() {
+        let (u, v) = (Dual::u(1.5), Dual::v(-0.5));
+        // f = u^2 v / (u + v), checked against the derivatives worked out by hand
+        let f = u * u * v / (u + v);
+        let (a, b) = (1.5f64, -0.5f64);
+        assert_close(f.value, a * a * b / (a + b));
+        assert_close(
+            f.du,
+            (2.0 * a * b * (a + b) - a * a * b) / ((a + b) * (a + b)),
+        );
+        assert_close(f.dv, (a * a * (a + b) - a * a * b) / ((a + b) * (a + b)));
+        let g = -(u - v);
+        assert_eq!((g.du, g.dv), (-1.0, 1.0));
+    }
+
+    #[test]
+    fn sin_of_product_of_parameters() {
+        // d/du sin(u * v) = v cos(u v), d/dv = u cos(u v)
+        let (a, b) = (0.3f64, 2.0f64);
+        let f = (Dual::u(a) * Dual::v(b)).sin();
+        assert_close(f.du, b * (a * b).cos());
+        assert_close(f.dv, a * (a * b).cos());
+    }
+}
@@ -0,0 +1,706 @@
+use crate::{
+    dual::{sign, Dual},
+    scalar::{ScalarFn, ScalarPoint},
+    surface_data::{DerivativeFn, SurfaceFn},
+};
+use anyhow::Context;
+use std::{fmt, sync::Arc};
+
+/// Error produced while parsing an expression. `column` is 1-based and counts characters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Math functions available inside expressions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Ln,
+    Log10,
+    Sqrt,
+    Abs,
+    Floor,
+    Ceil,
+    Sign,
+    Atan2,
+    Pow,
+    Min,
+    Max,
+}
+
+impl Function {
+    const ALL: [(&'static str, Function); 22] = [
+        ("sin", Function::Sin),
+        ("cos", Function::Cos),
+        ("tan", Function::Tan),
+        ("asin", Function::Asin),
+        ("acos", Function::Acos),
+        ("atan", Function::Atan),
+        ("sinh", Function::Sinh),
+        ("cosh", Function::Cosh),
+        ("tanh", Function::Tanh),
+        ("exp", Function::Exp),
+        ("ln", Function::Ln),
+        ("log", Function::Ln),
+        ("log10", Function::Log10),
+        ("sqrt", Function::Sqrt),
+        ("abs", Function::Abs),
+        ("floor", Function::Floor),
+        ("ceil", Function::Ceil),
+        ("sign", Function::Sign),
+        ("atan2", Function::Atan2),
+        ("pow", Function::Pow),
+        ("min", Function::Min),
+        ("max", Function::Max),
+    ];
+
+    pub fn from_name(name: &str) -> Option<Function> {
+        Self::ALL.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Function::Atan2 | Function::Pow | Function::Min | Function::Max => 2,
+            _ => 1,
+        }
+    }
+
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Function::Sin => a.sin(),
+            Function::Cos => a.cos(),
+            Function::Tan => a.tan(),
+            Function::Asin => a.asin(),
+            Function::Acos => a.acos(),
+            Function::Atan => a.atan(),
+            Function::Sinh => a.sinh(),
+            Function::Cosh => a.cosh(),
+            Function::Tanh => a.tanh(),
+            Function::Exp => a.exp(),
+            Function::Ln => a.ln(),
+            Function::Log10 => a.log10(),
+            Function::Sqrt => a.sqrt(),
+            Function::Abs => a.abs(),
+            Function::Floor => a.floor(),
+            Function::Ceil => a.ceil(),
+            Function::Sign => sign(a),
+            Function::Atan2 => a.atan2(b),
+            Function::Pow => a.powf(b),
+            Function::Min => a.min(b),
+            Function::Max => a.max(b),
+        }
+    }
+
+    fn apply_dual(self, a: Dual, b: Dual) -> Dual {
+        match self {
+            Function::Sin => a.sin(),
+            Function::Cos => a.cos(),
+            Function::Tan => a.tan(),
+            Function::Asin => a.asin(),
+            Function::Acos => a.acos(),
+            Function::Atan => a.atan(),
+            Function::Sinh => a.sinh(),
+            Function::Cosh => a.cosh(),
+            Function::Tanh => a.tanh(),
+            Function::Exp => a.exp(),
+            Function::Ln => a.ln(),
+            Function::Log10 => a.log10(),
+            Function::Sqrt => a.sqrt(),
+            Function::Abs => a.abs(),
+            // piecewise constant, the derivative is zero almost everywhere
+            Function::Floor | Function::Ceil | Function::Sign => {
+                Dual::constant(self.apply(a.value, 0.0))
+            }
+            Function::Atan2 => a.atan2(b),
+            Function::Pow => a.powf(b),
+            Function::Min => a.min(b),
+            Function::Max => a.max(b),
+        }
+    }
+}
+
+/// Instructions of a compiled expression, evaluated on a small stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Const(f64),
+    U,
+    V,
+    Param(usize),
+    Neg,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Call(Function),
+}
+
+/// A compiled expression of `u`, `v` and named parameters.
+#[derive(Debug, Clone)]
+pub struct Expression {
+    ops: Vec<Op>,
+    stack_size: usize,
+}
+
+impl Expression {
+    /// Parses `src`. `params` names the parameters in the order of the surface `params` array.
+    ///
+    /// Supports numbers, `u`, `v`, `pi`, `e`, the parameter names, `+ - * / ^`,
+    /// parentheses and the functions listed in `Function`.
+    pub fn parse(src: &str, params: &[&str]) -> Result<Self, ParseError> {
+        let tokens = tokenize(src)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            params,
+            ops: Vec::new(),
+        };
+        parser.expr()?;
+        let (token, column) = parser.peek();
+        if *token != Token::End {
+            return Err(ParseError {
+                column,
+                message: format!("unexpected {}", token),
+            });
+        }
+
+        // track the deepest stack the program needs so evaluation never reallocates
+        let mut depth: usize = 0;
+        let mut stack_size = 0;
+        for op in &parser.ops {
+            match op {
+                Op::Const(_) | Op::U | Op::V | Op::Param(_) => depth += 1,
+                Op::Neg => {}
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Pow => depth -= 1,
+                Op::Call(f) => depth = depth + 1 - f.arity(),
+            }
+            stack_size = stack_size.max(depth);
+        }
+        Ok(Expression {
+            ops: parser.ops,
+            stack_size,
+        })
+    }
+
+    pub fn eval(&self, u: f64, v: f64, params: &[f32]) -> f64 {
+        let mut stack: Vec<f64> = Vec::with_capacity(self.stack_size);
+        for op in &self.ops {
+            match *op {
+                Op::Const(c) => stack.push(c),
+                Op::U => stack.push(u),
+                Op::V => stack.push(v),
+                Op::Param(i) => stack.push(params[i] as f64),
+                Op::Neg => {
+                    let a = stack.pop().unwrap();
+                    stack.push(-a);
+                }
+                Op::Call(f) if f.arity() == 1 => {
+                    let a = stack.pop().unwrap();
+                    stack.push(f.apply(a, 0.0));
+                }
+                _ => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    stack.push(match *op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        Op::Pow => a.powf(b),
+                        Op::Call(f) => f.apply(a, b),
+                        _ => unreachable!(),
+                    });
+                }
+            }
+        }
+        stack.pop().unwrap_or(f64::NAN)
+    }
+
+    /// Evaluates the expression together with its exact partial derivatives along u and v.
+    pub fn eval_dual(&self, u: f64, v: f64, params: &[f32]) -> Dual {
+        let mut stack: Vec<Dual> = Vec::with_capacity(self.stack_size);
+        for op in &self.ops {
+            match *op {
+                Op::Const(c) => stack.push(Dual::constant(c)),
+                Op::U => stack.push(Dual::u(u)),
+                Op::V => stack.push(Dual::v(v)),
+                Op::Param(i) => stack.push(Dual::constant(params[i] as f64)),
+                Op::Neg => {
+                    let a = stack.pop().unwrap();
+                    stack.push(-a);
+                }
+                Op::Call(f) if f.arity() == 1 => {
+                    let a = stack.pop().unwrap();
+                    stack.push(f.apply_dual(a, Dual::constant(0.0)));
+                }
+                _ => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    stack.push(match *op {
+                        Op::Add => a + b,
+                        Op::Sub => a - b,
+                        Op::Mul => a * b,
+                        Op::Div => a / b,
+                        Op::Pow => a.powf(b),
+                        Op::Call(f) => f.apply_dual(a, b),
+                        _ => unreachable!(),
+                    });
+                }
+            }
+        }
+        stack.pop().unwrap_or(Dual::constant(f64::NAN))
+    }
+}
+
+/// Compiles the three coordinate expressions into a function usable as `ParametricSurface::f`.
+/// At most five parameter names are allowed, matching the surface `params` array.
+pub fn compile_surface(
+    x: &str,
+    y: &str,
+    z: &str,
+    param_names: &[String],
+) -> anyhow::Result<SurfaceFn> {
+    let [ex, ey, ez] = parse_surface(x, y, z, param_names)?;
+    Ok(Arc::new(move |u: f32, v: f32, params: [f32; 5]| {
+        let (u, v) = (u as f64, v as f64);
+        [
+            ex.eval(u, v, &params) as f32,
+            ey.eval(u, v, &params) as f32,
+            ez.eval(u, v, &params) as f32,
+        ]
+    }))
+}
+
+/// Compiles the partial derivatives of the surface given by the three coordinate expressions,
+/// differentiated automatically with dual numbers.
+pub fn compile_surface_derivatives(
+    x: &str,
+    y: &str,
+    z: &str,
+    param_names: &[String],
+) -> anyhow::Result<DerivativeFn> {
+    let [ex, ey, ez] = parse_surface(x, y, z, param_names)?;
+    Ok(Arc::new(move |u: f64, v: f64, params: [f32; 5]| {
+        let [x, y, z] = [&ex, &ey, &ez].map(|e| e.eval_dual(u, v, &params));
+        [[x.du, y.du, z.du], [x.dv, y.dv, z.dv]]
+    }))
+}
+
+/// Compiles a scalar expression of `u`, `v`, the position `x`, `y`, `z` and the normal
+/// `nx`, `ny`, `nz`, usable as `ScalarSource::Function`.
+pub fn compile_scalar(src: &str) -> anyhow::Result<ScalarFn> {
+    // the position and normal are passed the way surface parameters are
+    let expression = Expression::parse(src, &["x", "y", "z", "nx", "ny", "nz"])?;
+    Ok(Arc::new(move |point: &ScalarPoint| {
+        let [x, y, z] = point.position;
+        let [nx, ny, nz] = point.normal;
+        expression.eval(point.u as f64, point.v as f64, &[x, y, z, nx, ny, nz]) as f32
+    }))
+}
+
+fn parse_surface(
+    x: &str,
+    y: &str,
+    z: &str,
+    param_names: &[String],
+) -> anyhow::Result<[Expression; 3]> {
+    if param_names.len() > 5 {
+        anyhow::bail!(
+            "at most 5 parameter names are supported, got {}",
+            param_names.len()
+        );
+    }
+    for (i, name) in param_names.iter().enumerate() {
+        let mut chars = name.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            anyhow::bail!("parameter name '{}' is not a valid identifier", name);
+        }
+        if is_reserved(name) {
+            anyhow::bail!("parameter name '{}' is reserved", name);
+        }
+        if param_names[..i].contains(name) {
+            anyhow::bail!("parameter name '{}' is given twice", name);
+        }
+    }
+
+    let names: Vec<&str> = param_names.iter().map(|n| n.as_str()).collect();
+    let ex = Expression::parse(x, &names).context("x(u, v)")?;
+    let ey = Expression::parse(y, &names).context("y(u, v)")?;
+    let ez = Expression::parse(z, &names).context("z(u, v)")?;
+    Ok([ex, ey, ez])
+}
+
+fn is_reserved(name: &str) -> bool {
+    matches!(name, "u" | "v" | "pi" | "e") || Function::from_name(name).is_some()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Ident(name) => write!(f, "'{}'", name),
+            Token::Op(c) => write!(f, "'{}'", c),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::End => write!(f, "end of expression"),
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // exponent, e.g. 1.5e-3
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let value = text.parse::<f64>().map_err(|_| ParseError {
+                column,
+                message: format!("invalid number '{}'", text),
+            })?;
+            tokens.push((Token::Number(value), column));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), column));
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => {
+                    return Err(ParseError {
+                        column,
+                        message: format!("unexpected character '{}'", c),
+                    })
+                }
+            };
+            tokens.push((token, column));
+            i += 1;
+        }
+    }
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    params: &'a [&'a str],
+    ops: Vec<Op>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> (&Token, usize) {
+        let (token, column) = &self.tokens[self.pos];
+        (token, *column)
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.pos].clone();
+        if token.0 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        let (token, column) = self.next();
+        if token == expected {
+            Ok(())
+        } else {
+            Err(ParseError {
+                column,
+                message: format!("expected {}, found {}", expected, token),
+            })
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<(), ParseError> {
+        self.term()?;
+        loop {
+            let op = match self.peek().0 {
+                Token::Op('+') => Op::Add,
+                Token::Op('-') => Op::Sub,
+                _ => return Ok(()),
+            };
+            self.next();
+            self.term()?;
+            self.ops.push(op);
+        }
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<(), ParseError> {
+        self.unary()?;
+        loop {
+            let op = match self.peek().0 {
+                Token::Op('*') => Op::Mul,
+                Token::Op('/') => Op::Div,
+                _ => return Ok(()),
+            };
+            self.next();
+            self.unary()?;
+            self.ops.push(op);
+        }
+    }
+
+    // unary := ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<(), ParseError> {
+        match self.peek().0 {
+            Token::Op('-') => {
+                self.next();
+                self.unary()?;
+                self.ops.push(Op::Neg);
+                Ok(())
+            }
+            Token::Op('+') => {
+                self.next();
+                self.unary()
+            }
+            _ => self.power(),
+        }
+    }
+
+    // power := primary ('^' unary)?, so -u^2 is -(u^2) and u^-1 works
+    fn power(&mut self) -> Result<(), ParseError> {
+        self.primary()?;
+        if *self.peek().0 == Token::Op('^') {
+            self.next();
+            self.unary()?;
+            self.ops.push(Op::Pow);
+        }
+        Ok(())
+    }
+
+    fn primary(&mut self) -> Result<(), ParseError> {
+        let (token, column) = self.next();
+        match token {
+            Token::Number(n) => self.ops.push(Op::Const(n)),
+            Token::LParen => {
+                self.expr()?;
+                self.expect(Token::RParen)?;
+            }
+            Token::Ident(name) => {
+                if *self.peek().0 == Token::LParen {
+                    return self.call(&name, column);
+                }
+                let op = match name.as_str() {
+                    "u" => Op::U,
+                    "v" => Op::V,
+                    "pi" => Op::Const(std::f64::consts::PI),
+                    "e" => Op::Const(std::f64::consts::E),
+                    _ => match self.params.iter().position(|p| *p == name) {
+                        Some(i) => Op::Param(i),
+                        None if Function::from_name(&name).is_some() => {
+                            return Err(ParseError {
+                                column,
+                                message: format!("function '{}' needs arguments", name),
+                            })
+                        }
+                        None => {
+                            return Err(ParseError {
+                                column,
+                                message: format!("unknown variable '{}'", name),
+                            })
+                        }
+                    },
+                };
+                self.ops.push(op);
+            }
+            _ => {
+                return Err(ParseError {
+                    column,
+                    message: format!("expected a value, found {}", token),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &str, column: usize) -> Result<(), ParseError> {
+        let function = Function::from_name(name).ok_or_else(|| ParseError {
+            column,
+            message: format!("unknown function '{}'", name),
+        })?;
+        self.expect(Token::LParen)?;
+        let mut count = 0;
+        if *self.peek().0 != Token::RParen {
+            loop {
+                self.expr()?;
+                count += 1;
+                if *self.peek().0 != Token::Comma {
+                    break;
+                }
+                self.next();
+            }
+        }
+        self.expect(Token::RParen)?;
+        if count != function.arity() {
+            return Err(ParseError {
+                column,
+                message: format!(
+                    "'{}' takes {} argument{}, got {}",
+                    name,
+                    function.arity(),
+                    if function.arity() == 1 { "" } else { "s" },
+                    count
+                ),
+            });
+        }
+        self.ops.push(Op::Call(function));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> f64 {
+        Expression::parse(src, &[]).unwrap().eval(0.0, 0.0, &[])
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("8 / 4 / 2"), 1.0);
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("2^3^2"), 512.0);
+        assert_eq!(eval("2^-1"), 0.5);
+        assert_eq!(eval("2 * -3"), -6.0);
+        assert_eq!(eval("1.5e-3 * 2E3"), 3.0);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let e = Expression::parse("a * cos(u) + b * v", &["a", "b"]).unwrap();
+        assert_eq!(e.eval(0.0, 2.0, &[3.0, 0.5]), 4.0);
+        assert_eq!(eval("pi"), std::f64::consts::PI);
+        assert_eq!(eval("ln(e)"), 1.0);
+        assert_eq!(eval("max(1, min(5, 3))"), 3.0);
+        assert_eq!(eval("atan2(1, 1)"), std::f64::consts::FRAC_PI_4);
+        assert_eq!(eval("sign(-2) + abs(-2) + floor(1.5) + ceil(1.5)"), 4.0);
+    }
+
+    #[test]
+    fn errors_report_the_column() {
+        let err = Expression::parse("u + w", &[]).unwrap_err();
+        assert_eq!(err.column, 5);
+        assert_eq!(err.message, "unknown variable 'w'");
+        let err = Expression::parse("foo(u)", &[]).unwrap_err();
+        assert_eq!(err.message, "unknown function 'foo'");
+        let err = Expression::parse("pow(u)", &[]).unwrap_err();
+        assert_eq!(err.message, "'pow' takes 2 arguments, got 1");
+        let err = Expression::parse("sin + 1", &[]).unwrap_err();
+        assert_eq!(err.message, "function 'sin' needs arguments");
+        let err = Expression::parse("(u + 1", &[]).unwrap_err();
+        assert_eq!(err.column, 7);
+        assert!(Expression::parse("u v", &[]).is_err());
+        assert!(Expression::parse("u $ v", &[]).is_err());
+        assert!(Expression::parse("", &[]).is_err());
+    }
+
+    #[test]
+    fn surface_parameter_names() {
+        let f = compile_surface("a * u", "b * v", "c", &names(&["a", "b", "c"])).unwrap();
+        assert_eq!(f(2.0, 3.0, [1.0, 2.0, 5.0, 0.0, 0.0]), [2.0, 6.0, 5.0]);
+
+        let err = |params: &[&str]| {
+            compile_surface("u", "v", "0", &names(params))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            err(&["a", "b", "c", "d", "f", "g"]),
+            "at most 5 parameter names are supported, got 6"
+        );
+        assert_eq!(err(&["a", "a"]), "parameter name 'a' is given twice");
+        assert_eq!(err(&["sin"]), "parameter name 'sin' is reserved");
+        assert_eq!(err(&["u"]), "parameter name 'u' is reserved");
+        assert_eq!(
+            err(&["1a"]),
+            "parameter name '1a' is not a valid identifier"
+        );
+        let unknown = compile_surface("u", "q", "0", &[]).err().unwrap();
+        assert_eq!(
+            format!("{:#}", unknown),
+            "y(u, v): column 1: unknown variable 'q'"
+        );
+    }
+
+    #[test]
+    fn derivatives() {
+        let d = compile_surface_derivatives("u * v", "sin(u)", "a * v^2", &names(&["a"])).unwrap();
+        let [du, dv] = d(2.0, 3.0, [0.5, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(du, [3.0, 2f64.cos(), 0.0]);
+        assert_eq!(dv, [2.0, 0.0, 3.0]);
+    }
+}
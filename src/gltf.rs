@@ -0,0 +1,315 @@
+use crate::mesh::Mesh;
+use anyhow::Context;
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use serde_json::json;
+use std::{fs, path::Path};
+
+// glTF constants
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const TRIANGLES: u32 = 4;
+
+const GLB_MAGIC: u32 = 0x4654_6C67;
+const GLB_CHUNK_JSON: u32 = 0x4E4F_534A;
+const GLB_CHUNK_BIN: u32 = 0x004E_4942;
+
+/// A mesh placed in a glTF scene by its node matrix.
+pub struct GltfNode<'a> {
+    pub name: &'a str,
+    pub mesh: &'a Mesh,
+    pub transform: Matrix4<f32>,
+}
+
+/// Writes the mesh as glTF 2.0: `.glb` produces a single binary file, anything else
+/// a `.gltf` JSON file with its buffer in a `.bin` file next to it.
+/// The transform is stored as the matrix of the node holding the mesh.
+pub fn write_gltf(mesh: &Mesh, path: &Path, transform: Matrix4<f32>) -> anyhow::Result<()> {
+    let node = GltfNode {
+        name: "surface",
+        mesh,
+        transform,
+    };
+    write_gltf_scene(&[node], path)
+}
+
+/// Like `write_gltf`, but with one node and mesh per entry of `nodes`, all sharing one buffer.
+/// Fails if a mesh has no triangles.
+pub fn write_gltf_scene(nodes: &[GltfNode], path: &Path) -> anyhow::Result<()> {
+    let is_glb = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("glb"));
+    let mut bin = Vec::new();
+    for node in nodes {
+        // empty buffer views and accessors without bounds are invalid glTF
+        if node.mesh.indices.is_empty() {
+            anyhow::bail!("mesh '{}' has no triangles", node.name);
+        }
+        let (min, max) = node.mesh.bounds();
+        if min[0] > max[0] {
+            anyhow::bail!("mesh '{}' has no finite vertex positions", node.name);
+        }
+        append_buffer_data(&mut bin, node.mesh);
+    }
+
+    if is_glb {
+        let document = document(nodes, None, bin.len());
+        let glb = glb_container(&serde_json::to_vec(&document)?, &bin);
+        fs::write(path, glb).with_context(|| format!("failed to write {}", path.display()))
+    } else {
+        let bin_path = path.with_extension("bin");
+        let bin_name = bin_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .context("invalid buffer file name")?;
+        let document = document(nodes, Some(bin_name), bin.len());
+        fs::write(&bin_path, &bin)
+            .with_context(|| format!("failed to write {}", bin_path.display()))?;
+        fs::write(path, serde_json::to_string_pretty(&document)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Packs positions, normals, colors and indices one after another; every block is 4-byte aligned.
+/// Normals and colors are left out if the mesh lacks them.
+fn append_buffer_data(bin: &mut Vec<u8>, mesh: &Mesh) {
+    for p in &mesh.positions {
+        bin.extend(p.iter().flat_map(|c| c.to_le_bytes()));
+    }
+    let normals = if mesh.has_normals() {
+        &mesh.normals[..]
+    } else {
+        &[]
+    };
+    for n in normals {
+        // glTF requires unit normals
+        let n = Vector3::from(*n);
+        let n: [f32; 3] = if n.magnitude2() > 0.0 && n.magnitude2().is_finite() {
+            n.normalize().into()
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        bin.extend(n.iter().flat_map(|c| c.to_le_bytes()));
+    }
+    let colors = if mesh.has_colors() {
+        &mesh.colors[..]
+    } else {
+        &[]
+    };
+    for c in colors {
+        let c = c.map(|c| c.clamp(0.0, 1.0));
+        bin.extend(c.iter().flat_map(|c| c.to_le_bytes()));
+    }
+    for i in &mesh.indices {
+        bin.extend(i.to_le_bytes());
+    }
+}
+
+fn document(nodes: &[GltfNode], uri: Option<&str>, byte_length: usize) -> serde_json::Value {
+    let mut buffer = json!({ "byteLength": byte_length });
+    if let Some(uri) = uri {
+        buffer["uri"] = json!(uri);
+    }
+
+    let mut gltf_nodes = Vec::new();
+    let mut meshes = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut offset = 0;
+    for (i, node) in nodes.iter().enumerate() {
+        let mesh = node.mesh;
+        let n = mesh.positions.len();
+        let (min, max) = mesh.bounds();
+        let matrix: &[f32; 16] = node.transform.as_ref();
+        // every accessor has a buffer view of its own, so both are numbered alike
+        let mut add = |byte_length: usize, target: u32, mut accessor: serde_json::Value| {
+            let index = accessors.len();
+            buffer_views.push(json!({
+                "buffer": 0,
+                "byteOffset": offset,
+                "byteLength": byte_length,
+                "target": target,
+            }));
+            offset += byte_length;
+            accessor["bufferView"] = json!(index);
+            accessors.push(accessor);
+            index
+        };
+        let vec3 = json!({ "componentType": FLOAT, "count": n, "type": "VEC3" });
+        let mut attributes = json!({
+            "POSITION": add(n * 12, ARRAY_BUFFER, json!({ "componentType": FLOAT, "count": n, "type": "VEC3", "min": min, "max": max })),
+        });
+        if mesh.has_normals() {
+            attributes["NORMAL"] = json!(add(n * 12, ARRAY_BUFFER, vec3.clone()));
+        }
+        if mesh.has_colors() {
+            attributes["COLOR_0"] = json!(add(n * 12, ARRAY_BUFFER, vec3));
+        }
+        let indices = add(
+            mesh.indices.len() * 4,
+            ELEMENT_ARRAY_BUFFER,
+            json!({ "componentType": UNSIGNED_INT, "count": mesh.indices.len(), "type": "SCALAR" }),
+        );
+
+        gltf_nodes.push(json!({ "name": node.name, "mesh": i, "matrix": matrix }));
+        meshes.push(json!({
+            "name": node.name,
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices,
+                "material": 0,
+                "mode": TRIANGLES,
+            }],
+        }));
+    }
+
+    json!({
+        "asset": { "version": "2.0", "generator": "Immersion's Control Engine" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": gltf_nodes,
+        "meshes": meshes,
+        "materials": [{
+            "name": "surface",
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [1.0, 1.0, 1.0, 1.0],
+                "metallicFactor": 0.0,
+                "roughnessFactor": 0.6,
+            },
+            "doubleSided": true,
+        }],
+        "buffers": [buffer],
+        "bufferViews": buffer_views,
+        "accessors": accessors,
+    })
+}
+
+/// Wraps the JSON and binary chunks into a GLB file. Chunks are padded to 4 bytes,
+/// the JSON with spaces and the binary data with zeros.
+fn glb_container(json: &[u8], bin: &[u8]) -> Vec<u8> {
+    let json_len = json.len().next_multiple_of(4);
+    let bin_len = bin.len().next_multiple_of(4);
+    let total = 12 + 8 + json_len + 8 + bin_len;
+
+    let mut glb = Vec::with_capacity(total);
+    glb.extend(GLB_MAGIC.to_le_bytes());
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((total as u32).to_le_bytes());
+
+    glb.extend((json_len as u32).to_le_bytes());
+    glb.extend(GLB_CHUNK_JSON.to_le_bytes());
+    glb.extend(json);
+    glb.resize(glb.len() + json_len - json.len(), b' ');
+
+    glb.extend((bin_len as u32).to_le_bytes());
+    glb.extend(GLB_CHUNK_BIN.to_le_bytes());
+    glb.extend(bin);
+    glb.resize(glb.len() + bin_len - bin.len(), 0);
+    glb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::tests::tetrahedron;
+    use cgmath::SquareMatrix;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let mesh = tetrahedron();
+        let path = std::env::temp_dir().join(format!("ice-round-trip-{}.glb", std::process::id()));
+        write_gltf(&mesh, &path, Matrix4::identity()).unwrap();
+        let glb = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(u32_at(&glb, 0), GLB_MAGIC);
+        assert_eq!(u32_at(&glb, 8) as usize, glb.len());
+        let json_len = u32_at(&glb, 12) as usize;
+        assert_eq!(u32_at(&glb, 16), GLB_CHUNK_JSON);
+        let document: serde_json::Value = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        let bin = &glb[20 + json_len + 8..];
+        assert_eq!(u32_at(&glb, 20 + json_len + 4), GLB_CHUNK_BIN);
+
+        let accessors = document["accessors"].as_array().unwrap();
+        assert_eq!(accessors[0]["count"], 4);
+        assert_eq!(accessors[0]["min"], json!([0.0, 0.0, 0.0]));
+        assert_eq!(accessors[0]["max"], json!([1.0, 1.0, 1.0]));
+        assert_eq!(accessors[3]["count"], 12);
+        assert_eq!(document["buffers"][0]["byteLength"], 3 * 4 * 12 + 12 * 4);
+
+        // read the positions and indices back through their buffer views
+        let view = |i: usize| {
+            let view = &document["bufferViews"][i];
+            let offset = view["byteOffset"].as_u64().unwrap() as usize;
+            &bin[offset..offset + view["byteLength"].as_u64().unwrap() as usize]
+        };
+        let positions: Vec<f32> = view(0)
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(positions, mesh.positions.concat());
+        let indices: Vec<u32> = view(3)
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(indices, mesh.indices);
+    }
+
+    #[test]
+    fn missing_normals_and_colors_are_left_out() {
+        let mut mesh = tetrahedron();
+        mesh.normals.clear();
+        let node = GltfNode {
+            name: "surface",
+            mesh: &mesh,
+            transform: Matrix4::identity(),
+        };
+        let document = document(&[node], None, 0);
+        let primitive = &document["meshes"][0]["primitives"][0];
+        assert_eq!(
+            primitive["attributes"],
+            json!({ "POSITION": 0, "COLOR_0": 1 })
+        );
+        assert_eq!(primitive["indices"], 2);
+        assert_eq!(document["accessors"].as_array().unwrap().len(), 3);
+        assert_eq!(document["bufferViews"][2]["byteOffset"], 2 * 4 * 12);
+        let mut bin = Vec::new();
+        append_buffer_data(&mut bin, &mesh);
+        assert_eq!(bin.len(), 2 * 4 * 12 + 12 * 4);
+    }
+
+    #[test]
+    fn bounds_skip_non_finite_positions() {
+        let mut mesh = tetrahedron();
+        mesh.positions[3] = [f32::NAN, f32::INFINITY, 0.0];
+        let node = GltfNode {
+            name: "surface",
+            mesh: &mesh,
+            transform: Matrix4::identity(),
+        };
+        let document = document(&[node], None, 0);
+        assert_eq!(document["accessors"][0]["min"], json!([0.0, 0.0, 0.0]));
+        assert_eq!(document["accessors"][0]["max"], json!([1.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    fn empty_meshes_are_rejected() {
+        let path = std::env::temp_dir().join(format!("ice-empty-{}.glb", std::process::id()));
+        let err = write_gltf(&Mesh::default(), &path, Matrix4::identity()).unwrap_err();
+        assert_eq!(err.to_string(), "mesh 'surface' has no triangles");
+        assert!(!path.exists());
+
+        let mut mesh = tetrahedron();
+        mesh.positions = vec![[f32::NAN; 3]; 4];
+        let err = write_gltf(&mesh, &path, Matrix4::identity()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "mesh 'surface' has no finite vertex positions"
+        );
+    }
+}
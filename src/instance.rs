@@ -16,3 +16,34 @@ pub async fn get_instance(window: Arc<Window>) -> (Arc<Surface>, Arc<Adapter>) {
         .expect("Failed to find appropriate Adapter!");
     (Arc::new(surface), Arc::new(adapter))
 }
+
+/// Requests an adapter that is not tied to any window surface.
+/// Falls back to a software adapter when no hardware adapter is available,
+/// so images can be produced on machines without a GPU.
+pub async fn get_headless_adapter(force_fallback: bool) -> anyhow::Result<Arc<Adapter>> {
+    let instance = Instance::new(Backends::all());
+    let mut adapter = None;
+    if !force_fallback {
+        adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await;
+    }
+    if adapter.is_none() {
+        adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await;
+    }
+    let adapter = adapter.ok_or_else(|| {
+        anyhow::anyhow!("no graphics adapter found, not even a software fallback")
+    })?;
+    log::info!("headless adapter: {:?}", adapter.get_info());
+    Ok(Arc::new(adapter))
+}
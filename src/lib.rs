@@ -1,99 +1,413 @@
+pub mod camera;
+pub mod colorbar;
+pub mod colormap;
 pub mod config;
+pub mod curvature;
 pub mod device;
+pub mod dual;
+pub mod expr;
+pub mod gltf;
 pub mod instance;
+pub mod math_func;
+pub mod mesh;
+pub mod obj;
 pub mod pipeline;
+pub mod ply;
+pub mod registry;
+pub mod scalar;
+pub mod scene;
 pub mod shader;
+pub mod stl;
+pub mod surface_data;
+pub mod text;
+pub mod texture;
 pub mod vertex_data;
 pub mod window;
+use camera::OrbitCamera;
 use cgmath::Matrix4;
 use cgmath::{Matrix, SquareMatrix};
+use colorbar::{colorbar_image, colorbar_origin, contrasting_color, ColorKey};
 use config::get_config;
-use device::get_device;
-use instance::get_instance;
-use pipeline::{create_projection, create_transforms, get_render_pipeline, light};
+use device::{check_sample_count, get_device};
+use instance::{get_headless_adapter, get_instance};
+use mesh::Mesh;
+use pipeline::{
+    bounding_sphere, create_animation_transform, create_object_uniforms, create_oit_targets,
+    create_render_object, create_render_targets, create_screen_image, create_shadow_map,
+    get_oit_pipelines, get_render_pipeline, get_screen_image_pipeline, get_shadow_pipeline,
+    get_wireframe_pipeline, light_uniforms, object_uniforms, shadow_uniforms,
+    shadow_view_projection, OitTargets, RenderObject, ScreenImage, ShadowMap, WireframeKind,
+    MAX_LIGHTS,
+};
+use scene::{
+    CameraSettings, ColorbarSettings, LightSettings, MaterialSettings, RenderMode, Scene,
+    ShadowSettings,
+};
 use shader::get_shaders;
 use std::iter;
+use std::num::NonZeroU32;
+use std::path::Path;
 use std::sync::Arc;
+use texture::{create_texture_bind_group, create_texture_bind_group_layout};
 use wgpu;
-use wgpu::{BindGroup, Buffer, Device, Queue, RenderPipeline, Surface, SurfaceConfiguration};
-use winit::{event::WindowEvent, window::Window};
-#[path = "./math_func.rs"]
-mod math_func;
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, CommandEncoder, Device, Queue, RenderPass, RenderPipeline,
+    Surface, SurfaceConfiguration, TextureView,
+};
+use winit::{
+    event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent},
+    window::Window,
+};
 
-const IS_PERSPECTIVE: bool = true;
-const ANIMATION_SPEED: f32 = 1.0;
 pub struct State {
     pub init: InitWgpu,
     pipeline: Arc<RenderPipeline>,
-    vertex_buffer: Buffer,
+    wire_pipeline: RenderPipeline,
     uniform_bind_group: BindGroup,
     vertex_uniform_buffer: Buffer,
-    view_mat: Matrix4<f32>,
+    fragment_uniform_buffer: Buffer,
+    light_uniform_buffer: Buffer,
+    shadow_map: ShadowMap,
+    shadow_pipeline: RenderPipeline,
+    shadow_bind_group: BindGroup,
+    /// Multisampled color target, resolved into the output texture; `None` without multisampling.
+    msaa_view: Option<TextureView>,
+    depth_view: TextureView,
+    /// Weighted blended order-independent transparency for the translucent objects.
+    oit_pipeline: RenderPipeline,
+    oit_composite_pipeline: RenderPipeline,
+    oit_composite_layout: BindGroupLayout,
+    oit_targets: OitTargets,
+    screen_pipeline: RenderPipeline,
+    screen_layout: BindGroupLayout,
     project_mat: Matrix4<f32>,
-    num_vertices: u32,
-    index_buffer: wgpu::Buffer,
+    /// Scene objects in draw order; the keys 1 to 9 toggle the visibility of the first nine.
+    pub objects: Vec<RenderObject>,
+    object_uniform_buffer: Buffer,
+    object_bind_group: BindGroup,
+    object_uniform_stride: u64,
+    /// Bound for objects without a texture.
+    default_texture_bind_group: BindGroup,
+    pub camera: OrbitCamera,
+    camera_settings: CameraSettings,
+    /// Scene lights, uploaded on every update so they can be changed at runtime.
+    /// F1 to F8 switch the first eight on and off.
+    pub lights: Vec<LightSettings>,
+    pub shadows: ShadowSettings,
+    background: wgpu::Color,
+    /// Switched with the W key.
+    pub render_mode: RenderMode,
+    pub wire_color: [f32; 3],
+    /// Spins the model around while `true`; toggled with the space bar.
+    pub auto_rotate: bool,
+    /// Shows the colorbar legend while `true`; toggled with the C key.
+    pub show_colorbar: bool,
+    colorbar_settings: ColorbarSettings,
+    /// Colors explained by the colorbar, `None` if no object is colored by a colormap.
+    color_key: Option<ColorKey>,
+    /// The colorbar drawn for the current window size.
+    colorbar: Option<ScreenImage>,
+    /// Rotation speed in radians per second.
+    pub animation_speed: f32,
+    model_angle: f32,
+    last_update: Option<std::time::Duration>,
 }
 
 pub struct InitWgpu {
-    pub surface: Arc<Surface>,
+    /// `None` for headless states, which can only render through `render_to_image`.
+    pub surface: Option<Arc<Surface>>,
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
     pub config: SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
+    /// Samples per pixel, checked against the adapter.
+    pub sample_count: u32,
 }
 
 impl InitWgpu {
-    async fn new(window: Arc<Window>) -> Self {
+    async fn new(window: Arc<Window>, sample_count: u32) -> anyhow::Result<Self> {
         let (surface, adapter) = get_instance(window.clone()).await;
         let (device, queue) = get_device(adapter.clone()).await;
         let size = window.inner_size();
         let config = get_config(adapter.clone(), surface.clone(), window.clone()).await;
-        Self {
-            surface,
+        let sample_count = check_sample_count(&adapter, config.format, sample_count)?;
+        Ok(Self {
+            surface: Some(surface),
             device,
             queue,
             config,
             size,
-        }
+            sample_count,
+        })
+    }
+
+    async fn new_headless(
+        width: u32,
+        height: u32,
+        force_fallback: bool,
+        sample_count: u32,
+    ) -> anyhow::Result<Self> {
+        let adapter = get_headless_adapter(force_fallback).await?;
+        let (device, queue) = get_device(adapter.clone()).await;
+        // the configuration is never applied to a surface, it only describes the offscreen target
+        let config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        let sample_count = check_sample_count(&adapter, config.format, sample_count)?;
+        Ok(Self {
+            surface: None,
+            device,
+            queue,
+            config,
+            size: winit::dpi::PhysicalSize::new(width, height),
+            sample_count,
+        })
     }
 }
 
 impl State {
-    pub async fn new(window: Arc<Window>) -> Self {
-        let init = InitWgpu::new(window).await;
-        let is_two_side: i32 = 1;
+    pub async fn new(window: Arc<Window>, scene: Scene) -> anyhow::Result<Self> {
+        let init = InitWgpu::new(window, scene.render.samples).await?;
+        Ok(Self::from_init(init, scene))
+    }
+
+    /// Creates a state that renders into an offscreen texture of the given size instead of a window.
+    pub async fn new_headless(
+        width: u32,
+        height: u32,
+        force_fallback: bool,
+        scene: Scene,
+    ) -> anyhow::Result<Self> {
+        if width == 0 || height == 0 {
+            anyhow::bail!("image size must be non-zero, got {}x{}", width, height);
+        }
+        let init =
+            InitWgpu::new_headless(width, height, force_fallback, scene.render.samples).await?;
+        Ok(Self::from_init(init, scene))
+    }
+
+    fn from_init(init: InitWgpu, scene: Scene) -> Self {
         let shader = get_shaders(init.device.clone());
-        let light_data = light([1.0, 1.0, 1.0], 0.1, 0.8, 0.4, 30.0, is_two_side);
+        let shadow_resolution = scene
+            .shadows
+            .resolution
+            .min(init.device.limits().max_texture_dimension_2d);
+        let shadow_map = create_shadow_map(&init.device, shadow_resolution);
+        let texture_bind_group_layout = create_texture_bind_group_layout(&init.device);
+        let default_texture_bind_group = create_texture_bind_group(
+            &init.device,
+            &init.queue,
+            &texture_bind_group_layout,
+            &image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 255, 255, 255])),
+        );
 
         // uniform data
         let (
             pipeline,
-            vertex_buffer,
             uniform_bind_group,
             vertex_uniform_buffer,
-            view_mat,
-            project_mat,
-            num_vertices,
-            index_buffer,
+            fragment_uniform_buffer,
+            light_uniform_buffer,
+            object_bind_group_layout,
+            pipeline_layout,
         ) = get_render_pipeline(
             init.device.clone(),
             shader.clone(),
-            init.queue.clone(),
             &init.config,
-            light_data,
+            init.sample_count,
+            &shadow_map,
+            &texture_bind_group_layout,
         );
+        let (shadow_pipeline, shadow_bind_group) = get_shadow_pipeline(
+            &init.device,
+            &shader,
+            &object_bind_group_layout,
+            &shadow_map,
+        );
+        let wireframe = WireframeKind::for_device(&init.device);
+        let wire_pipeline = get_wireframe_pipeline(
+            &init.device,
+            &shader,
+            &init.config,
+            &pipeline_layout,
+            wireframe,
+            init.sample_count,
+        );
+        let (msaa_view, depth_view) =
+            create_render_targets(&init.device, &init.config, init.sample_count);
+        let (oit_pipeline, oit_composite_pipeline, oit_composite_layout) = get_oit_pipelines(
+            &init.device,
+            &shader,
+            &init.config,
+            &pipeline_layout,
+            init.sample_count,
+        );
+        let oit_targets = create_oit_targets(
+            &init.device,
+            &init.config,
+            init.sample_count,
+            &oit_composite_layout,
+        );
+
+        let (screen_pipeline, screen_layout) =
+            get_screen_image_pipeline(&init.device, &shader, &init.config, init.sample_count);
+
+        let meshes: Vec<Mesh> = scene.objects.iter().map(|object| object.mesh()).collect();
+        // the named object, or else the first visible one colored by a colormap
+        let color_key = match &scene.colorbar.object {
+            Some(name) => scene
+                .objects
+                .iter()
+                .position(|object| &object.name == name)
+                .and_then(|i| meshes[i].color_key.clone()),
+            None => scene
+                .objects
+                .iter()
+                .zip(&meshes)
+                .find(|(object, mesh)| object.visible && mesh.color_key.is_some())
+                .and_then(|(_, mesh)| mesh.color_key.clone()),
+        };
+        if scene.colorbar.enabled && color_key.is_none() {
+            log::warn!("no object is colored by a colormap, the colorbar is not shown");
+        }
+        let mut colorbar_settings = scene.colorbar;
+        colorbar_settings.text_color = colorbar_settings
+            .text_color
+            .or_else(|| Some(contrasting_color(scene.background)));
 
-        Self {
+        let mut objects: Vec<RenderObject> = scene
+            .objects
+            .iter()
+            .zip(&meshes)
+            .map(|(object, mesh)| {
+                let mut render_object = create_render_object(
+                    &init.device,
+                    mesh,
+                    &object.name,
+                    object.transform,
+                    object.material.material(),
+                    object.visible,
+                    wireframe,
+                );
+                render_object.texture_bind_group = object.texture.as_ref().map(|image| {
+                    create_texture_bind_group(
+                        &init.device,
+                        &init.queue,
+                        &texture_bind_group_layout,
+                        image,
+                    )
+                });
+                render_object.translucent |= object
+                    .texture
+                    .as_ref()
+                    .is_some_and(|image| image.pixels().any(|p| p[3] < 255));
+                render_object
+            })
+            .collect();
+        if scene.ground.enabled {
+            let (center, radius) = bounding_sphere(&objects);
+            let height = scene.ground.height.unwrap_or(center.y - 1.01 * radius);
+            let mesh = Mesh::ground_plane(
+                [center.x, height, center.z],
+                3.0 * radius,
+                scene.ground.color,
+            );
+            let mut ground = create_render_object(
+                &init.device,
+                &mesh,
+                "ground",
+                Matrix4::identity(),
+                MaterialSettings::default().material(),
+                true,
+                wireframe,
+            );
+            ground.animated = false;
+            // the ground only receives shadows, fitting the shadow map around it would waste
+            // most of its resolution
+            ground.casts_shadow = false;
+            objects.push(ground);
+        }
+        let (object_uniform_buffer, object_bind_group, object_uniform_stride) =
+            create_object_uniforms(&init.device, &object_bind_group_layout, objects.len());
+
+        let camera = OrbitCamera::new(scene.camera.position.into(), scene.camera.target.into());
+        let project_mat = scene
+            .camera
+            .projection_mat(init.config.width as f32 / init.config.height as f32);
+        let [r, g, b] = scene.background;
+
+        let mut state = Self {
             init,
             pipeline,
-            vertex_buffer,
+            wire_pipeline,
             uniform_bind_group,
             vertex_uniform_buffer,
-            view_mat,
+            fragment_uniform_buffer,
+            light_uniform_buffer,
+            shadow_map,
+            shadow_pipeline,
+            shadow_bind_group,
+            msaa_view,
+            depth_view,
+            oit_pipeline,
+            oit_composite_pipeline,
+            oit_composite_layout,
+            oit_targets,
+            screen_pipeline,
+            screen_layout,
             project_mat,
-            num_vertices,
-            index_buffer,
-        }
+            objects,
+            object_uniform_buffer,
+            object_bind_group,
+            object_uniform_stride,
+            default_texture_bind_group,
+            camera,
+            camera_settings: scene.camera,
+            lights: scene.lights,
+            shadows: scene.shadows,
+            background: wgpu::Color {
+                r: r as f64,
+                g: g as f64,
+                b: b as f64,
+                a: 1.0,
+            },
+            render_mode: scene.render.mode,
+            wire_color: scene.render.wire_color,
+            auto_rotate: scene.animation.auto_rotate,
+            show_colorbar: colorbar_settings.enabled,
+            colorbar_settings,
+            color_key,
+            colorbar: None,
+            animation_speed: scene.animation.speed,
+            model_angle: 0.0,
+            last_update: None,
+        };
+        state.update_colorbar();
+        state
+    }
+
+    /// Redraws the colorbar image for the current window size.
+    fn update_colorbar(&mut self) {
+        let key = match &self.color_key {
+            Some(key) => key,
+            None => return,
+        };
+        let window = (self.init.config.width, self.init.config.height);
+        let text_color = self.colorbar_settings.text_color.unwrap_or([0.0; 3]);
+        let image = colorbar_image(key, &self.colorbar_settings, window, text_color);
+        let origin = colorbar_origin(self.colorbar_settings.position, image.dimensions(), window);
+        self.colorbar = Some(create_screen_image(
+            &self.init.device,
+            &self.init.queue,
+            &self.screen_layout,
+            &image,
+            origin,
+            window,
+        ));
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -101,117 +415,571 @@ impl State {
             self.init.size = new_size;
             self.init.config.width = new_size.width;
             self.init.config.height = new_size.height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
-            self.project_mat = create_projection(
-                new_size.width as f32 / new_size.height as f32,
-                IS_PERSPECTIVE,
+            if let Some(surface) = &self.init.surface {
+                surface.configure(&self.init.device, &self.init.config);
+            }
+            let (msaa_view, depth_view) =
+                create_render_targets(&self.init.device, &self.init.config, self.init.sample_count);
+            self.msaa_view = msaa_view;
+            self.depth_view = depth_view;
+            self.oit_targets = create_oit_targets(
+                &self.init.device,
+                &self.init.config,
+                self.init.sample_count,
+                &self.oit_composite_layout,
             );
+            self.project_mat = self
+                .camera_settings
+                .projection_mat(new_size.width as f32 / new_size.height as f32);
+            self.update_colorbar();
         }
     }
 
-    #[allow(unused_variables)]
-    pub fn input(&mut self, event: &WindowEvent) -> bool {
-        false
+    /// Current model transform of the object at `index`: its scene transform applied after
+    /// the auto rotation, so every object spins in place. The ground plane does not spin.
+    pub fn model_mat(&self, index: usize) -> Matrix4<f32> {
+        let object = &self.objects[index];
+        if object.animated {
+            object.transform * create_animation_transform(self.model_angle)
+        } else {
+            object.transform
+        }
     }
 
-    // Used to update the position of the object.
-    pub fn update(&mut self, dt: std::time::Duration) {
-        // update uniform buffer
-        let dt = ANIMATION_SPEED * dt.as_secs_f32();
-        let model_mat =
-            create_transforms([0.0, 0.0, 0.0], [dt.sin(), dt.cos(), 0.0], [1.0, 1.0, 1.0]);
-        let view_project_mat = self.project_mat * self.view_mat;
+    /// Handles camera and keyboard input. Returns `true` if the event was consumed.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        if self.camera.process_event(event) {
+            return true;
+        }
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Space),
+                        ..
+                    },
+                ..
+            } => {
+                self.auto_rotate = !self.auto_rotate;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::W),
+                        ..
+                    },
+                ..
+            } => {
+                self.render_mode = self.render_mode.next();
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::C),
+                        ..
+                    },
+                ..
+            } => {
+                self.show_colorbar = !self.show_colorbar;
+                true
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(object) = object_key_index(*key).and_then(|i| self.objects.get_mut(i)) {
+                    object.visible = !object.visible;
+                    true
+                } else if let Some(light) =
+                    light_key_index(*key).and_then(|i| self.lights.get_mut(i))
+                {
+                    light.enabled = !light.enabled;
+                    true
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        }
+    }
 
-        let normal_mat = (model_mat.invert().unwrap()).transpose();
+    // Used to update the position of the object. `elapsed` is the time since rendering started.
+    pub fn update(&mut self, elapsed: std::time::Duration) {
+        // the model only spins while auto rotation is on, so it rests where it was paused
+        let dt = match self.last_update {
+            Some(last) => elapsed.saturating_sub(last),
+            None => std::time::Duration::ZERO,
+        };
+        self.last_update = Some(elapsed);
+        if self.auto_rotate {
+            self.model_angle += self.animation_speed * dt.as_secs_f32();
+        }
 
-        let model_ref: &[f32; 16] = model_mat.as_ref();
+        // update uniform buffers
+        let view_project_mat = self.project_mat * self.camera.view_mat();
         let view_projection_ref: &[f32; 16] = view_project_mat.as_ref();
-        let normal_ref: &[f32; 16] = normal_mat.as_ref();
-
         self.init.queue.write_buffer(
             &self.vertex_uniform_buffer,
             0,
-            bytemuck::cast_slice(model_ref),
+            bytemuck::cast_slice(view_projection_ref),
         );
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let model_mat = self.model_mat(i);
+            let normal_mat = model_mat
+                .invert()
+                .unwrap_or_else(Matrix4::identity)
+                .transpose();
+            let uniforms = object_uniforms(model_mat, normal_mat, object.material, object.overlay);
+            self.init.queue.write_buffer(
+                &self.object_uniform_buffer,
+                i as u64 * self.object_uniform_stride,
+                bytemuck::cast_slice(&[uniforms]),
+            );
+        }
+
+        // lights without a fixed position sit at the eye, so they move together with the camera
+        let eye_position: [f32; 3] = self.camera.position().into();
+        let view_direction: [f32; 3] = (self.camera.target - self.camera.position()).into();
+        let enabled: Vec<_> = self
+            .lights
+            .iter()
+            .filter(|light| light.enabled)
+            .take(MAX_LIGHTS)
+            .collect();
+        let lights: Vec<_> = enabled
+            .iter()
+            .map(|light| light.light(eye_position, view_direction))
+            .collect();
+        let shadow_light = self.shadow_light_index();
         self.init.queue.write_buffer(
-            &self.vertex_uniform_buffer,
-            64,
-            bytemuck::cast_slice(view_projection_ref),
+            &self.light_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[light_uniforms(&lights, shadow_light)]),
         );
+
+        // the shadow map covers the visible casters from the shadow casting light
+        if let Some(i) = shadow_light {
+            let visible = || self.objects.iter().filter(|o| o.visible);
+            let (center, radius) = bounding_sphere(visible().filter(|o| o.casts_shadow));
+            let light_view_project_mat =
+                shadow_view_projection(&lights[i], center, radius, bounding_sphere(visible()));
+            let uniforms = shadow_uniforms(
+                light_view_project_mat,
+                self.shadows.depth_bias,
+                self.shadow_map.resolution,
+            );
+            self.init.queue.write_buffer(
+                &self.shadow_map.uniform_buffer,
+                0,
+                bytemuck::cast_slice(&[uniforms]),
+            );
+        }
+        let [r, g, b] = self.wire_color;
+        let [x, y, z] = eye_position;
         self.init.queue.write_buffer(
-            &self.vertex_uniform_buffer,
-            128,
-            bytemuck::cast_slice(normal_ref),
+            &self.fragment_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[x, y, z, 1.0, r, g, b, 1.0]),
         );
     }
 
+    /// Index among the enabled lights of the one casting shadows, if shadows are on.
+    fn shadow_light_index(&self) -> Option<usize> {
+        if !self.shadows.enabled {
+            return None;
+        }
+        self.lights
+            .iter()
+            .filter(|light| light.enabled)
+            .take(MAX_LIGHTS)
+            .position(|light| light.cast_shadows)
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         //let output = self.init.surface.get_current_frame()?.output;
-        let output = self.init.surface.get_current_texture()?;
+        let output = self
+            .init
+            .surface
+            .as_ref()
+            .expect("headless states have no surface, use render_to_image instead")
+            .get_current_texture()?;
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
-        let depth_texture = self.init.device.create_texture(&wgpu::TextureDescriptor {
+
+        let mut encoder =
+            self.init
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+        self.draw(&mut encoder, &view);
+
+        self.init.queue.submit(iter::once(encoder.finish()));
+        output.present();
+
+        Ok(())
+    }
+
+    /// Renders the current frame into an offscreen texture and reads it back.
+    pub fn render_to_image(&mut self) -> anyhow::Result<image::RgbaImage> {
+        let width = self.init.config.width;
+        let height = self.init.config.height;
+        let texture = self.init.device.create_texture(&wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
-                width: self.init.config.width,
-                height: self.init.config.height,
+                width,
+                height,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Depth24Plus,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            label: None,
+            format: self.init.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            label: Some("Offscreen Texture"),
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // rows copied out of a texture have to be padded to COPY_BYTES_PER_ROW_ALIGNMENT
+        let unpadded_bytes_per_row = 4 * width;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+        let output_buffer = self.init.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
         });
-        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut encoder =
             self.init
                 .device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
+                    label: Some("Offscreen Encoder"),
                 });
+        self.draw(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &output_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: NonZeroU32::new(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.init.queue.submit(iter::once(encoder.finish()));
+
+        let buffer_slice = output_buffer.slice(..);
+        let mapping = buffer_slice.map_async(wgpu::MapMode::Read);
+        self.init.device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapping)?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = buffer_slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+            }
+        }
+        output_buffer.unmap();
+
+        // window surfaces usually prefer a BGRA format
+        if matches!(
+            self.init.config.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("readback buffer does not match the image size"))
+    }
+
+    /// Renders the current frame and writes it to an image file, e.g. a PNG.
+    pub fn save_image<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let image = self.render_to_image()?;
+        image
+            .save(path)
+            .map_err(|e| anyhow::anyhow!("failed to write {}: {}", path.display(), e))
+    }
 
+    /// Renders the depth of the visible shadow casters as seen from the shadow casting light.
+    fn draw_shadow_map(&self, encoder: &mut CommandEncoder) {
+        let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.shadow_map.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        shadow_pass.set_pipeline(&self.shadow_pipeline);
+        shadow_pass.set_bind_group(0, &self.shadow_bind_group, &[]);
+        // translucent objects cast the same shadow as opaque ones
+        for (i, object) in self.objects.iter().enumerate() {
+            if !object.visible || !object.casts_shadow {
+                continue;
+            }
+            let offset = (i as u64 * self.object_uniform_stride) as u32;
+            shadow_pass.set_bind_group(1, &self.object_bind_group, &[offset]);
+            shadow_pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
+            shadow_pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            shadow_pass.draw_indexed(0..object.num_indices, 0, 0..1);
+        }
+    }
+
+    fn texture_bind_group<'a>(&'a self, object: &'a RenderObject) -> &'a BindGroup {
+        object
+            .texture_bind_group
+            .as_ref()
+            .unwrap_or(&self.default_texture_bind_group)
+    }
+
+    /// Draws the visible objects that are translucent or not, with the pipeline already set.
+    fn draw_objects<'a>(&'a self, render_pass: &mut RenderPass<'a>, translucent: bool) {
+        for (i, object) in self.objects.iter().enumerate() {
+            if !object.visible || object.translucent != translucent {
+                continue;
+            }
+            let offset = (i as u64 * self.object_uniform_stride) as u32;
+            render_pass.set_bind_group(1, &self.object_bind_group, &[offset]);
+            render_pass.set_bind_group(2, self.texture_bind_group(object), &[]);
+            render_pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..object.num_indices, 0, 0..1);
+        }
+    }
+
+    /// Accumulates the translucent objects behind the nearest opaque surface, in any order,
+    /// and blends their weighted average color over the frame in `target`.
+    fn draw_translucent(
+        &self,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        resolve_target: Option<&TextureView>,
+    ) {
+        let oit = &self.oit_targets;
+        let (accum, accum_resolve, revealage, revealage_resolve) = match &oit.msaa_views {
+            Some((accum, revealage)) => (
+                accum,
+                Some(&oit.accum_view),
+                revealage,
+                Some(&oit.revealage_view),
+            ),
+            None => (&oit.accum_view, None, &oit.revealage_view, None),
+        };
         {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.2,
-                            g: 0.247,
-                            b: 0.314,
-                            a: 1.0,
-                        }),
-                        store: true,
+            let mut accum_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("OIT Accumulation Pass"),
+                color_attachments: &[
+                    wgpu::RenderPassColorAttachment {
+                        view: accum,
+                        resolve_target: accum_resolve,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    },
+                    wgpu::RenderPassColorAttachment {
+                        view: revealage,
+                        resolve_target: revealage_resolve,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                            store: true,
+                        },
                     },
-                }],
-                //depth_stencil_attachment: None,
+                ],
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &depth_view,
+                    view: &self.depth_view,
                     depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
+                        load: wgpu::LoadOp::Load,
                         store: false,
                     }),
                     stencil_ops: None,
                 }),
             });
+            accum_pass.set_pipeline(&self.oit_pipeline);
+            accum_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            self.draw_objects(&mut accum_pass, true);
+        }
+
+        let mut composite_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("OIT Composite Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        composite_pass.set_pipeline(&self.oit_composite_pipeline);
+        composite_pass.set_bind_group(0, &oit.bind_group, &[]);
+        composite_pass.draw(0..3, 0..1);
+    }
+
+    fn draw(&self, encoder: &mut CommandEncoder, view: &TextureView) {
+        if self.shadow_light_index().is_some() {
+            self.draw_shadow_map(encoder);
+        }
+        let shaded = self.render_mode != RenderMode::Wireframe;
+        let translucent = shaded && self.objects.iter().any(|o| o.visible && o.translucent);
 
+        // with multisampling the frame is drawn into the multisampled target and resolved into `view`
+        let (target, resolve_target) = match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(view)),
+            None => (view, None),
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.background),
+                    store: true,
+                },
+            }],
+            //depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                // the transparency pass tests against the depth of the opaque objects
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: translucent,
+                }),
+                stencil_ops: None,
+            }),
+        });
+
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        if shaded {
             render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.draw_indexed(0..self.num_vertices, 0, 0..1);
+            self.draw_objects(&mut render_pass, false);
         }
 
-        self.init.queue.submit(iter::once(encoder.finish()));
-        output.present();
+        // the edges go on top, depth tested against the shaded surfaces in the hidden-line mode
+        if self.render_mode != RenderMode::Shaded {
+            render_pass.set_pipeline(&self.wire_pipeline);
+            for (i, object) in self.objects.iter().enumerate() {
+                if !object.visible {
+                    continue;
+                }
+                let offset = (i as u64 * self.object_uniform_stride) as u32;
+                render_pass.set_bind_group(1, &self.object_bind_group, &[offset]);
+                render_pass.set_bind_group(2, self.texture_bind_group(object), &[]);
+                match &object.wire_vertex_buffer {
+                    Some(wire_vertex_buffer) => {
+                        render_pass.set_vertex_buffer(0, wire_vertex_buffer.slice(..));
+                        render_pass.draw(0..object.num_indices, 0..1);
+                    }
+                    None => {
+                        render_pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
+                        render_pass.set_index_buffer(
+                            object.index_buffer.slice(..),
+                            wgpu::IndexFormat::Uint32,
+                        );
+                        render_pass.draw_indexed(0..object.num_indices, 0, 0..1);
+                    }
+                }
+            }
+        }
+        drop(render_pass);
 
-        Ok(())
+        if translucent {
+            self.draw_translucent(encoder, target, resolve_target);
+        }
+        if let Some(colorbar) = self.colorbar.as_ref().filter(|_| self.show_colorbar) {
+            self.draw_screen_image(encoder, colorbar, target, resolve_target);
+        }
+    }
+
+    /// Blends `image` over the finished frame.
+    fn draw_screen_image(
+        &self,
+        encoder: &mut CommandEncoder,
+        image: &ScreenImage,
+        target: &TextureView,
+        resolve_target: Option<&TextureView>,
+    ) {
+        let mut screen_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Screen Image Pass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        screen_pass.set_pipeline(&self.screen_pipeline);
+        screen_pass.set_bind_group(0, &image.bind_group, &[]);
+        screen_pass.set_vertex_buffer(0, image.vertex_buffer.slice(..));
+        screen_pass.draw(0..6, 0..1);
     }
 }
+
+/// Maps the function keys F1 to F8 to light indices 0 to 7.
+fn light_key_index(key: VirtualKeyCode) -> Option<usize> {
+    let keys = [
+        VirtualKeyCode::F1,
+        VirtualKeyCode::F2,
+        VirtualKeyCode::F3,
+        VirtualKeyCode::F4,
+        VirtualKeyCode::F5,
+        VirtualKeyCode::F6,
+        VirtualKeyCode::F7,
+        VirtualKeyCode::F8,
+    ];
+    keys.iter().position(|k| *k == key)
+}
+
+/// Maps the number keys 1 to 9 to object indices 0 to 8.
+fn object_key_index(key: VirtualKeyCode) -> Option<usize> {
+    let keys = [
+        VirtualKeyCode::Key1,
+        VirtualKeyCode::Key2,
+        VirtualKeyCode::Key3,
+        VirtualKeyCode::Key4,
+        VirtualKeyCode::Key5,
+        VirtualKeyCode::Key6,
+        VirtualKeyCode::Key7,
+        VirtualKeyCode::Key8,
+        VirtualKeyCode::Key9,
+    ];
+    keys.iter().position(|k| *k == key)
+}
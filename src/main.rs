@@ -1,21 +1,63 @@
-pub mod config;
-pub mod device;
-pub mod instance;
-pub mod pipeline;
-pub mod shader;
-pub mod vertex_data;
-pub mod window;
+mod cli;
 
-use immersions_control_engine::State;
-use window::get_window;
+use clap::Parser;
+use cli::Cli;
+use immersions_control_engine::{
+    gltf::{write_gltf_scene, GltfNode},
+    mesh::Mesh,
+    pipeline::create_animation_transform,
+    registry::SurfaceRegistry,
+    scene::Scene,
+    stl::{write_stl, StlFormat},
+    window::get_window,
+    State,
+};
 use winit::{
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::ControlFlow,
 };
 
 fn main() {
+    let cli = Cli::parse();
+    let registry = SurfaceRegistry::default();
+    if cli.list {
+        Cli::print_list(&registry);
+        return;
+    }
+    let scene = match cli.scene(&registry) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(path) = &cli.export {
+        if let Err(e) = export_mesh(&cli, path, &scene) {
+            eprintln!("error: {:#}", e);
+            std::process::exit(1);
+        }
+        if cli.output.is_none() {
+            return;
+        }
+    }
+
+    if let Some(output) = &cli.output {
+        if let Err(e) = render_headless(&cli, output, scene) {
+            eprintln!("error: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let (event_loop, window) = get_window();
-    let mut state = pollster::block_on(State::new(window.clone()));
+    let mut state = match pollster::block_on(State::new(window.clone(), scene)) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            std::process::exit(1);
+        }
+    };
     let render_start_time = std::time::Instant::now();
 
     event_loop.run(
@@ -64,3 +106,72 @@ fn main() {
         },
     );
 }
+
+fn render_headless(cli: &Cli, output: &std::path::Path, scene: Scene) -> anyhow::Result<()> {
+    env_logger::init();
+    let mut state = pollster::block_on(State::new_headless(
+        cli.width,
+        cli.height,
+        cli.fallback_adapter,
+        scene,
+    ))?;
+    state.update(std::time::Duration::ZERO);
+    state.save_image(output)?;
+    println!("wrote {} ({}x{})", output.display(), cli.width, cli.height);
+    Ok(())
+}
+
+/// Writes the visible objects. glTF keeps one node per object, the other formats get a single
+/// mesh with the object transforms applied.
+fn export_mesh(cli: &Cli, path: &std::path::Path, scene: &Scene) -> anyhow::Result<()> {
+    let objects: Vec<_> = scene
+        .objects
+        .iter()
+        .filter(|o| o.visible)
+        .map(|o| (o, o.geometry.to_mesh()))
+        .collect();
+    if objects.is_empty() {
+        anyhow::bail!("the scene has no visible objects");
+    }
+    let mut mesh = Mesh::default();
+    for (object, object_mesh) in &objects {
+        mesh.append(&object_mesh.transformed(object.transform));
+    }
+    if cli.check_watertight {
+        let report = mesh.check_watertight()?;
+        println!("mesh is watertight ({})", report);
+    }
+
+    let is_gltf = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gltf") || e.eq_ignore_ascii_case("glb"));
+    if cli.stl_ascii {
+        let is_stl = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("stl"));
+        if !is_stl {
+            anyhow::bail!("--stl-ascii needs an .stl export file");
+        }
+        write_stl(&mesh, path, StlFormat::Ascii)?;
+    } else if is_gltf {
+        // glTF keeps the rest pose of the rotating objects as their node transforms
+        let nodes: Vec<_> = objects
+            .iter()
+            .map(|(object, object_mesh)| GltfNode {
+                name: &object.name,
+                mesh: object_mesh,
+                transform: object.transform * create_animation_transform(0.0),
+            })
+            .collect();
+        write_gltf_scene(&nodes, path)?;
+    } else {
+        mesh.save(path)?;
+    }
+    println!(
+        "wrote {} ({} vertices, {} triangles)",
+        path.display(),
+        mesh.positions.len(),
+        mesh.triangle_count()
+    );
+    Ok(())
+}
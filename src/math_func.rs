@@ -43,6 +43,33 @@ pub fn torus(u: f32, v: f32, params: [f32; 5]) -> [f32; 3] {
     [x, y, z]
 }
 
+/// Partial derivatives of `torus` along u and v.
+pub fn torus_derivatives(u: f64, v: f64, params: [f32; 5]) -> [[f64; 3]; 2] {
+    let (big_r, r) = (params[0] as f64, params[1] as f64);
+    let ring = big_r + r * v.cos();
+    [
+        [-ring * u.sin(), 0.0, ring * u.cos()],
+        [-r * v.sin() * u.cos(), r * v.cos(), -r * v.sin() * u.sin()],
+    ]
+}
+
+/// Parametric function of a sphere, `u` is the polar and `v` the azimuthal angle and params[0] the radius.
+pub fn sphere(u: f32, v: f32, params: [f32; 5]) -> [f32; 3] {
+    let x = params[0] * u.sin() * v.cos();
+    let y = params[0] * u.cos();
+    let z = -params[0] * u.sin() * v.sin();
+    [x, y, z]
+}
+
+/// Partial derivatives of `sphere` along u and v.
+pub fn sphere_derivatives(u: f64, v: f64, params: [f32; 5]) -> [[f64; 3]; 2] {
+    let r = params[0] as f64;
+    [
+        [r * u.cos() * v.cos(), -r * u.sin(), -r * u.cos() * v.sin()],
+        [-r * u.sin() * v.sin(), 0.0, -r * u.sin() * v.cos()],
+    ]
+}
+
 /// Used to create peaks surface.
 pub fn peaks(x: f32, z: f32) -> [f32; 3] {
     let y = 3.0 * (1.0 - x) * (1.0 - x) * (-(x * x) - (z + 1.0) * (z + 1.0)).exp()
@@ -0,0 +1,801 @@
+use crate::{
+    colorbar::ColorKey,
+    colormap::{self, Colormap},
+    gltf, obj,
+    pipeline::{vertex, Vertex},
+    ply,
+    scalar::{clamp_position, ColorScale, ScalarOverlay, ScalarPoint, ScalarSource},
+    stl::{self, StlFormat},
+    surface_data::{HeightField, ParametricSurface, Seam},
+};
+use anyhow::Context;
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Vector3};
+use std::{collections::HashMap, fmt, path::Path};
+
+/// Indexed triangle mesh with per-vertex normals, colors, opacities and texture coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 3]>,
+    /// Texture coordinates, empty for meshes without them.
+    pub uvs: Vec<[f32; 2]>,
+    /// Opacity of every vertex, empty for opaque meshes.
+    pub alphas: Vec<f32>,
+    /// Position of every vertex on the colormap, only kept for the overlay.
+    pub scalars: Vec<f32>,
+    /// Bands and contour lines the fragment shader draws from `scalars`.
+    pub overlay: ScalarOverlay,
+    /// What the vertex colors stand for, if they come from a colormap.
+    pub color_key: Option<ColorKey>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Tessellates the surface; the texture coordinates are `u` and `v` normalized to [0, 1].
+    /// Normals of coincident vertices along the seams and at the poles are averaged,
+    /// then the declared seams are welded.
+    pub fn from_parametric(ps: ParametricSurface) -> Self {
+        let (u_segments, v_segments) = (ps.u_segments, ps.v_segments);
+        let (u_seam, v_seam) = (ps.u_seam, ps.v_seam);
+        // the colors, the opacity and the overlay follow the colormap
+        let alpha = ps.alpha_ramp;
+        let values = if ps.use_colormap || alpha != [1.0, 1.0] || !ps.contours.is_empty() {
+            ps.scalar_values()
+        } else {
+            Vec::new()
+        };
+        let range = ps
+            .color_scale
+            .resolve_range(&values, ps.scalar.is_curvature());
+        let t: Vec<f32> = values
+            .iter()
+            .map(|&x| ps.color_scale.position(range, x))
+            .collect();
+        let overlay = ScalarOverlay::new(
+            &ps.colormap,
+            &ps.color_scale,
+            &ps.contours,
+            range,
+            ps.use_colormap,
+        );
+        let color_key = ps.use_colormap.then(|| ColorKey {
+            colormap: ps.colormap.clone(),
+            scale: ps.color_scale,
+            range,
+            label: ps.scalar.label(),
+        });
+        let (positions, normals, colors, indices) = ParametricSurface::new(ps, &t);
+        let mut mesh = Mesh {
+            positions,
+            normals,
+            colors,
+            uvs: grid_uvs(u_segments, v_segments),
+            indices,
+            color_key,
+            ..Default::default()
+        };
+        mesh.average_coincident_normals();
+        if alpha != [1.0, 1.0] {
+            mesh.apply_alpha_ramp(alpha, &t);
+        }
+        mesh.apply_overlay(overlay, t);
+        mesh.weld_seams(u_segments, v_segments, u_seam, v_seam);
+        mesh
+    }
+
+    /// Welds the vertices along the seams of an `(n + 1) x (m + 1)` vertex grid stored row by row,
+    /// so closed surfaces get a closed index buffer. A seam is only welded where the surface
+    /// really closes up, which a declared seam stops doing e.g. once the domain is overridden.
+    /// The welded vertices keep the attributes of the `umin` or `vmin` side.
+    fn weld_seams(&mut self, n: usize, m: usize, u_seam: Seam, v_seam: Seam) {
+        let index = |i: usize, j: usize| i * (m + 1) + j;
+        let u_pairs: Vec<(usize, usize)> = match u_seam {
+            Seam::Open => Vec::new(),
+            Seam::Periodic => (0..=m).map(|j| (index(n, j), index(0, j))).collect(),
+            Seam::Flipped => (0..=m).map(|j| (index(n, j), index(0, m - j))).collect(),
+        };
+        let v_pairs: Vec<(usize, usize)> = match v_seam {
+            Seam::Open => Vec::new(),
+            Seam::Periodic => (0..=n).map(|i| (index(i, m), index(i, 0))).collect(),
+            Seam::Flipped => (0..=n).map(|i| (index(i, m), index(n - i, 0))).collect(),
+        };
+        let tolerance = self.weld_tolerance();
+        let closes = |pairs: &[(usize, usize)]| {
+            pairs.iter().all(|&(a, b)| {
+                let (p, q) = (self.positions[a], self.positions[b]);
+                let d2 = (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2);
+                d2 <= tolerance * tolerance
+            })
+        };
+        let mut pairs = Vec::new();
+        for seam_pairs in [u_pairs, v_pairs] {
+            if closes(&seam_pairs) {
+                pairs.extend(seam_pairs);
+            }
+        }
+        if pairs.is_empty() {
+            return;
+        }
+
+        // union-find with the smaller index as representative, since the corners of a surface
+        // closed along both parameters are identified through both seams
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let count = self.positions.len();
+        let mut parent: Vec<usize> = (0..count).collect();
+        for (a, b) in pairs {
+            let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+            parent[ra.max(rb)] = ra.min(rb);
+        }
+        let mut kept = Vec::new();
+        let new_index: Vec<u32> = (0..count)
+            .map(|i| {
+                if find(&mut parent, i) == i {
+                    kept.push(i);
+                }
+                kept.len() as u32 - 1
+            })
+            .collect();
+        let remap: Vec<u32> = (0..count)
+            .map(|i| new_index[find(&mut parent, i)])
+            .collect();
+
+        fn select<T: Copy>(values: &[T], kept: &[usize]) -> Vec<T> {
+            if values.is_empty() {
+                return Vec::new();
+            }
+            kept.iter().map(|&i| values[i]).collect()
+        }
+        self.positions = select(&self.positions, &kept);
+        self.normals = select(&self.normals, &kept);
+        self.colors = select(&self.colors, &kept);
+        self.uvs = select(&self.uvs, &kept);
+        self.alphas = select(&self.alphas, &kept);
+        self.scalars = select(&self.scalars, &kept);
+        // a seam of a single segment folds its triangles flat
+        self.indices = self
+            .indices
+            .chunks_exact(3)
+            .map(|t| [0, 1, 2].map(|k| remap[t[k] as usize]))
+            .filter(|[a, b, c]| a != b && b != c && a != c)
+            .flatten()
+            .collect();
+    }
+
+    /// Tessellates the height field grid into two triangles per cell, facing up.
+    pub fn from_height_field(hf: &HeightField) -> Self {
+        let pts = hf.points();
+        let nz = hf.z_segments + 1;
+        let mut mesh = Mesh {
+            positions: pts.into_iter().flatten().collect(),
+            uvs: grid_uvs(hf.x_segments, hf.z_segments),
+            ..Default::default()
+        };
+        for i in 0..hf.x_segments {
+            for j in 0..hf.z_segments {
+                let idx0 = (j + i * nz) as u32;
+                let idx1 = (j + 1 + i * nz) as u32;
+                let idx2 = (j + 1 + (i + 1) * nz) as u32;
+                let idx3 = (j + (i + 1) * nz) as u32;
+                mesh.indices.extend([idx0, idx1, idx2, idx2, idx3, idx0]);
+            }
+        }
+        mesh.compute_normals();
+        let values = mesh.scalar_values(&hf.scalar, &hf.grid_parameters());
+        let range = hf.color_scale.resolve_range(&values, false);
+        let t: Vec<f32> = values
+            .iter()
+            .map(|&x| hf.color_scale.position(range, x))
+            .collect();
+        if hf.use_colormap {
+            mesh.apply_scalar_colors(&hf.colormap, &hf.color_scale, &t);
+            mesh.color_key = Some(ColorKey {
+                colormap: hf.colormap.clone(),
+                scale: hf.color_scale,
+                range,
+                label: hf.scalar.label(),
+            });
+        } else {
+            mesh.fill_color(hf.color);
+        }
+        if hf.alpha_ramp != [1.0, 1.0] {
+            mesh.apply_alpha_ramp(hf.alpha_ramp, &t);
+        }
+        let overlay = ScalarOverlay::new(
+            &hf.colormap,
+            &hf.color_scale,
+            &hf.contours,
+            range,
+            hf.use_colormap,
+        );
+        mesh.apply_overlay(overlay, t);
+        mesh
+    }
+
+    /// A horizontal square at `center` facing up, used as ground plane.
+    pub fn ground_plane(center: [f32; 3], half_size: f32, color: [f32; 3]) -> Self {
+        let [x, y, z] = center;
+        Mesh {
+            positions: vec![
+                [x - half_size, y, z - half_size],
+                [x - half_size, y, z + half_size],
+                [x + half_size, y, z + half_size],
+                [x + half_size, y, z - half_size],
+            ],
+            normals: vec![[0.0, 1.0, 0.0]; 4],
+            colors: vec![color; 4],
+            uvs: vec![[0.0, 0.0], [0.0, 1.0], [1.0, 1.0], [1.0, 0.0]],
+            indices: vec![0, 1, 2, 2, 3, 0],
+            ..Default::default()
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    /// Returns `true` if every vertex has a normal. Meshes assembled by hand may leave the
+    /// normals out, and the writers then leave them out of the file too.
+    pub fn has_normals(&self) -> bool {
+        self.normals.len() == self.positions.len()
+    }
+
+    /// Returns `true` if every vertex has a color, see `has_normals`.
+    pub fn has_colors(&self) -> bool {
+        self.colors.len() == self.positions.len()
+    }
+
+    /// Interleaves the attributes into the layout used by the render pipeline.
+    pub fn vertices(&self) -> Vec<Vertex> {
+        (0..self.positions.len())
+            .map(|i| {
+                let mut v = vertex(self.positions[i], self.normals[i], self.colors[i]);
+                v.color[3] = self.alphas.get(i).copied().unwrap_or(1.0);
+                v.uv = self.uvs.get(i).copied().unwrap_or_default();
+                v.scalar = self.scalars.get(i).copied().unwrap_or_default();
+                v
+            })
+            .collect()
+    }
+
+    /// Writes the mesh to `path`, picking the format from the extension.
+    /// Supported formats: `.obj` (with a `.mtl` next to it), binary `.stl`, binary `.ply`,
+    /// `.gltf` (with a `.bin` next to it) and `.glb`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        self.save_with_transform(path, Matrix4::identity())
+    }
+
+    /// Like `save`, but glTF files also record `transform` as the node matrix.
+    /// The other formats always store the untransformed mesh.
+    pub fn save_with_transform<P: AsRef<Path>>(
+        &self,
+        path: P,
+        transform: Matrix4<f32>,
+    ) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => obj::write_obj(self, path),
+            "stl" => stl::write_stl(self, path, StlFormat::Binary),
+            "ply" => ply::write_ply(self, path),
+            "gltf" | "glb" => gltf::write_gltf(self, path, transform),
+            _ => anyhow::bail!(
+                "unsupported mesh format '{}' for {}, expected .obj, .stl, .ply, .gltf or .glb",
+                extension,
+                path.display()
+            ),
+        }
+    }
+
+    /// Reads an `.obj`, `.stl` or `.ply` file, picking the format from the extension.
+    /// Missing normals are computed from the faces and meshes without vertex colors
+    /// are colored with the `jet` colormap along y.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut mesh = match extension.as_str() {
+            "obj" => obj::read_obj(path)?,
+            "stl" => stl::read_stl(path)?,
+            "ply" => ply::read_ply(path)?,
+            _ => anyhow::bail!(
+                "unsupported mesh format '{}' for {}, expected .obj, .stl or .ply",
+                extension,
+                path.display()
+            ),
+        };
+        mesh.validate()
+            .with_context(|| format!("invalid mesh {}", path.display()))?;
+        if mesh.normals.len() != mesh.positions.len() {
+            mesh.compute_normals();
+        }
+        if mesh.colors.len() != mesh.positions.len() {
+            mesh.apply_colormap(&Colormap::default(), 1);
+        }
+        Ok(mesh)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.indices.is_empty() {
+            anyhow::bail!("the mesh has no triangles");
+        }
+        if let Some(i) = self
+            .indices
+            .iter()
+            .find(|&&i| i as usize >= self.positions.len())
+        {
+            anyhow::bail!(
+                "vertex index {} out of range, the mesh has {} vertices",
+                i,
+                self.positions.len()
+            );
+        }
+        if self.positions.iter().flatten().any(|c| !c.is_finite()) {
+            anyhow::bail!("vertex positions must be finite");
+        }
+        Ok(())
+    }
+
+    /// Replaces the normals with the area-weighted average of the adjacent face normals.
+    pub fn compute_normals(&mut self) {
+        let mut sums = vec![Vector3::new(0.0, 0.0, 0.0); self.positions.len()];
+        for t in self.indices.chunks_exact(3) {
+            let [a, b, c] = [t[0], t[1], t[2]].map(|i| Vector3::from(self.positions[i as usize]));
+            // the cross product length is twice the triangle area
+            let n = (b - a).cross(c - a);
+            for &i in t {
+                sums[i as usize] += n;
+            }
+        }
+        self.normals = sums
+            .into_iter()
+            .map(|n| {
+                if n.magnitude2() > 0.0 {
+                    n.normalize().into()
+                } else {
+                    [0.0, 0.0, 0.0]
+                }
+            })
+            .collect();
+    }
+
+    /// Colors every vertex by its coordinate along `axis` (0 = x, 1 = y, 2 = z),
+    /// spreading the colormap over the extent of the mesh.
+    pub fn apply_colormap(&mut self, colormap: &Colormap, axis: usize) {
+        let (min, max) = self.bounds();
+        let (min, mut max) = (min[axis], max[axis]);
+        if max <= min {
+            // flat along the axis, every vertex gets the first color
+            max = min + 1.0;
+        }
+        self.colors = self
+            .positions
+            .iter()
+            .map(|p| colormap::color_interp(colormap, min, max, p[axis]))
+            .collect();
+    }
+
+    /// Scalar of every vertex. `parameters` holds the surface parameters of every vertex;
+    /// if empty, the texture coordinates are used, or NaN without them.
+    pub fn scalar_values(&self, source: &ScalarSource, parameters: &[[f32; 2]]) -> Vec<f32> {
+        let parameters = if parameters.is_empty() {
+            &self.uvs
+        } else {
+            parameters
+        };
+        (0..self.positions.len())
+            .map(|i| {
+                let [u, v] = parameters.get(i).copied().unwrap_or([f32::NAN; 2]);
+                let point = ScalarPoint {
+                    u,
+                    v,
+                    position: self.positions[i],
+                    normal: self.normals.get(i).copied().unwrap_or_default(),
+                    curvatures: None,
+                };
+                source.value(i, &point)
+            })
+            .collect()
+    }
+
+    /// Colors every vertex by its position `t` on the colormap, see `ColorScale::normalize`.
+    pub fn apply_scalar_colors(&mut self, colormap: &Colormap, scale: &ColorScale, t: &[f32]) {
+        self.colors = t.iter().map(|t| scale.color(colormap, *t)).collect();
+    }
+
+    /// Fades the opacity from `alpha[0]` at the low end of the colormap to `alpha[1]` at the
+    /// high end, given the position `t` of every vertex on the colormap.
+    pub fn apply_alpha_ramp(&mut self, alpha: [f32; 2], t: &[f32]) {
+        self.alphas = t
+            .iter()
+            .map(|t| alpha[0] + (alpha[1] - alpha[0]) * clamp_position(*t))
+            .collect();
+    }
+
+    /// Draws `overlay` from the colormap position `t` of every vertex; an empty overlay
+    /// leaves the smooth vertex colors.
+    pub fn apply_overlay(&mut self, overlay: ScalarOverlay, t: Vec<f32>) {
+        if overlay.is_empty() {
+            self.scalars.clear();
+            self.overlay = ScalarOverlay::default();
+        } else {
+            self.scalars = t;
+            self.overlay = overlay;
+        }
+    }
+
+    /// Whether any vertex is partly transparent.
+    pub fn is_translucent(&self) -> bool {
+        self.alphas.iter().any(|a| *a < 1.0)
+    }
+
+    pub fn fill_color(&mut self, color: [f32; 3]) {
+        self.colors = vec![color; self.positions.len()];
+    }
+
+    pub fn scale(&mut self, factor: f32) {
+        for p in &mut self.positions {
+            *p = p.map(|c| c * factor);
+        }
+    }
+
+    /// Returns a copy with positions and normals transformed by `m`.
+    pub fn transformed(&self, m: Matrix4<f32>) -> Mesh {
+        let normal_mat = m.invert().unwrap_or_else(Matrix4::identity).transpose();
+        let mut mesh = self.clone();
+        for p in &mut mesh.positions {
+            *p = (m * Vector3::from(*p).extend(1.0)).truncate().into();
+        }
+        for n in &mut mesh.normals {
+            let t = (normal_mat * Vector3::from(*n).extend(0.0)).truncate();
+            *n = if t.magnitude2() > 0.0 {
+                t.normalize().into()
+            } else {
+                *n
+            };
+        }
+        mesh
+    }
+
+    /// Adds the vertices and triangles of `other` to this mesh.
+    /// The overlays and color keys are dropped, only the vertex colors are kept.
+    pub fn append(&mut self, other: &Mesh) {
+        self.scalars.clear();
+        self.overlay = ScalarOverlay::default();
+        self.color_key = None;
+        // texture coordinates are optional, missing ones become zero once either mesh has them
+        if !self.uvs.is_empty() || !other.uvs.is_empty() {
+            self.uvs.resize(self.positions.len(), [0.0; 2]);
+            self.uvs.extend_from_slice(&other.uvs);
+            self.uvs
+                .resize(self.positions.len() + other.positions.len(), [0.0; 2]);
+        }
+        // the same for opacities, missing ones are opaque
+        if !self.alphas.is_empty() || !other.alphas.is_empty() {
+            self.alphas.resize(self.positions.len(), 1.0);
+            self.alphas.extend_from_slice(&other.alphas);
+            self.alphas
+                .resize(self.positions.len() + other.positions.len(), 1.0);
+        }
+        let offset = self.positions.len() as u32;
+        self.positions.extend_from_slice(&other.positions);
+        self.normals.extend_from_slice(&other.normals);
+        self.colors.extend_from_slice(&other.colors);
+        self.indices
+            .extend(other.indices.iter().map(|i| i + offset));
+    }
+
+    /// Axis-aligned bounding box of the finite positions as `(min, max)`. Without any,
+    /// `min` is `f32::MAX` and `max` is `f32::MIN`.
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in self
+            .positions
+            .iter()
+            .filter(|p| p.iter().all(|c| c.is_finite()))
+        {
+            for k in 0..3 {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+        (min, max)
+    }
+
+    /// Maps every vertex to the first vertex at the same position, within `tolerance`.
+    /// Parametric meshes repeat the vertices along their seams, so the triangles only share
+    /// edges once the duplicates are welded.
+    pub fn welded_indices(&self, tolerance: f32) -> Vec<u32> {
+        let cell = |p: [f32; 3]| {
+            [
+                (p[0] / tolerance).floor() as i64,
+                (p[1] / tolerance).floor() as i64,
+                (p[2] / tolerance).floor() as i64,
+            ]
+        };
+        let mut grid: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.positions.len());
+        for (i, p) in self.positions.iter().enumerate() {
+            let c = cell(*p);
+            let mut found = None;
+            // a neighbor within tolerance can sit in any adjacent cell
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        if let Some(candidates) = grid.get(&[c[0] + dx, c[1] + dy, c[2] + dz]) {
+                            for &j in candidates {
+                                let q = self.positions[j as usize];
+                                let d2 = (p[0] - q[0]).powi(2)
+                                    + (p[1] - q[1]).powi(2)
+                                    + (p[2] - q[2]).powi(2);
+                                if d2 <= tolerance * tolerance {
+                                    found = Some(j);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            match found {
+                Some(j) => remap.push(j),
+                None => {
+                    grid.entry(c).or_default().push(i as u32);
+                    remap.push(i as u32);
+                }
+            }
+        }
+        remap
+    }
+
+    /// Distance below which two vertices count as the same point: a tiny fraction of the
+    /// bounding box diagonal.
+    pub fn weld_tolerance(&self) -> f32 {
+        let (min, max) = self.bounds();
+        let diagonal =
+            ((max[0] - min[0]).powi(2) + (max[1] - min[1]).powi(2) + (max[2] - min[2]).powi(2))
+                .sqrt();
+        (diagonal * 1e-5).max(f32::MIN_POSITIVE)
+    }
+
+    /// Gives vertices at the same position the average of their normals, so the shading
+    /// is smooth across the seams of closed surfaces and around poles. Normals facing the other
+    /// way, as across the seam of a Moebius strip, are averaged flipped and keep their side.
+    /// Vertices without a normal take the one of the group.
+    pub fn average_coincident_normals(&mut self) {
+        let remap = self.welded_indices(self.weld_tolerance());
+        let normal = |i: usize| Vector3::from(self.normals[i]);
+        // the first usable normal of a group decides which side counts as the front
+        let mut reference: HashMap<u32, Vector3<f32>> = HashMap::new();
+        for (i, &r) in remap.iter().enumerate() {
+            let n = normal(i);
+            if n.magnitude2() > 0.0 && n.magnitude2().is_finite() {
+                reference.entry(r).or_insert(n);
+            }
+        }
+        let mut sums: HashMap<u32, Vector3<f32>> = HashMap::new();
+        for (i, &r) in remap.iter().enumerate() {
+            let n = normal(i);
+            if let Some(front) = reference.get(&r) {
+                if n.magnitude2().is_finite() {
+                    let sign = if n.dot(*front) < 0.0 { -1.0 } else { 1.0 };
+                    *sums.entry(r).or_insert(Vector3::new(0.0, 0.0, 0.0)) += n * sign;
+                }
+            }
+        }
+        for (i, &r) in remap.iter().enumerate() {
+            let Some(sum) = sums.get(&r).filter(|s| s.magnitude2() > 0.0) else {
+                continue;
+            };
+            let n = Vector3::from(self.normals[i]);
+            let sign = if n.dot(*sum) < 0.0 { -1.0 } else { 1.0 };
+            self.normals[i] = (sum.normalize() * sign).into();
+        }
+    }
+
+    /// Counts open and non-manifold edges after welding coincident vertices.
+    /// Triangles that collapse to a line or point, e.g. at the poles of a sphere, are ignored.
+    pub fn watertight_report(&self) -> WatertightReport {
+        let remap = self.welded_indices(self.weld_tolerance());
+
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut degenerate_triangles = 0;
+        for t in self.indices.chunks_exact(3) {
+            let [a, b, c] = [
+                remap[t[0] as usize],
+                remap[t[1] as usize],
+                remap[t[2] as usize],
+            ];
+            if a == b || b == c || c == a {
+                degenerate_triangles += 1;
+                continue;
+            }
+            for (p, q) in [(a, b), (b, c), (c, a)] {
+                *edges.entry((p.min(q), p.max(q))).or_insert(0) += 1;
+            }
+        }
+        WatertightReport {
+            boundary_edges: edges.values().filter(|&&n| n == 1).count(),
+            non_manifold_edges: edges.values().filter(|&&n| n > 2).count(),
+            degenerate_triangles,
+        }
+    }
+
+    /// Fails with a summary of the defects unless the mesh is watertight.
+    pub fn check_watertight(&self) -> anyhow::Result<WatertightReport> {
+        let report = self.watertight_report();
+        if !report.is_watertight() {
+            anyhow::bail!("mesh is not watertight: {}", report);
+        }
+        Ok(report)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatertightReport {
+    /// Edges used by a single triangle, i.e. holes or an open border.
+    pub boundary_edges: usize,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: usize,
+    pub degenerate_triangles: usize,
+}
+
+impl WatertightReport {
+    pub fn is_watertight(&self) -> bool {
+        self.boundary_edges == 0 && self.non_manifold_edges == 0
+    }
+}
+
+impl fmt::Display for WatertightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} boundary edges, {} non-manifold edges, {} degenerate triangles",
+            self.boundary_edges, self.non_manifold_edges, self.degenerate_triangles
+        )
+    }
+}
+
+/// Replaces NaN or infinite normals, e.g. at degenerate points, so other tools can read the file.
+pub(crate) fn finite_normal(n: [f32; 3]) -> [f32; 3] {
+    if n.iter().all(|c| c.is_finite()) {
+        n
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+/// Texture coordinates of a `(n + 1) x (m + 1)` vertex grid stored row by row,
+/// spanning [0, 1] along both directions.
+fn grid_uvs(n: usize, m: usize) -> Vec<[f32; 2]> {
+    (0..=n)
+        .flat_map(|i| (0..=m).map(move |j| [i as f32 / n as f32, j as f32 / m as f32]))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Closed tetrahedron with outward facing triangles and distinct 8-bit vertex colors.
+    pub(crate) fn tetrahedron() -> Mesh {
+        let mut mesh = Mesh {
+            positions: vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+            ],
+            colors: vec![
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.2, 0.6, 1.0],
+            ],
+            indices: vec![0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3],
+            ..Default::default()
+        };
+        mesh.compute_normals();
+        mesh
+    }
+
+    /// Corner positions of every triangle, comparable across differently indexed meshes.
+    pub(crate) fn triangles(mesh: &Mesh) -> Vec<[[f32; 3]; 3]> {
+        mesh.indices
+            .chunks_exact(3)
+            .map(|t| [0, 1, 2].map(|k| mesh.positions[t[k] as usize]))
+            .collect()
+    }
+
+    #[test]
+    fn watertight() {
+        let mesh = tetrahedron();
+        let report = mesh.check_watertight().unwrap();
+        assert_eq!(report.degenerate_triangles, 0);
+
+        // every triangle with its own vertices, as in STL files, is closed once welded
+        let mut unshared = Mesh::default();
+        for [a, b, c] in triangles(&mesh) {
+            let first = unshared.positions.len() as u32;
+            unshared.positions.extend([a, b, c]);
+            unshared.indices.extend([first, first + 1, first + 2]);
+        }
+        assert!(unshared.watertight_report().is_watertight());
+
+        let mut open = mesh.clone();
+        open.indices.truncate(9);
+        let report = open.watertight_report();
+        assert_eq!(report.boundary_edges, 3);
+        assert_eq!(
+            open.check_watertight().unwrap_err().to_string(),
+            "mesh is not watertight: 3 boundary edges, 0 non-manifold edges, 0 degenerate triangles"
+        );
+
+        let mut fin = mesh;
+        fin.positions.push([1.0, 1.0, 1.0]);
+        fin.indices.extend([1, 2, 4]);
+        assert_eq!(fin.watertight_report().non_manifold_edges, 1);
+    }
+
+    fn surface(name: &str) -> ParametricSurface {
+        match crate::registry::SurfaceRegistry::default()
+            .get(name)
+            .unwrap()
+        {
+            crate::scene::Geometry::Parametric(ps) => ps,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn periodic_seams_close_the_torus() {
+        let ps = surface("torus");
+        let (n, m) = (ps.u_segments, ps.v_segments);
+        let mesh = Mesh::from_parametric(ps.clone());
+        // the last row and column of the grid fall onto the first ones
+        assert_eq!(mesh.positions.len(), n * m);
+        assert_eq!(mesh.triangle_count(), 2 * n * m);
+        assert!(mesh.indices.iter().all(|&i| (i as usize) < n * m));
+        let report = mesh.check_watertight().unwrap();
+        assert_eq!(report.degenerate_triangles, 0);
+
+        let open = Mesh::from_parametric(ParametricSurface {
+            u_seam: Seam::Open,
+            ..ps.clone()
+        });
+        assert_eq!(open.positions.len(), (n + 1) * m);
+        // the check welds coincident vertices itself
+        assert!(open.check_watertight().is_ok());
+
+        // half a torus does not close up, so the declared seam is left alone
+        let half = Mesh::from_parametric(ParametricSurface {
+            umax: std::f32::consts::PI,
+            ..ps
+        });
+        assert_eq!(half.positions.len(), (n + 1) * m);
+        assert_eq!(half.watertight_report().boundary_edges, 2 * m);
+    }
+
+    #[test]
+    fn flipped_seam_closes_the_klein_bottle() {
+        let ps = surface("klein_bottle");
+        let (n, m) = (ps.u_segments, ps.v_segments);
+        let mesh = Mesh::from_parametric(ps);
+        assert_eq!(mesh.positions.len(), n * m);
+        assert_eq!(mesh.watertight_report().boundary_edges, 0);
+    }
+}
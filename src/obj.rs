@@ -0,0 +1,285 @@
+use crate::mesh::{finite_normal, Mesh};
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Writes the mesh as Wavefront OBJ together with a `.mtl` material file next to it.
+/// Vertex colors use the common `v x y z r g b` extension understood by Blender and MeshLab.
+/// Normals and colors are left out if the mesh lacks them.
+pub fn write_obj(mesh: &Mesh, path: &Path) -> anyhow::Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("invalid material file name")?;
+
+    write_mtl(&mtl_path).with_context(|| format!("failed to write {}", mtl_path.display()))?;
+
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    write_obj_data(mesh, mtl_name, &mut w)
+        .and_then(|_| w.flush())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn write_obj_data<W: Write>(mesh: &Mesh, mtl_name: &str, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "# exported by Immersion's Control Engine")?;
+    writeln!(
+        w,
+        "# {} vertices, {} triangles",
+        mesh.positions.len(),
+        mesh.triangle_count()
+    )?;
+    writeln!(w, "mtllib {}", mtl_name)?;
+    writeln!(w, "o surface")?;
+
+    for (i, p) in mesh.positions.iter().enumerate() {
+        if mesh.has_colors() {
+            let c = mesh.colors[i];
+            writeln!(w, "v {} {} {} {} {} {}", p[0], p[1], p[2], c[0], c[1], c[2])?;
+        } else {
+            writeln!(w, "v {} {} {}", p[0], p[1], p[2])?;
+        }
+    }
+    if mesh.has_normals() {
+        for n in &mesh.normals {
+            let n = finite_normal(*n);
+            writeln!(w, "vn {} {} {}", n[0], n[1], n[2])?;
+        }
+    }
+
+    // OBJ indices start at 1; position and normal share the same index
+    writeln!(w, "usemtl surface")?;
+    writeln!(w, "s 1")?;
+    for t in mesh.indices.chunks_exact(3) {
+        let (a, b, c) = (t[0] + 1, t[1] + 1, t[2] + 1);
+        if mesh.has_normals() {
+            writeln!(w, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        } else {
+            writeln!(w, "f {} {} {}", a, b, c)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_mtl(path: &Path) -> std::io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    writeln!(w, "# exported by Immersion's Control Engine")?;
+    // white diffuse so viewers multiply it with the vertex colors
+    writeln!(w, "newmtl surface")?;
+    writeln!(w, "Ka 0.1 0.1 0.1")?;
+    writeln!(w, "Kd 1 1 1")?;
+    writeln!(w, "Ks 0.4 0.4 0.4")?;
+    writeln!(w, "Ns 30")?;
+    writeln!(w, "d 1")?;
+    writeln!(w, "illum 2")?;
+    w.flush()
+}
+
+/// Reads the triangles of a Wavefront OBJ file. Polygons are split into triangle fans and
+/// `v x y z r g b` vertex colors are kept. Normals are only returned if every face references one.
+pub(crate) fn read_obj(path: &Path) -> anyhow::Result<Mesh> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    parse_obj(&text).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn parse_obj(text: &str) -> anyhow::Result<Mesh> {
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut normals = Vec::new();
+    // OBJ indexes positions and normals separately, the mesh shares one index for both
+    let mut corners: Vec<(usize, Option<usize>)> = Vec::new();
+    let mut vertex_index: HashMap<(usize, Option<usize>), u32> = HashMap::new();
+    let mut mesh = Mesh::default();
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line_no = line_no + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let values = parse_floats(tokens).with_context(|| format!("line {}", line_no))?;
+                match values.len() {
+                    3 | 4 => positions.push([values[0], values[1], values[2]]),
+                    6 => {
+                        positions.push([values[0], values[1], values[2]]);
+                        colors.push([values[3], values[4], values[5]]);
+                    }
+                    n => {
+                        anyhow::bail!("line {}: expected 3 or 6 vertex values, got {}", line_no, n)
+                    }
+                }
+            }
+            Some("vn") => {
+                let values = parse_floats(tokens).with_context(|| format!("line {}", line_no))?;
+                if values.len() != 3 {
+                    anyhow::bail!(
+                        "line {}: expected 3 normal values, got {}",
+                        line_no,
+                        values.len()
+                    );
+                }
+                normals.push([values[0], values[1], values[2]]);
+            }
+            Some("f") => {
+                let mut face = Vec::new();
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let p = resolve_index(parts.next(), positions.len())
+                        .with_context(|| format!("line {}: invalid vertex '{}'", line_no, token))?
+                        .with_context(|| format!("line {}: vertex without position", line_no))?;
+                    let _texcoord = parts.next();
+                    let n = resolve_index(parts.next(), normals.len())
+                        .with_context(|| format!("line {}: invalid vertex '{}'", line_no, token))?;
+                    let index = *vertex_index.entry((p, n)).or_insert_with(|| {
+                        corners.push((p, n));
+                        corners.len() as u32 - 1
+                    });
+                    face.push(index);
+                }
+                if face.len() < 3 {
+                    anyhow::bail!("line {}: a face needs at least 3 vertices", line_no);
+                }
+                for i in 1..face.len() - 1 {
+                    mesh.indices.extend([face[0], face[i], face[i + 1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let has_colors = !colors.is_empty() && colors.len() == positions.len();
+    let has_normals = corners.iter().all(|(_, n)| n.is_some());
+    for &(p, n) in &corners {
+        mesh.positions.push(positions[p]);
+        if has_colors {
+            mesh.colors.push(colors[p]);
+        }
+        if let (true, Some(n)) = (has_normals, n) {
+            mesh.normals.push(normals[n]);
+        }
+    }
+    Ok(mesh)
+}
+
+fn parse_floats<'a>(tokens: impl Iterator<Item = &'a str>) -> anyhow::Result<Vec<f32>> {
+    tokens
+        .map(|t| {
+            t.parse::<f32>()
+                .with_context(|| format!("invalid number '{}'", t))
+        })
+        .collect()
+}
+
+/// Turns a 1-based or negative (relative to the end) OBJ index into a 0-based one.
+/// Returns `None` for an empty index, e.g. the texture coordinate in `1//1`.
+fn resolve_index(token: Option<&str>, len: usize) -> anyhow::Result<Option<usize>> {
+    let token = match token {
+        Some(t) if !t.is_empty() => t,
+        _ => return Ok(None),
+    };
+    let index: i64 = token.parse()?;
+    let resolved = if index < 0 {
+        len as i64 + index
+    } else {
+        index - 1
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        anyhow::bail!("index {} out of range, {} defined so far", index, len);
+    }
+    Ok(Some(resolved as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::tests::{tetrahedron, triangles};
+
+    #[test]
+    fn round_trip() {
+        let mesh = tetrahedron();
+        let mut data = Vec::new();
+        write_obj_data(&mesh, "surface.mtl", &mut data).unwrap();
+        let read = parse_obj(std::str::from_utf8(&data).unwrap()).unwrap();
+        // the reader numbers the vertices in the order the faces use them
+        assert_eq!(triangles(&read), triangles(&mesh));
+        assert_eq!(read.positions.len(), 4);
+        for (p, (n, c)) in read
+            .positions
+            .iter()
+            .zip(read.normals.iter().zip(&read.colors))
+        {
+            let i = mesh.positions.iter().position(|q| q == p).unwrap();
+            assert_eq!((*n, *c), (mesh.normals[i], mesh.colors[i]));
+        }
+    }
+
+    #[test]
+    fn missing_normals_and_colors_are_left_out() {
+        let mut mesh = tetrahedron();
+        mesh.normals.clear();
+        mesh.colors.clear();
+        let mut data = Vec::new();
+        write_obj_data(&mesh, "surface.mtl", &mut data).unwrap();
+        let text = String::from_utf8(data).unwrap();
+        assert!(text.contains("\nv 0 0 0\n"), "{}", text);
+        assert!(text.contains("\nf 1 3 2\n"), "{}", text);
+        assert!(!text.contains("vn "));
+        let read = parse_obj(&text).unwrap();
+        assert_eq!(triangles(&read), triangles(&mesh));
+        assert!(read.normals.is_empty() && read.colors.is_empty());
+    }
+
+    #[test]
+    fn negative_indices_and_polygons() {
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf -4 -3 -2 -1\nv 0 0 1\nf 1 2 -1\n";
+        let read = parse_obj(text).unwrap();
+        assert_eq!(read.indices, vec![0, 1, 2, 0, 2, 3, 0, 1, 4]);
+        assert_eq!(read.positions[4], [0.0, 0.0, 1.0]);
+        assert!(read.normals.is_empty() && read.colors.is_empty());
+    }
+
+    #[test]
+    fn normals_only_when_every_face_has_them() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n";
+        assert_eq!(parse_obj(text).unwrap().normals, vec![[0.0, 0.0, 1.0]; 3]);
+        let mixed = format!("{}f 1 2 3\n", text);
+        assert!(parse_obj(&mixed).unwrap().normals.is_empty());
+    }
+
+    #[test]
+    fn bad_faces() {
+        let err = |text: &str| format!("{:#}", parse_obj(text).unwrap_err());
+        let vertices = "v 0 0 0\nv 1 0 0\nv 0 1 0\n";
+        assert_eq!(
+            err(&format!("{}f 1 2 4\n", vertices)),
+            "line 4: invalid vertex '4': index 4 out of range, 3 defined so far"
+        );
+        assert_eq!(
+            err(&format!("{}f 0 1 2\n", vertices)),
+            "line 4: invalid vertex '0': index 0 out of range, 3 defined so far"
+        );
+        assert_eq!(
+            err(&format!("{}f -4 1 2\n", vertices)),
+            "line 4: invalid vertex '-4': index -4 out of range, 3 defined so far"
+        );
+        assert_eq!(
+            err(&format!("{}f 1 2\n", vertices)),
+            "line 4: a face needs at least 3 vertices"
+        );
+        assert_eq!(
+            err(&format!("{}f 1//1 2 3\n", vertices)),
+            "line 4: invalid vertex '1//1': index 1 out of range, 0 defined so far"
+        );
+        assert_eq!(
+            err("v 0 0\n"),
+            "line 1: expected 3 or 6 vertex values, got 2"
+        );
+        assert!(err("v 0 0 x\n").starts_with("line 1: invalid number 'x'"));
+    }
+}
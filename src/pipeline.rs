@@ -1,45 +1,523 @@
 #![allow(dead_code)]
 
 use bytemuck::{cast_slice, Pod, Zeroable};
-use cgmath::{ortho, perspective, Matrix4, Point3, Rad, Vector3};
-use std::{f32::consts::PI, mem, sync::Arc};
+use cgmath::{ortho, perspective, InnerSpace, Matrix4, Point3, Rad, Vector3};
+use std::{f32::consts::PI, mem, num::NonZeroU64, sync::Arc};
 use wgpu::{self, util::DeviceExt, *};
 
-#[path = "math_func.rs"]
-mod math_func;
-#[path = "./surface_data.rs"]
-mod surface_data;
+use crate::{colormap::Colormap, mesh::Mesh, scalar::ScalarOverlay, surface_data};
 
 const ANIMATION_SPEED: f32 = 1.0;
 const IS_PERSPECTIVE: bool = true;
 
+/// Size of the light array in the fragment shader.
+pub const MAX_LIGHTS: usize = 8;
+
+/// Sizes of the band color and contour arrays in the fragment shader.
+pub const MAX_BANDS: usize = 32;
+pub const MAX_CONTOURS: usize = 32;
+
+pub const LIGHT_DIRECTIONAL: i32 = 0;
+pub const LIGHT_POINT: i32 = 1;
+pub const LIGHT_SPOT: i32 = 2;
+
+/// One entry of the light array. Colors are premultiplied by the light intensity.
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct Light {
-    specular_color: [f32; 4],
+    pub position: [f32; 4],
+    /// Direction the light travels in, for directional and spot lights.
+    pub direction: [f32; 4],
+    pub color: [f32; 4],
+    pub specular_color: [f32; 4],
+    /// Constant, linear and quadratic distance attenuation.
+    pub attenuation: [f32; 4],
+    pub kind: i32,
+    /// Cosines of the spot cone angles; full intensity inside the inner cone, none outside the outer.
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    pub _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct LightUniforms {
+    pub lights: [Light; MAX_LIGHTS],
+    pub num_lights: i32,
+    /// Index of the light casting shadows, -1 for none.
+    pub shadow_light: i32,
+    _padding: [i32; 2],
+}
+
+/// Packs up to `MAX_LIGHTS` lights; any further lights are dropped.
+pub fn light_uniforms(lights: &[Light], shadow_light: Option<usize>) -> LightUniforms {
+    let mut uniforms = LightUniforms::zeroed();
+    let n = lights.len().min(MAX_LIGHTS);
+    uniforms.lights[..n].copy_from_slice(&lights[..n]);
+    uniforms.num_lights = n as i32;
+    uniforms.shadow_light = match shadow_light {
+        Some(i) if i < n => i as i32,
+        _ => -1,
+    };
+    uniforms
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ShadowUniforms {
+    pub light_view_project_mat: [[f32; 4]; 4],
+    /// Subtracted from the depth of a point before it is compared with the shadow map.
+    pub depth_bias: f32,
+    /// Size of one shadow map texel in texture coordinates, the step of the PCF kernel.
+    pub texel_size: f32,
+    _padding: [f32; 2],
+}
+
+pub fn shadow_uniforms(
+    light_view_project_mat: Matrix4<f32>,
+    depth_bias: f32,
+    resolution: u32,
+) -> ShadowUniforms {
+    ShadowUniforms {
+        light_view_project_mat: light_view_project_mat.into(),
+        depth_bias,
+        texel_size: 1.0 / resolution as f32,
+        _padding: [0.0; 2],
+    }
+}
+
+/// View-projection matrix of the shadow map for `light`, fitted around the bounding sphere
+/// of the shadow casters. The depth range reaches to the far side of the `receivers` sphere,
+/// so shadows falling further away, e.g. on the ground, are not cut off.
+/// Directional lights use an orthographic projection; point lights look at the
+/// scene and spot lights along their axis, both with a perspective projection.
+pub fn shadow_view_projection(
+    light: &Light,
+    center: Point3<f32>,
+    radius: f32,
+    receivers: (Point3<f32>, f32),
+) -> Matrix4<f32> {
+    let radius = radius.max(1e-3);
+    let position = Point3::new(light.position[0], light.position[1], light.position[2]);
+    let direction = Vector3::new(light.direction[0], light.direction[1], light.direction[2]);
+    let (eye, target, project_mat) = if light.kind == LIGHT_DIRECTIONAL {
+        let eye = center - direction * 2.0 * radius;
+        let reach = (receivers.0 - eye).dot(direction.normalize()) + receivers.1;
+        let far = reach.max(3.1 * radius);
+        let project_mat =
+            create_projection_ortho(-radius, radius, -radius, radius, 0.9 * radius, far);
+        (eye, center, project_mat)
+    } else {
+        let distance = (center - position).magnitude();
+        let (target, fovy) = if light.kind == LIGHT_SPOT || distance < 1e-4 {
+            (position + direction, 2.0 * light.cos_outer.acos())
+        } else if distance > radius {
+            (center, 2.0 * (radius / distance).asin())
+        } else {
+            // the light is inside the scene, so only part of it can be covered
+            (center, 120f32.to_radians())
+        };
+        let fovy = fovy.clamp(1f32.to_radians(), 150f32.to_radians());
+        let near = (distance - radius).max(0.01 * radius);
+        let far = (distance + radius).max((receivers.0 - position).magnitude() + receivers.1);
+        let project_mat = create_perspective_projection(Rad(fovy), 1.0, near, far);
+        (position, target, project_mat)
+    };
+    let forward = (target - eye).normalize();
+    let up = if forward.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    };
+    project_mat * Matrix4::look_at_rh(eye, target, up)
+}
+
+/// Depth texture rendered from the shadow casting light, with the comparison sampler and
+/// the uniforms used to look it up.
+pub struct ShadowMap {
+    pub view: TextureView,
+    pub sampler: Sampler,
+    pub uniform_buffer: Buffer,
+    pub resolution: u32,
+}
+
+pub fn create_shadow_map(device: &Device, resolution: u32) -> ShadowMap {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        label: Some("Shadow Map"),
+    });
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Shadow Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+        ..Default::default()
+    });
+    let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Shadow Uniform Buffer"),
+        size: mem::size_of::<ShadowUniforms>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    ShadowMap {
+        view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+        sampler,
+        uniform_buffer,
+        resolution,
+    }
+}
+
+/// Creates the depth-only pipeline rendering the objects into the shadow map,
+/// together with the bind group (group 0) holding the light's view-projection matrix.
+pub fn get_shadow_pipeline(
+    device: &Device,
+    shader: &ShaderModule,
+    object_bind_group_layout: &BindGroupLayout,
+    shadow_map: &ShadowMap,
+) -> (RenderPipeline, BindGroup) {
+    let shadow_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("Shadow Bind Group Layout"),
+        });
+    let shadow_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &shadow_bind_group_layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 3,
+            resource: shadow_map.uniform_buffer.as_entire_binding(),
+        }],
+        label: Some("Shadow Bind Group"),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Shadow Pipeline Layout"),
+        bind_group_layouts: &[&shadow_bind_group_layout, object_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_shadow",
+            buffers: &[Vertex::desc()],
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            // slope-scaled bias against shadow acne on surfaces seen at grazing angles
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+    (pipeline, shadow_bind_group)
+}
+
+/// Reflection coefficients of an object, for either the Phong or the PBR model.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct Material {
     ambient_intensity: f32,
     diffuse_intensity: f32,
     specular_intensity: f32,
     specular_shininess: f32,
     is_two_side: i32,
+    is_pbr: i32,
+    metallic: f32,
+    roughness: f32,
+    texture_repeat: [f32; 2],
+    is_textured: i32,
+    opacity: f32,
 }
 
-pub fn light(
-    sc: [f32; 3],
+pub fn material(
     ambient: f32,
     diffuse: f32,
     specular: f32,
     shininess: f32,
     two_side: i32,
-) -> Light {
-    Light {
-        specular_color: [sc[0], sc[1], sc[2], 1.0],
+) -> Material {
+    Material {
         ambient_intensity: ambient,
         diffuse_intensity: diffuse,
         specular_intensity: specular,
         specular_shininess: shininess,
         is_two_side: two_side,
+        is_pbr: 0,
+        metallic: 0.0,
+        roughness: 1.0,
+        texture_repeat: [1.0, 1.0],
+        is_textured: 0,
+        opacity: 1.0,
+    }
+}
+
+impl Material {
+    /// Switches to the metallic/roughness model. Only the ambient intensity and the two-side flag
+    /// are kept from the Phong coefficients.
+    pub fn pbr(self, metallic: f32, roughness: f32) -> Material {
+        Material {
+            is_pbr: 1,
+            metallic,
+            roughness,
+            ..self
+        }
+    }
+
+    /// Takes the color from the object's texture instead of the vertex colors,
+    /// repeating the texture `repeat` times along u and v.
+    pub fn textured(self, repeat: [f32; 2]) -> Material {
+        Material {
+            is_textured: 1,
+            texture_repeat: repeat,
+            ..self
+        }
+    }
+
+    /// Multiplies the opacity of the vertex colors and texture by `opacity`.
+    pub fn translucent(self, opacity: f32) -> Material {
+        Material { opacity, ..self }
+    }
+
+    /// Whether the object has to go through the transparency pass whatever its colors.
+    pub fn is_translucent(&self) -> bool {
+        self.opacity < 1.0
+    }
+}
+
+/// Per-object uniforms, stored for all objects in one buffer and selected with a dynamic offset.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ObjectUniforms {
+    pub model_mat: [[f32; 4]; 4],
+    pub normal_mat: [[f32; 4]; 4],
+    pub material: Material,
+    pub overlay: OverlayUniforms,
+}
+
+pub fn object_uniforms(
+    model_mat: Matrix4<f32>,
+    normal_mat: Matrix4<f32>,
+    material: Material,
+    overlay: OverlayUniforms,
+) -> ObjectUniforms {
+    ObjectUniforms {
+        model_mat: model_mat.into(),
+        normal_mat: normal_mat.into(),
+        material,
+        overlay,
+    }
+}
+
+/// Bands and contour lines of an object, drawn from the colormap position of its vertices.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct OverlayUniforms {
+    band_colors: [[f32; 4]; MAX_BANDS],
+    /// Four contour positions per entry.
+    contour_positions: [[f32; 4]; MAX_CONTOURS / 4],
+    contour_color: [f32; 4],
+    num_bands: i32,
+    num_contours: i32,
+    contour_width: f32,
+    /// Whether positions outside [0, 1] keep the vertex color.
+    out_of_range: i32,
+}
+
+/// Packs up to `MAX_BANDS` bands and `MAX_CONTOURS` contour lines; any further ones are dropped.
+pub fn overlay_uniforms(overlay: &ScalarOverlay) -> OverlayUniforms {
+    let mut uniforms = OverlayUniforms::zeroed();
+    let bands = overlay.band_colors.len().min(MAX_BANDS);
+    for (i, [r, g, b]) in overlay.band_colors[..bands].iter().enumerate() {
+        uniforms.band_colors[i] = [*r, *g, *b, 1.0];
+    }
+    let contours = overlay.contour_positions.len().min(MAX_CONTOURS);
+    for (i, position) in overlay.contour_positions[..contours].iter().enumerate() {
+        uniforms.contour_positions[i / 4][i % 4] = *position;
+    }
+    let [r, g, b] = overlay.contour_color;
+    uniforms.contour_color = [r, g, b, 1.0];
+    uniforms.num_bands = bands as i32;
+    uniforms.num_contours = contours as i32;
+    uniforms.contour_width = overlay.contour_width;
+    uniforms.out_of_range = overlay.out_of_range as i32;
+    uniforms
+}
+
+/// GPU buffers of one scene object together with its placement, material and visibility.
+pub struct RenderObject {
+    pub name: String,
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    pub num_indices: u32,
+    /// Distance of the farthest vertex from the object origin, for fitting the shadow map.
+    pub radius: f32,
+    /// Whether the object takes part in the auto rotation.
+    pub animated: bool,
+    /// Whether the object is drawn into the shadow map, which is fitted around the casters.
+    pub casts_shadow: bool,
+    /// Unindexed triangles with barycentric coordinates, only present when the wireframe
+    /// is drawn by the barycentric fallback.
+    pub wire_vertex_buffer: Option<Buffer>,
+    /// Texture of the object; `None` binds the default texture, which the material ignores.
+    pub texture_bind_group: Option<BindGroup>,
+    /// Drawn in the transparency pass instead of the opaque one, because of the material opacity
+    /// or partly transparent vertex colors or texture.
+    pub translucent: bool,
+    pub transform: Matrix4<f32>,
+    pub material: Material,
+    pub overlay: OverlayUniforms,
+    pub visible: bool,
+}
+
+pub fn create_render_object(
+    device: &Device,
+    mesh: &Mesh,
+    name: &str,
+    transform: Matrix4<f32>,
+    material: Material,
+    visible: bool,
+    wireframe: WireframeKind,
+) -> RenderObject {
+    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Index Buffer"),
+        contents: cast_slice(&mesh.indices),
+        usage: wgpu::BufferUsages::INDEX,
+    });
+    let vertex_data = mesh.vertices();
+
+    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Vertex Buffer"),
+        contents: cast_slice(&vertex_data),
+        usage: wgpu::BufferUsages::VERTEX,
+    });
+    let wire_vertex_buffer = match wireframe {
+        WireframeKind::PolygonLine => None,
+        WireframeKind::Barycentric => Some(device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Wire Vertex Buffer"),
+                contents: cast_slice(&wire_vertices(mesh)),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        )),
+    };
+    RenderObject {
+        name: name.to_string(),
+        vertex_buffer,
+        index_buffer,
+        num_indices: mesh.indices.len() as u32,
+        radius: mesh
+            .positions
+            .iter()
+            .map(|p| Vector3::from(*p).magnitude())
+            .fold(0.0, f32::max),
+        animated: true,
+        casts_shadow: true,
+        wire_vertex_buffer,
+        texture_bind_group: None,
+        translucent: material.is_translucent() || mesh.is_translucent(),
+        transform,
+        material,
+        overlay: overlay_uniforms(&mesh.overlay),
+        visible,
+    }
+}
+
+/// Sphere enclosing the objects in their current placement. The auto rotation turns objects
+/// around their origin, so the sphere holds for any rotation angle.
+pub fn bounding_sphere<'a>(
+    objects: impl IntoIterator<Item = &'a RenderObject>,
+) -> (Point3<f32>, f32) {
+    let mut bounds: Option<(Point3<f32>, f32)> = None;
+    for object in objects {
+        let m = object.transform;
+        let center = Point3::new(m.w.x, m.w.y, m.w.z);
+        let scale = [m.x, m.y, m.z]
+            .iter()
+            .map(|c| c.truncate().magnitude())
+            .fold(0.0, f32::max);
+        let radius = object.radius * scale;
+        bounds = Some(match bounds {
+            None => (center, radius),
+            Some((c, r)) => {
+                let d = (center - c).magnitude();
+                if d + radius <= r {
+                    (c, r)
+                } else if d + r <= radius {
+                    (center, radius)
+                } else {
+                    let new_radius = 0.5 * (d + r + radius);
+                    (c + (center - c) * ((new_radius - r) / d), new_radius)
+                }
+            }
+        });
     }
+    bounds.unwrap_or((Point3::new(0.0, 0.0, 0.0), 1.0))
+}
+
+/// Creates the buffer holding the uniforms of `count` objects and its bind group.
+/// Returns the buffer, the bind group and the stride between two objects.
+pub fn create_object_uniforms(
+    device: &Device,
+    layout: &BindGroupLayout,
+    count: usize,
+) -> (Buffer, BindGroup, u64) {
+    // dynamic offsets have to be multiples of the device alignment
+    let size = mem::size_of::<ObjectUniforms>() as u64;
+    let align = device.limits().min_uniform_buffer_offset_alignment as u64;
+    let stride = size.div_ceil(align) * align;
+    let object_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Object Uniform Buffer"),
+        size: stride * count.max(1) as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let object_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &object_uniform_buffer,
+                offset: 0,
+                size: NonZeroU64::new(size),
+            }),
+        }],
+        label: Some("Object Bind Group"),
+    });
+    (object_uniform_buffer, object_bind_group, stride)
 }
 
 #[repr(C)]
@@ -48,6 +526,9 @@ pub struct Vertex {
     pub position: [f32; 4],
     pub normal: [f32; 4],
     pub color: [f32; 4],
+    pub uv: [f32; 2],
+    /// Position on the colormap, read by the overlay.
+    pub scalar: f32,
 }
 
 #[allow(dead_code)]
@@ -56,6 +537,67 @@ pub fn vertex(p: [f32; 3], n: [f32; 3], c: [f32; 3]) -> Vertex {
         position: [p[0], p[1], p[2], 1.0],
         normal: [n[0], n[1], n[2], 1.0],
         color: [c[0], c[1], c[2], 1.0],
+        uv: [0.0, 0.0],
+        scalar: 0.0,
+    }
+}
+
+/// Vertex of the barycentric wireframe. Every triangle gets its own three vertices,
+/// whose barycentric coordinates tell the fragment shader how close it is to an edge.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct WireVertex {
+    pub position: [f32; 4],
+    pub barycentric: [f32; 4],
+}
+
+impl WireVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0=>Float32x4, 1=>Float32x4];
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<WireVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+fn wire_vertices(mesh: &Mesh) -> Vec<WireVertex> {
+    const CORNERS: [[f32; 4]; 3] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ];
+    mesh.indices
+        .chunks_exact(3)
+        .flat_map(|triangle| {
+            triangle.iter().zip(CORNERS).map(|(&i, barycentric)| {
+                let [x, y, z] = mesh.positions[i as usize];
+                WireVertex {
+                    position: [x, y, z, 1.0],
+                    barycentric,
+                }
+            })
+        })
+        .collect()
+}
+
+/// How the wireframe is rasterized. `PolygonLine` needs the `POLYGON_MODE_LINE` feature,
+/// which many backends lack; the barycentric fallback works everywhere.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WireframeKind {
+    PolygonLine,
+    Barycentric,
+}
+
+impl WireframeKind {
+    pub fn for_device(device: &Device) -> Self {
+        if device.features().contains(Features::POLYGON_MODE_LINE) {
+            WireframeKind::PolygonLine
+        } else {
+            WireframeKind::Barycentric
+        }
     }
 }
 
@@ -69,8 +611,9 @@ pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
 );
 
 impl Vertex {
-    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
-        wgpu::vertex_attr_array![0=>Float32x4, 1=>Float32x4, 2=>Float32x4];
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        0=>Float32x4, 1=>Float32x4, 2=>Float32x4, 3=>Float32x2, 4=>Float32
+    ];
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -80,44 +623,37 @@ impl Vertex {
     }
 }
 
+/// Creates the render pipeline and the uniforms shared by all objects.
+/// Returns the pipeline, the frame bind group (group 0) with its vertex, fragment and light buffers,
+/// the layout of the per-object bind group (group 1) and the pipeline layout.
+/// Textures are bound in group 2 with `texture_bind_group_layout`.
 pub fn get_render_pipeline(
     device: Arc<Device>,
     shader: Arc<ShaderModule>,
-    queue: Arc<Queue>,
     config: &SurfaceConfiguration,
-    light_data: Light,
+    sample_count: u32,
+    shadow_map: &ShadowMap,
+    texture_bind_group_layout: &BindGroupLayout,
 ) -> (
     Arc<RenderPipeline>,
-    Buffer,
     BindGroup,
     Buffer,
-    Matrix4<f32>,
-    Matrix4<f32>,
-    u32,
     Buffer,
+    Buffer,
+    BindGroupLayout,
+    PipelineLayout,
 ) {
-    let camera_position = (3.0, 1.5, 3.0).into();
-    let look_direction = (0.0, 0.0, 0.0).into();
-    let up_direction = cgmath::Vector3::unit_y();
-
-    let _model_mat = create_transforms([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
-    let (view_mat, project_mat, _) = create_view_projection(
-        camera_position,
-        look_direction,
-        up_direction,
-        config.width as f32 / config.height as f32,
-        IS_PERSPECTIVE,
-    );
     // create vertex uniform buffer
-    // model_mat and view_projection_mat will be stored in vertex_uniform_buffer inside the update function
+    // view_projection_mat will be stored in vertex_uniform_buffer inside the update function
     let vertex_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Vertex Uniform Buffer"),
-        size: 192,
+        size: 64,
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         mapped_at_creation: false,
     });
 
-    // create fragment uniform buffer. here we set eye_position = camera_position and light_position = eye_position
+    // create fragment uniform buffer. eye_position follows the camera and the wireframe color
+    // can change at runtime, so both are written inside the update function as well
     let fragment_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Fragment Uniform Buffer"),
         size: 32,
@@ -125,35 +661,15 @@ pub fn get_render_pipeline(
         mapped_at_creation: false,
     });
 
-    // store light and eye positions
-    let light_position: &[f32; 3] = camera_position.as_ref();
-    let eye_position: &[f32; 3] = camera_position.as_ref();
-    queue.write_buffer(
-        &fragment_uniform_buffer,
-        0,
-        bytemuck::cast_slice(light_position),
-    );
-    queue.write_buffer(
-        &fragment_uniform_buffer,
-        16,
-        bytemuck::cast_slice(eye_position),
-    );
-
-    // create light uniform buffer
+    // create light uniform buffer. lights can change at runtime and headlights follow the camera,
+    // so the whole array is written inside the update function
     let light_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
         label: Some("Light Uniform Buffer"),
-        size: 48,
+        size: mem::size_of::<LightUniforms>() as u64,
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         mapped_at_creation: false,
     });
 
-    // store light parameters
-    queue.write_buffer(
-        &light_uniform_buffer,
-        0,
-        bytemuck::cast_slice(&[light_data]),
-    );
-
     let uniform_bind_group_layout =
         device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
@@ -187,6 +703,32 @@ pub fn get_render_pipeline(
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
             ],
             label: Some("Uniform Bind Group Layout"),
         });
@@ -206,16 +748,46 @@ pub fn get_render_pipeline(
                 binding: 2,
                 resource: light_uniform_buffer.as_entire_binding(),
             },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: shadow_map.uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+            },
         ],
         label: Some("Uniform Bind Group"),
     });
 
+    let object_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: NonZeroU64::new(mem::size_of::<ObjectUniforms>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("Object Bind Group Layout"),
+        });
+
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
-        bind_group_layouts: &[&uniform_bind_group_layout],
+        bind_group_layouts: &[
+            &uniform_bind_group_layout,
+            &object_bind_group_layout,
+            texture_bind_group_layout,
+        ],
         push_constant_ranges: &[],
     });
-
     let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Render Pipeline"),
         layout: Some(&pipeline_layout),
@@ -248,78 +820,467 @@ pub fn get_render_pipeline(
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         }),
-        multisample: wgpu::MultisampleState::default(),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
         multiview: None,
     });
-    let mut function_selection = 0;
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() > 1 {
-        function_selection = args[1].parse().unwrap();
-    }
+    (
+        Arc::new(pipeline),
+        uniform_bind_group,
+        vertex_uniform_buffer,
+        fragment_uniform_buffer,
+        light_uniform_buffer,
+        object_bind_group_layout,
+        pipeline_layout,
+    )
+}
 
-    let ps_struct: surface_data::ParametricSurface;
-
-    if function_selection == 1 {
-        ps_struct = surface_data::ParametricSurface {
-            f: math_func::klein_bottle,
-            umin: 0.0,
-            umax: PI,
-            vmin: 0.0,
-            vmax: 2.0 * PI,
-            u_segments: 120,
-            v_segments: 40,
-            scale: 1.0,
-            ..Default::default()
-        };
-    } else if function_selection == 2 {
-        ps_struct = surface_data::ParametricSurface {
-            f: math_func::wellenkugel,
-            umin: 0.0,
-            umax: 14.5,
-            vmin: 0.0,
-            vmax: 5.0,
-            u_segments: 100,
-            v_segments: 50,
-            scale: 0.17,
-            colormap_name: "cool",
+/// Creates the pipeline drawing the triangle edges of the objects, either as lines or with the
+/// barycentric fallback. The edges are pulled towards the camera, so they stay visible on top
+/// of the shaded surface in the hidden-line mode.
+pub fn get_wireframe_pipeline(
+    device: &Device,
+    shader: &ShaderModule,
+    config: &SurfaceConfiguration,
+    layout: &PipelineLayout,
+    kind: WireframeKind,
+    sample_count: u32,
+) -> RenderPipeline {
+    let (vertex_entry, fragment_entry, buffers, polygon_mode) = match kind {
+        WireframeKind::PolygonLine => ("vs_wire", "fs_wire", [Vertex::desc()], PolygonMode::Line),
+        WireframeKind::Barycentric => (
+            "vs_wire_barycentric",
+            "fs_wire_barycentric",
+            [WireVertex::desc()],
+            PolygonMode::Fill,
+        ),
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Wireframe Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vertex_entry,
+            buffers: &buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fragment_entry,
+            targets: &[wgpu::ColorTargetState {
+                format: config.format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            polygon_mode,
             ..Default::default()
-        };
-    } else {
-        ps_struct = surface_data::ParametricSurface {
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth24Plus,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState {
+                constant: -2,
+                slope_scale: -1.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
             ..Default::default()
+        },
+        multiview: None,
+    })
+}
+
+/// Creates the depth buffer and, for more than one sample, the multisampled color target
+/// that is resolved into the output texture. Both have to be recreated when the size changes.
+pub fn create_render_targets(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+) -> (Option<TextureView>, TextureView) {
+    let size = wgpu::Extent3d {
+        width: config.width,
+        height: config.height,
+        depth_or_array_layers: 1,
+    };
+    let msaa_view = (sample_count > 1).then(|| {
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                label: Some("Multisampled Color Texture"),
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    });
+    let depth_view = device
+        .create_texture(&wgpu::TextureDescriptor {
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth24Plus,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            label: Some("Depth Texture"),
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default());
+    (msaa_view, depth_view)
+}
+
+/// Format of the weighted sum of premultiplied colors in the transparency pass.
+pub const OIT_ACCUM_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Format of the revealage, the product of `1 - alpha` of all translucent fragments of a pixel.
+pub const OIT_REVEALAGE_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// Targets of the weighted blended order-independent transparency pass. With multisampling the
+/// pass draws into `msaa_views`, which are resolved into the textures the composite pass reads.
+pub struct OitTargets {
+    pub accum_view: TextureView,
+    pub revealage_view: TextureView,
+    pub msaa_views: Option<(TextureView, TextureView)>,
+    /// Binds the resolved targets for the composite pass.
+    pub bind_group: BindGroup,
+}
+
+/// Creates the transparency targets; like the other render targets they have to be recreated
+/// when the size changes.
+pub fn create_oit_targets(
+    device: &Device,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+    composite_layout: &BindGroupLayout,
+) -> OitTargets {
+    let create_view = |format: TextureFormat, sample_count: u32, label: &str| {
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
         };
+        device
+            .create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: config.width,
+                    height: config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                label: Some(label),
+            })
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    };
+    let accum_view = create_view(OIT_ACCUM_FORMAT, 1, "OIT Accumulation Texture");
+    let revealage_view = create_view(OIT_REVEALAGE_FORMAT, 1, "OIT Revealage Texture");
+    let msaa_views = (sample_count > 1).then(|| {
+        (
+            create_view(
+                OIT_ACCUM_FORMAT,
+                sample_count,
+                "Multisampled OIT Accumulation Texture",
+            ),
+            create_view(
+                OIT_REVEALAGE_FORMAT,
+                sample_count,
+                "Multisampled OIT Revealage Texture",
+            ),
+        )
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: composite_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&accum_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::TextureView(&revealage_view),
+            },
+        ],
+        label: Some("OIT Composite Bind Group"),
+    });
+    OitTargets {
+        accum_view,
+        revealage_view,
+        msaa_views,
+        bind_group,
     }
+}
 
-    let (pos_data, normal_data, color_data, index_data) =
-        surface_data::ParametricSurface::new(ps_struct);
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Index Buffer"),
-        contents: cast_slice(&index_data),
-        usage: wgpu::BufferUsages::INDEX,
+/// Creates the pipelines of the weighted blended order-independent transparency.
+/// The accumulation pipeline draws the translucent objects with `layout`, depth tested against
+/// the opaque objects but without writing depth, adding up weighted colors and multiplying the
+/// revealage. The composite pipeline blends the averaged color over the frame.
+/// Returns both pipelines and the layout of the composite bind group.
+pub fn get_oit_pipelines(
+    device: &Device,
+    shader: &ShaderModule,
+    config: &SurfaceConfiguration,
+    layout: &PipelineLayout,
+    sample_count: u32,
+) -> (RenderPipeline, RenderPipeline, BindGroupLayout) {
+    let multisample = wgpu::MultisampleState {
+        count: sample_count,
+        ..Default::default()
+    };
+    let add = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let multiply = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::Zero,
+        dst_factor: wgpu::BlendFactor::OneMinusSrc,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let accum_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("OIT Accumulation Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_accum",
+            targets: &[
+                wgpu::ColorTargetState {
+                    format: OIT_ACCUM_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: add,
+                        alpha: add,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                },
+                wgpu::ColorTargetState {
+                    format: OIT_REVEALAGE_FORMAT,
+                    blend: Some(wgpu::BlendState {
+                        color: multiply,
+                        alpha: multiply,
+                    }),
+                    write_mask: wgpu::ColorWrites::RED,
+                },
+            ],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: wgpu::TextureFormat::Depth24Plus,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample,
+        multiview: None,
+    });
+
+    let composite_bind_group_layout =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[6, 7].map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }),
+            label: Some("OIT Composite Bind Group Layout"),
+        });
+    let composite_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("OIT Composite Pipeline Layout"),
+        bind_group_layouts: &[&composite_bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let composite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("OIT Composite Pipeline"),
+        layout: Some(&composite_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_composite",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_composite",
+            targets: &[wgpu::ColorTargetState {
+                format: config.format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample,
+        multiview: None,
     });
-    let mut vertex_data: Vec<Vertex> = Vec::with_capacity(pos_data.len());
-    for i in 0..pos_data.len() {
-        vertex_data.push(vertex(pos_data[i], normal_data[i], color_data[i]));
+    (
+        accum_pipeline,
+        composite_pipeline,
+        composite_bind_group_layout,
+    )
+}
+
+/// Vertex of a screen-space image: the position in normalized device coordinates and the
+/// texel of the image it shows, in pixels.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct ScreenVertex {
+    pub position: [f32; 2],
+    pub texel: [f32; 2],
+}
+
+impl ScreenVertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+        wgpu::vertex_attr_array![0=>Float32x2, 1=>Float32x2];
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<ScreenVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
     }
-    vertex_data.to_vec();
+}
+
+/// Image drawn over the frame pixel for pixel, e.g. the colorbar.
+pub struct ScreenImage {
+    pub vertex_buffer: Buffer,
+    pub bind_group: BindGroup,
+}
 
+/// Uploads `image` and places its top left corner at `origin` in a target of `target_size`
+/// pixels.
+pub fn create_screen_image(
+    device: &Device,
+    queue: &Queue,
+    layout: &BindGroupLayout,
+    image: &image::RgbaImage,
+    origin: (u32, u32),
+    target_size: (u32, u32),
+) -> ScreenImage {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            label: Some("Screen Image Texture"),
+        },
+        image.as_raw(),
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 8,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+        label: Some("Screen Image Bind Group"),
+    });
+
+    let (x0, y0) = (origin.0 as f32, origin.1 as f32);
+    let (x1, y1) = (x0 + image.width() as f32, y0 + image.height() as f32);
+    let (width, height) = (target_size.0 as f32, target_size.1 as f32);
+    let corner = |x: f32, y: f32| ScreenVertex {
+        position: [2.0 * x / width - 1.0, 1.0 - 2.0 * y / height],
+        texel: [x - x0, y - y0],
+    };
+    let vertices = [
+        corner(x0, y0),
+        corner(x0, y1),
+        corner(x1, y1),
+        corner(x1, y1),
+        corner(x1, y0),
+        corner(x0, y0),
+    ];
     let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: cast_slice(&vertex_data),
+        label: Some("Screen Image Vertex Buffer"),
+        contents: cast_slice(&vertices),
         usage: wgpu::BufferUsages::VERTEX,
     });
-    let num_vertices = index_data.len() as u32;
-
-    (
-        Arc::new(pipeline),
+    ScreenImage {
         vertex_buffer,
-        uniform_bind_group,
-        vertex_uniform_buffer,
-        view_mat,
-        project_mat,
-        num_vertices,
-        index_buffer,
-    )
+        bind_group,
+    }
+}
+
+/// Creates the pipeline that blends screen images over the frame, without depth test.
+/// Returns the pipeline and the layout of the image bind group.
+pub fn get_screen_image_pipeline(
+    device: &Device,
+    shader: &ShaderModule,
+    config: &SurfaceConfiguration,
+    sample_count: u32,
+) -> (RenderPipeline, BindGroupLayout) {
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 8,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+        label: Some("Screen Image Bind Group Layout"),
+    });
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Screen Image Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Screen Image Pipeline"),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_screen",
+            buffers: &[ScreenVertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_screen",
+            targets: &[wgpu::ColorTargetState {
+                format: config.format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    });
+    (pipeline, bind_group_layout)
 }
 
 /*fn create_vertices() -> Vec<Vertex> {
@@ -357,7 +1318,7 @@ fn create_vertices(r_torus: f32, r_tube: f32, n_torus: usize, n_tube: usize) ->
 // This functions is used to get vertices and get the colors of the sinc surface too.
 pub fn create_vertices(
     f: &dyn Fn(f32, f32) -> [f32; 3],
-    colormap_name: &str,
+    colormap: &Colormap,
     xmin: f32,
     xmax: f32,
     zmin: f32,
@@ -371,7 +1332,7 @@ pub fn create_vertices(
         surface_data::simple_surface_points(f, xmin, xmax, zmin, zmax, nx, nz, scale, aspect);
     let pos = surface_data::simple_surface_positions(&pts, nx, nz);
     let normal = surface_data::simple_surface_normals(&pts, nx, nz);
-    let color = surface_data::simple_surface_colors(&pts, nx, nz, yrange, colormap_name);
+    let color = surface_data::simple_surface_colors(&pts, nx, nz, yrange, colormap);
     let mut data: Vec<Vertex> = Vec::with_capacity(pos.len());
     for i in 0..pos.len() {
         data.push(vertex(pos[i], normal[i], color[i]));
@@ -484,3 +1445,12 @@ pub fn create_transforms(
     // return final model matrix
     model_mat
 }
+
+/// Model transform of the auto-rotating surface after it turned by `angle`.
+pub fn create_animation_transform(angle: f32) -> Matrix4<f32> {
+    create_transforms(
+        [0.0, 0.0, 0.0],
+        [angle.sin(), angle.cos(), 0.0],
+        [1.0, 1.0, 1.0],
+    )
+}
@@ -0,0 +1,392 @@
+use crate::mesh::{finite_normal, Mesh};
+use anyhow::Context;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Writes the mesh as binary little-endian PLY with positions, normals and 8-bit vertex colors,
+/// the layout ParaView and MeshLab read directly. Normals and colors are left out if the mesh
+/// lacks them.
+pub fn write_ply(mesh: &Mesh, path: &Path) -> anyhow::Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    write_ply_data(mesh, &mut w)
+        .and_then(|_| w.flush())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn write_ply_data<W: Write>(mesh: &Mesh, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "ply")?;
+    writeln!(w, "format binary_little_endian 1.0")?;
+    writeln!(w, "comment exported by Immersion's Control Engine")?;
+    writeln!(w, "element vertex {}", mesh.positions.len())?;
+    let (normals, colors) = (mesh.has_normals(), mesh.has_colors());
+    for name in ["x", "y", "z"] {
+        writeln!(w, "property float {}", name)?;
+    }
+    if normals {
+        for name in ["nx", "ny", "nz"] {
+            writeln!(w, "property float {}", name)?;
+        }
+    }
+    if colors {
+        for name in ["red", "green", "blue"] {
+            writeln!(w, "property uchar {}", name)?;
+        }
+    }
+    writeln!(w, "element face {}", mesh.triangle_count())?;
+    writeln!(w, "property list uchar uint vertex_indices")?;
+    writeln!(w, "end_header")?;
+
+    for i in 0..mesh.positions.len() {
+        for value in mesh.positions[i] {
+            w.write_all(&value.to_le_bytes())?;
+        }
+        if normals {
+            for value in finite_normal(mesh.normals[i]) {
+                w.write_all(&value.to_le_bytes())?;
+            }
+        }
+        if colors {
+            let rgb = mesh.colors[i].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            w.write_all(&rgb)?;
+        }
+    }
+    for t in mesh.indices.chunks_exact(3) {
+        w.write_all(&[3])?;
+        for index in t {
+            w.write_all(&index.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads the vertices and faces of an ASCII or binary PLY file. Normals (`nx`, `ny`, `nz`) and
+/// colors (`red`, `green`, `blue`) are kept when present, polygons are split into triangle fans
+/// and any other element is skipped.
+pub(crate) fn read_ply(path: &Path) -> anyhow::Result<Mesh> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_ply(&data).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ScalarType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl ScalarType {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::I8,
+            "uchar" | "uint8" => ScalarType::U8,
+            "short" | "int16" => ScalarType::I16,
+            "ushort" | "uint16" => ScalarType::U16,
+            "int" | "int32" => ScalarType::I32,
+            "uint" | "uint32" => ScalarType::U32,
+            "float" | "float32" => ScalarType::F32,
+            "double" | "float64" => ScalarType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, ScalarType::F32 | ScalarType::F64)
+    }
+}
+
+enum Property {
+    Scalar(String, ScalarType),
+    List(String, ScalarType, ScalarType),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Body of the file after `end_header`, read one value at a time.
+enum Body<'a> {
+    Ascii(std::str::SplitWhitespace<'a>),
+    Binary { data: &'a [u8], big_endian: bool },
+}
+
+impl Body<'_> {
+    fn read(&mut self, ty: ScalarType) -> anyhow::Result<f64> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().context("unexpected end of file")?;
+                token
+                    .parse()
+                    .with_context(|| format!("invalid number '{}'", token))
+            }
+            Body::Binary { data, big_endian } => {
+                if data.len() < ty.size() {
+                    anyhow::bail!("unexpected end of file");
+                }
+                let (bytes, rest) = data.split_at(ty.size());
+                *data = rest;
+                let mut buf = [0u8; 8];
+                buf[..bytes.len()].copy_from_slice(bytes);
+                if *big_endian {
+                    buf[..bytes.len()].reverse();
+                }
+                let [b0, b1, b2, b3, ..] = buf;
+                Ok(match ty {
+                    ScalarType::I8 => b0 as i8 as f64,
+                    ScalarType::U8 => b0 as f64,
+                    ScalarType::I16 => i16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::U16 => u16::from_le_bytes([b0, b1]) as f64,
+                    ScalarType::I32 => i32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::U32 => u32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::F32 => f32::from_le_bytes([b0, b1, b2, b3]) as f64,
+                    ScalarType::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+fn parse_ply(data: &[u8]) -> anyhow::Result<Mesh> {
+    let header_end = data
+        .windows(10)
+        .position(|w| w == b"end_header")
+        .context("missing end_header")?;
+    let body_start = data[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|i| header_end + i + 1)
+        .unwrap_or(data.len());
+    let header = std::str::from_utf8(&data[..header_end]).context("header is not text")?;
+
+    let mut lines = header.lines().map(str::trim);
+    if lines.next() != Some("ply") {
+        anyhow::bail!("not a PLY file");
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", f, _] => format = Some(f.to_string()),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .with_context(|| format!("invalid element count '{}'", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let element = elements.last_mut().context("property before element")?;
+                element.properties.push(Property::List(
+                    name.to_string(),
+                    scalar_type(count_ty)?,
+                    scalar_type(item_ty)?,
+                ));
+            }
+            ["property", ty, name] => {
+                let element = elements.last_mut().context("property before element")?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), scalar_type(ty)?));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => anyhow::bail!("unexpected header line '{}'", line),
+        }
+    }
+    let mut body = match format.as_deref() {
+        Some("ascii") => Body::Ascii(
+            std::str::from_utf8(&data[body_start..])
+                .context("ASCII body is not text")?
+                .split_whitespace(),
+        ),
+        Some("binary_little_endian") => Body::Binary {
+            data: &data[body_start..],
+            big_endian: false,
+        },
+        Some("binary_big_endian") => Body::Binary {
+            data: &data[body_start..],
+            big_endian: true,
+        },
+        Some(f) => anyhow::bail!("unsupported format '{}'", f),
+        None => anyhow::bail!("missing format line"),
+    };
+
+    let mut mesh = Mesh::default();
+    let mut faces: Vec<Vec<u32>> = Vec::new();
+    for element in &elements {
+        let find = |name: &str| {
+            element.properties.iter().position(|p| match p {
+                Property::Scalar(n, _) => n == name,
+                Property::List(..) => false,
+            })
+        };
+        let position = [find("x"), find("y"), find("z")];
+        let normal = [find("nx"), find("ny"), find("nz")];
+        let color = [find("red"), find("green"), find("blue")];
+        let is_vertex = element.name == "vertex";
+        if is_vertex && position.iter().any(Option::is_none) {
+            anyhow::bail!("vertex element needs x, y and z properties");
+        }
+        let has_normal = normal.iter().all(Option::is_some);
+        let has_color = color.iter().all(Option::is_some);
+
+        let mut values = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            let mut face = Vec::new();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property {
+                    Property::Scalar(_, ty) => {
+                        values[i] = body.read(*ty)?;
+                        // integer colors are 8-bit
+                        if !ty.is_float() && color.contains(&Some(i)) {
+                            values[i] /= 255.0;
+                        }
+                    }
+                    Property::List(name, count_ty, item_ty) => {
+                        let count = body.read(*count_ty)? as usize;
+                        let is_indices = element.name == "face"
+                            && (name == "vertex_indices" || name == "vertex_index");
+                        for _ in 0..count {
+                            let value = body.read(*item_ty)?;
+                            if is_indices {
+                                face.push(value as u32);
+                            }
+                        }
+                    }
+                }
+            }
+            if is_vertex {
+                let get = |k: [Option<usize>; 3]| k.map(|i| values[i.unwrap()] as f32);
+                mesh.positions.push(get(position));
+                if has_normal {
+                    mesh.normals.push(get(normal));
+                }
+                if has_color {
+                    mesh.colors.push(get(color));
+                }
+            } else if element.name == "face" {
+                faces.push(face);
+            }
+        }
+    }
+
+    for face in faces {
+        if face.len() < 3 {
+            anyhow::bail!("a face needs at least 3 vertices, got {}", face.len());
+        }
+        for i in 1..face.len() - 1 {
+            mesh.indices.extend([face[0], face[i], face[i + 1]]);
+        }
+    }
+    Ok(mesh)
+}
+
+fn scalar_type(name: &str) -> anyhow::Result<ScalarType> {
+    ScalarType::from_name(name).with_context(|| format!("unknown property type '{}'", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::tests::tetrahedron;
+
+    #[test]
+    fn binary_round_trip() {
+        let mesh = tetrahedron();
+        let mut data = Vec::new();
+        write_ply_data(&mesh, &mut data).unwrap();
+        let read = parse_ply(&data).unwrap();
+        assert_eq!(read.positions, mesh.positions);
+        assert_eq!(read.normals, mesh.normals);
+        assert_eq!(read.colors, mesh.colors);
+        assert_eq!(read.indices, mesh.indices);
+    }
+
+    #[test]
+    fn missing_normals_and_colors_are_left_out() {
+        let mut mesh = tetrahedron();
+        mesh.normals.clear();
+        let mut data = Vec::new();
+        write_ply_data(&mesh, &mut data).unwrap();
+        let read = parse_ply(&data).unwrap();
+        assert_eq!(read.positions, mesh.positions);
+        assert!(read.normals.is_empty());
+        assert_eq!(read.colors, mesh.colors);
+
+        mesh.colors.clear();
+        let mut data = Vec::new();
+        write_ply_data(&mesh, &mut data).unwrap();
+        let read = parse_ply(&data).unwrap();
+        assert_eq!(read.positions, mesh.positions);
+        assert!(read.colors.is_empty());
+        assert_eq!(read.indices, mesh.indices);
+    }
+
+    #[test]
+    fn ascii_polygons_become_fans() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\n\
+                    property float y\nproperty float z\nelement face 1\n\
+                    property list uchar int vertex_indices\nend_header\n\
+                    0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n";
+        let read = parse_ply(text.as_bytes()).unwrap();
+        assert_eq!(read.positions.len(), 4);
+        assert!(read.normals.is_empty() && read.colors.is_empty());
+        assert_eq!(read.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn malformed() {
+        let mut data = Vec::new();
+        write_ply_data(&tetrahedron(), &mut data).unwrap();
+        data.truncate(data.len() - 5);
+        assert_eq!(
+            parse_ply(&data).unwrap_err().to_string(),
+            "unexpected end of file"
+        );
+
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                      property float y\nproperty float z\nelement face 1\n\
+                      property list uchar int vertex_indices\nend_header\n\
+                      0 0 0\n1 0 0\n0 1 0\n";
+        let two_corners = format!("{}2 0 1\n", header);
+        assert_eq!(
+            parse_ply(two_corners.as_bytes()).unwrap_err().to_string(),
+            "a face needs at least 3 vertices, got 2"
+        );
+        assert!(parse_ply(b"ply\nformat ascii 1.0\nelement vertex 1\n").is_err());
+        assert!(parse_ply(b"obj\nend_header\n").is_err());
+
+        // indices past the vertices are caught when the mesh is loaded
+        let path = std::env::temp_dir().join(format!("ice-bad-index-{}.ply", std::process::id()));
+        std::fs::write(&path, format!("{}3 0 1 7\n", header)).unwrap();
+        let err = Mesh::load(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            format!("{:#}", err),
+            format!(
+                "invalid mesh {}: vertex index 7 out of range, the mesh has 3 vertices",
+                path.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    colormap::Colormap,
+    math_func,
+    scene::Geometry,
+    surface_data::{HeightField, ParametricSurface, Seam},
+};
+use std::{f32::consts::PI, sync::Arc};
+
+/// A named surface preset.
+pub struct SurfaceEntry {
+    pub name: String,
+    pub description: String,
+    pub surface: Geometry,
+}
+
+/// Maps surface names to parametric surface, height field or mesh presets.
+/// `SurfaceRegistry::default()` contains the built-in surfaces.
+pub struct SurfaceRegistry {
+    entries: Vec<SurfaceEntry>,
+}
+
+impl SurfaceRegistry {
+    /// Creates a registry without any surfaces.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds a preset, replacing any preset registered under the same name.
+    pub fn register(&mut self, name: &str, description: &str, surface: impl Into<Geometry>) {
+        let entry = SurfaceEntry {
+            name: name.to_string(),
+            description: description.to_string(),
+            surface: surface.into(),
+        };
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    pub fn entries(&self) -> &[SurfaceEntry] {
+        &self.entries
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    /// Returns a copy of the preset called `name`.
+    /// The position in the registry is accepted as well, so `0`, `1` and `2` keep selecting
+    /// the torus, the klein bottle and the wellenkugel.
+    pub fn get(&self, name: &str) -> anyhow::Result<Geometry> {
+        let entry = match name.parse::<usize>() {
+            Ok(index) => self.entries.get(index),
+            Err(_) => self.entries.iter().find(|e| e.name == name),
+        };
+        match entry {
+            Some(entry) => Ok(entry.surface.clone()),
+            None => anyhow::bail!(
+                "unknown surface '{}', expected one of: {}",
+                name,
+                self.names().join(", ")
+            ),
+        }
+    }
+}
+
+impl Default for SurfaceRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(
+            "torus",
+            "ring torus, params[0] is the ring radius and params[1] the tube radius",
+            ParametricSurface {
+                derivatives: Some(Arc::new(math_func::torus_derivatives)),
+                u_seam: Seam::Periodic,
+                v_seam: Seam::Periodic,
+                ..Default::default()
+            },
+        );
+        registry.register(
+            "klein_bottle",
+            "klein bottle immersed in its classic bottle shape",
+            ParametricSurface {
+                f: Arc::new(math_func::klein_bottle),
+                umin: 0.0,
+                umax: PI,
+                // centered on pi / 2, so the u seam, where v meets pi - v, is a plain flip
+                vmin: -0.5 * PI,
+                vmax: 1.5 * PI,
+                u_segments: 120,
+                v_segments: 40,
+                u_seam: Seam::Flipped,
+                v_seam: Seam::Periodic,
+                scale: 1.0,
+                ..Default::default()
+            },
+        );
+        registry.register(
+            "wellenkugel",
+            "wave sphere",
+            ParametricSurface {
+                f: Arc::new(math_func::wellenkugel),
+                umin: 0.0,
+                umax: 14.5,
+                vmin: 0.0,
+                vmax: 5.0,
+                u_segments: 100,
+                v_segments: 50,
+                scale: 0.17,
+                colormap: Colormap::builtin("cool").expect("built-in colormap"),
+                ..Default::default()
+            },
+        );
+        registry.register(
+            "peaks",
+            "height field y = peaks(x, z), a sum of translated gaussians",
+            HeightField {
+                ..Default::default()
+            },
+        );
+        registry.register(
+            "sinc",
+            "height field y = sin(r) / r with r = sqrt(x^2 + z^2)",
+            HeightField {
+                f: Arc::new(math_func::sinc),
+                xmin: -8.0,
+                xmax: 8.0,
+                zmin: -8.0,
+                zmax: 8.0,
+                x_segments: 64,
+                z_segments: 64,
+                ..Default::default()
+            },
+        );
+        registry.register(
+            "sphere",
+            "sphere, params[0] is the radius",
+            ParametricSurface {
+                f: Arc::new(math_func::sphere),
+                derivatives: Some(Arc::new(math_func::sphere_derivatives)),
+                umin: 0.0,
+                umax: PI,
+                vmin: 0.0,
+                vmax: 2.0 * PI,
+                u_segments: 32,
+                v_segments: 48,
+                v_seam: Seam::Periodic,
+                params: [1.0, 0.0, 0.0, 0.0, 0.0],
+                ..Default::default()
+            },
+        );
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::SurfaceDesc;
+
+    fn error<T>(result: anyhow::Result<T>) -> String {
+        match result {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    fn parametric(geometry: Geometry) -> ParametricSurface {
+        match geometry {
+            Geometry::Parametric(ps) => ps,
+            _ => panic!("expected a parametric surface"),
+        }
+    }
+
+    #[test]
+    fn lookup_by_name() {
+        let registry = SurfaceRegistry::default();
+        let sphere = parametric(registry.get("sphere").unwrap());
+        assert_eq!(sphere.params[0], 1.0);
+        assert!(matches!(
+            registry.get("sinc").unwrap(),
+            Geometry::HeightField(_)
+        ));
+        assert_eq!(
+            registry.names()[..3],
+            ["torus", "klein_bottle", "wellenkugel"]
+        );
+    }
+
+    #[test]
+    fn legacy_indices() {
+        let registry = SurfaceRegistry::default();
+        for (index, name) in ["0", "1", "2"]
+            .iter()
+            .zip(["torus", "klein_bottle", "wellenkugel"])
+        {
+            let by_index = parametric(registry.get(index).unwrap());
+            let by_name = parametric(registry.get(name).unwrap());
+            assert_eq!(
+                (
+                    by_index.umax,
+                    by_index.vmax,
+                    by_index.u_segments,
+                    by_index.scale
+                ),
+                (
+                    by_name.umax,
+                    by_name.vmax,
+                    by_name.u_segments,
+                    by_name.scale
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_surfaces_list_the_names() {
+        let registry = SurfaceRegistry::default();
+        let names = registry.names().join(", ");
+        for name in ["tours", "99", ""] {
+            assert_eq!(
+                error(registry.get(name)),
+                format!("unknown surface '{}', expected one of: {}", name, names)
+            );
+        }
+    }
+
+    #[test]
+    fn register_replaces_by_name() {
+        let mut registry = SurfaceRegistry::new();
+        registry.register("a", "first", HeightField::default());
+        registry.register("a", "second", ParametricSurface::default());
+        assert_eq!(registry.entries().len(), 1);
+        assert_eq!(registry.entries()[0].description, "second");
+        assert!(matches!(
+            registry.get("0").unwrap(),
+            Geometry::Parametric(_)
+        ));
+    }
+
+    #[test]
+    fn overrides_are_applied() {
+        let registry = SurfaceRegistry::default();
+        let desc = SurfaceDesc {
+            umin: Some(0.5),
+            vmax: Some(3.0),
+            u_segments: Some(12),
+            v_segments: Some(7),
+            params: Some(vec![2.0, 0.25]),
+            ..SurfaceDesc::from_preset("torus")
+        };
+        let torus = parametric(desc.build(&registry).unwrap());
+        assert_eq!((torus.umin, torus.vmax), (0.5, 3.0));
+        assert_eq!((torus.u_segments, torus.v_segments), (12, 7));
+        assert_eq!(torus.params, [2.0, 0.25, 0.0, 0.0, 0.0]);
+
+        let desc = SurfaceDesc {
+            xmin: Some(-2.0),
+            z_segments: Some(5),
+            ..SurfaceDesc::from_preset("sinc")
+        };
+        match desc.build(&registry).unwrap() {
+            Geometry::HeightField(hf) => {
+                assert_eq!((hf.xmin, hf.xmax, hf.z_segments), (-2.0, 8.0, 5));
+            }
+            _ => panic!("expected a height field"),
+        }
+    }
+
+    #[test]
+    fn bad_overrides_are_rejected() {
+        let registry = SurfaceRegistry::default();
+        let desc = SurfaceDesc {
+            params: Some(vec![1.0; 6]),
+            ..SurfaceDesc::from_preset("torus")
+        };
+        assert_eq!(
+            error(desc.build(&registry)),
+            "at most 5 params are supported"
+        );
+        let desc = SurfaceDesc {
+            v_segments: Some(0),
+            ..SurfaceDesc::from_preset("sphere")
+        };
+        assert_eq!(
+            error(desc.build(&registry)),
+            "segment counts must be at least 1, got 32x0"
+        );
+        let desc = SurfaceDesc {
+            x_segments: Some(0),
+            ..SurfaceDesc::from_preset("peaks")
+        };
+        assert!(error(desc.build(&registry)).starts_with("segment counts must be at least 1"));
+        let desc = SurfaceDesc {
+            umin: Some(0.0),
+            ..SurfaceDesc::from_preset("peaks")
+        };
+        assert!(error(desc.build(&registry)).contains("umin"));
+    }
+}
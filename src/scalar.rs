@@ -0,0 +1,489 @@
+use crate::{
+    colormap::Colormap,
+    curvature::{self, Curvature, Curvatures},
+    expr,
+    pipeline::{MAX_BANDS, MAX_CONTOURS},
+};
+use anyhow::Context;
+use std::{fmt, fs, path::Path, sync::Arc};
+
+/// Scalar computed from a point of a surface, e.g. a compiled expression.
+pub type ScalarFn = Arc<dyn Fn(&ScalarPoint) -> f32 + Send + Sync>;
+
+/// What is known about a vertex when its scalar is computed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScalarPoint {
+    /// Surface parameters: `u` and `v` of a parametric surface, `x` and `z` of a height field,
+    /// the texture coordinates of a mesh.
+    pub u: f32,
+    pub v: f32,
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Only computed for parametric surfaces colored by curvature.
+    pub curvatures: Option<Curvatures>,
+}
+
+/// Quantity fed to the colormap.
+#[derive(Clone, Default)]
+pub enum ScalarSource {
+    X,
+    #[default]
+    Y,
+    Z,
+    U,
+    V,
+    /// Distance from the origin of the object.
+    Distance,
+    /// Angle in degrees between the normal and the y axis.
+    NormalAngle,
+    Curvature(Curvature),
+    /// Function of `u`, `v`, the position and the normal.
+    Function(ScalarFn),
+    /// One value per vertex, in the order of the tessellation.
+    Data(Arc<Vec<f32>>),
+}
+
+/// Names accepted by `ScalarSource::parse` besides expressions.
+pub const SCALAR_NAMES: [&str; 11] = [
+    "x",
+    "y",
+    "z",
+    "u",
+    "v",
+    "distance",
+    "normal_angle",
+    "gaussian_curvature",
+    "mean_curvature",
+    "max_curvature",
+    "min_curvature",
+];
+
+impl ScalarSource {
+    /// Parses one of the `SCALAR_NAMES`, or else compiles an expression of
+    /// `u`, `v`, `x`, `y`, `z`, `nx`, `ny` and `nz`, e.g. `"x*x + z*z"`.
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        Ok(match source {
+            "x" => ScalarSource::X,
+            "y" => ScalarSource::Y,
+            "z" => ScalarSource::Z,
+            "u" => ScalarSource::U,
+            "v" => ScalarSource::V,
+            "distance" => ScalarSource::Distance,
+            "normal_angle" => ScalarSource::NormalAngle,
+            "gaussian_curvature" => ScalarSource::Curvature(Curvature::Gaussian),
+            "mean_curvature" => ScalarSource::Curvature(Curvature::Mean),
+            "max_curvature" => ScalarSource::Curvature(Curvature::Max),
+            "min_curvature" => ScalarSource::Curvature(Curvature::Min),
+            _ => ScalarSource::Function(expr::compile_scalar(source).with_context(|| {
+                format!(
+                    "scalar '{}' is neither one of {} nor a valid expression",
+                    source,
+                    SCALAR_NAMES.join(", ")
+                )
+            })?),
+        })
+    }
+
+    /// Coordinate along `axis` (0 = x, 1 = y, 2 = z).
+    pub fn axis(axis: usize) -> Self {
+        match axis {
+            0 => ScalarSource::X,
+            2 => ScalarSource::Z,
+            _ => ScalarSource::Y,
+        }
+    }
+
+    /// Reads per-vertex values from a text file: numbers separated by whitespace or commas,
+    /// with `#` starting a comment.
+    pub fn load_data<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read scalar data {}", path.display()))?;
+        let mut values = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            for item in line.split(|c: char| c.is_whitespace() || c == ',') {
+                if item.is_empty() {
+                    continue;
+                }
+                let value = item.parse::<f32>().with_context(|| {
+                    format!("{}:{}: invalid number '{}'", path.display(), i + 1, item)
+                })?;
+                values.push(value);
+            }
+        }
+        Ok(ScalarSource::Data(Arc::new(values)))
+    }
+
+    /// Name of the quantity for legends, e.g. `"mean curvature"`.
+    pub fn label(&self) -> String {
+        match self {
+            ScalarSource::X => "x",
+            ScalarSource::Y => "y",
+            ScalarSource::Z => "z",
+            ScalarSource::U => "u",
+            ScalarSource::V => "v",
+            ScalarSource::Distance => "distance",
+            ScalarSource::NormalAngle => "normal angle",
+            ScalarSource::Curvature(Curvature::Gaussian) => "gaussian curvature",
+            ScalarSource::Curvature(Curvature::Mean) => "mean curvature",
+            ScalarSource::Curvature(Curvature::Max) => "max curvature",
+            ScalarSource::Curvature(Curvature::Min) => "min curvature",
+            ScalarSource::Function(_) => "scalar",
+            ScalarSource::Data(_) => "data",
+        }
+        .to_string()
+    }
+
+    pub fn is_curvature(&self) -> bool {
+        matches!(self, ScalarSource::Curvature(_))
+    }
+
+    /// Checks that a surface with `count` vertices, parametric or not, can provide the scalar.
+    pub fn check(&self, count: usize, parametric: bool) -> anyhow::Result<()> {
+        match self {
+            ScalarSource::Curvature(_) if !parametric => {
+                anyhow::bail!("curvature coloring needs a parametric surface")
+            }
+            ScalarSource::Data(values) if values.len() != count => anyhow::bail!(
+                "scalar data has {} values but the surface has {} vertices",
+                values.len(),
+                count
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Scalar of the vertex `index` at `point`; NaN where it is undefined.
+    pub fn value(&self, index: usize, point: &ScalarPoint) -> f32 {
+        let [x, y, z] = point.position;
+        match self {
+            ScalarSource::X => x,
+            ScalarSource::Y => y,
+            ScalarSource::Z => z,
+            ScalarSource::U => point.u,
+            ScalarSource::V => point.v,
+            ScalarSource::Distance => (x * x + y * y + z * z).sqrt(),
+            ScalarSource::NormalAngle => {
+                let [nx, ny, nz] = point.normal;
+                let length = (nx * nx + ny * ny + nz * nz).sqrt();
+                (ny / length).clamp(-1.0, 1.0).acos().to_degrees()
+            }
+            ScalarSource::Curvature(kind) => {
+                point.curvatures.map_or(f32::NAN, |c| c.get(*kind) as f32)
+            }
+            ScalarSource::Function(f) => f(point),
+            ScalarSource::Data(values) => values.get(index).copied().unwrap_or(f32::NAN),
+        }
+    }
+}
+
+impl fmt::Debug for ScalarSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScalarSource::X => write!(f, "X"),
+            ScalarSource::Y => write!(f, "Y"),
+            ScalarSource::Z => write!(f, "Z"),
+            ScalarSource::U => write!(f, "U"),
+            ScalarSource::V => write!(f, "V"),
+            ScalarSource::Distance => write!(f, "Distance"),
+            ScalarSource::NormalAngle => write!(f, "NormalAngle"),
+            ScalarSource::Curvature(kind) => write!(f, "Curvature({:?})", kind),
+            ScalarSource::Function(_) => write!(f, "Function"),
+            ScalarSource::Data(values) => write!(f, "Data({} values)", values.len()),
+        }
+    }
+}
+
+/// How scalars are mapped onto the colormap.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColorScale {
+    /// Scalars mapped to the low and high end of the colormap. By default the extent of the
+    /// values, leaving out the most extreme 2% on either side for curvatures.
+    pub range: Option<[f32; 2]>,
+    /// Spreads the colormap logarithmically; non-positive values fall below the range.
+    pub log: bool,
+    /// Color of values outside the range. By default they get the color of the nearest end.
+    pub out_of_range_color: Option<[f32; 3]>,
+    /// Splits the colormap into this many bands of uniform color, taken at their centers.
+    pub bands: Option<usize>,
+}
+
+impl ColorScale {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some([min, max]) = self.range {
+            if !(min.is_finite() && max.is_finite() && min < max) {
+                anyhow::bail!(
+                    "scalar range must be finite and increasing, got [{}, {}]",
+                    min,
+                    max
+                );
+            }
+            if self.log && min <= 0.0 {
+                anyhow::bail!("a log scale needs a positive range, got [{}, {}]", min, max);
+            }
+        }
+        if let Some(color) = self.out_of_range_color {
+            if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+                anyhow::bail!(
+                    "out of range color components must be within [0, 1], got {:?}",
+                    color
+                );
+            }
+        }
+        if let Some(bands) = self.bands {
+            if !(2..=MAX_BANDS).contains(&bands) {
+                anyhow::bail!("bands must be within [2, {}], got {}", MAX_BANDS, bands);
+            }
+        }
+        Ok(())
+    }
+
+    /// The explicit range, or the range of the finite `values` that the scale can show.
+    /// `robust` leaves out the extremes, see `curvature::robust_range`.
+    pub fn resolve_range(&self, values: &[f32], robust: bool) -> [f32; 2] {
+        if let Some(range) = self.range {
+            return range;
+        }
+        let shown: Vec<f32> = values
+            .iter()
+            .copied()
+            .filter(|v| v.is_finite() && (!self.log || *v > 0.0))
+            .collect();
+        let (min, max) = if robust {
+            curvature::robust_range(&shown)
+        } else {
+            shown.iter().fold((f32::MAX, f32::MIN), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            })
+        };
+        if shown.is_empty() {
+            [
+                if self.log { 1.0 } else { 0.0 },
+                if self.log { 10.0 } else { 1.0 },
+            ]
+        } else if max > min {
+            [min, max]
+        } else if self.log {
+            // constant values, shown in the middle of the colormap
+            [min * 0.5, max * 2.0]
+        } else {
+            [min - 0.5, max + 0.5]
+        }
+    }
+
+    /// Position of every value on the colormap: 0 and 1 at the ends of the range, outside
+    /// [0, 1] beyond them and NaN where the value is undefined.
+    pub fn normalize(&self, values: &[f32], robust: bool) -> Vec<f32> {
+        let range = self.resolve_range(values, robust);
+        values.iter().map(|&x| self.position(range, x)).collect()
+    }
+
+    /// Position of `x` on the colormap spread over `range`.
+    pub fn position(&self, [min, max]: [f32; 2], x: f32) -> f32 {
+        if self.log {
+            if x <= 0.0 {
+                f32::NEG_INFINITY
+            } else {
+                (x.log10() - min.log10()) / (max.log10() - min.log10())
+            }
+        } else {
+            (x - min) / (max - min)
+        }
+    }
+
+    /// Color at position `t` returned by `normalize`; NaN is not out of range but takes the
+    /// middle color.
+    pub fn color(&self, colormap: &Colormap, t: f32) -> [f32; 3] {
+        match (self.out_of_range_color, self.bands) {
+            (Some(color), _) if !t.is_nan() && !(0.0..=1.0).contains(&t) => color,
+            (_, Some(bands)) => colormap.color(band_center(clamp_position(t), bands)),
+            _ => colormap.color(clamp_position(t)),
+        }
+    }
+}
+
+/// Center of the band containing `t` out of `bands` equal bands over [0, 1].
+fn band_center(t: f32, bands: usize) -> f32 {
+    let band = ((t * bands as f32).floor() as usize).min(bands - 1);
+    (band as f32 + 0.5) / bands as f32
+}
+
+/// Lines drawn on the surface where the scalar crosses chosen levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contours {
+    /// Scalars of the lines.
+    pub levels: Vec<f32>,
+    /// Number of further lines spaced evenly over the colormap, between its ends.
+    pub count: usize,
+    pub color: [f32; 3],
+    /// Width of the lines in pixels.
+    pub width: f32,
+}
+
+impl Default for Contours {
+    fn default() -> Self {
+        Contours {
+            levels: Vec::new(),
+            count: 0,
+            color: [0.0, 0.0, 0.0],
+            width: 1.5,
+        }
+    }
+}
+
+impl Contours {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.levels.len() + self.count > MAX_CONTOURS {
+            anyhow::bail!(
+                "at most {} contour lines are supported, got {}",
+                MAX_CONTOURS,
+                self.levels.len() + self.count
+            );
+        }
+        if let Some(level) = self.levels.iter().find(|l| !l.is_finite()) {
+            anyhow::bail!("contour levels must be finite, got {}", level);
+        }
+        if self.color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+            anyhow::bail!(
+                "contour color components must be within [0, 1], got {:?}",
+                self.color
+            );
+        }
+        if !(self.width > 0.0 && self.width.is_finite()) {
+            anyhow::bail!("contour width must be positive, got {}", self.width);
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty() && self.count == 0
+    }
+
+    /// Positions of the lines on the colormap, for scalars spread over `range` by `scale`.
+    pub fn positions(&self, scale: &ColorScale, range: [f32; 2]) -> Vec<f32> {
+        let spaced = (1..=self.count).map(|i| i as f32 / (self.count + 1) as f32);
+        self.levels
+            .iter()
+            .map(|&level| scale.position(range, level))
+            .chain(spaced)
+            .collect()
+    }
+}
+
+/// What the fragment shader draws from the colormap positions of a mesh instead of
+/// interpolating the vertex colors: sharp bands and contour lines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScalarOverlay {
+    /// Color of every band, empty for smooth colors.
+    pub band_colors: Vec<[f32; 3]>,
+    /// Whether positions outside [0, 1] keep the vertex color, i.e. the out of range color,
+    /// instead of taking the color of the nearest band.
+    pub out_of_range: bool,
+    /// Positions of the contour lines on the colormap.
+    pub contour_positions: Vec<f32>,
+    pub contour_color: [f32; 3],
+    pub contour_width: f32,
+}
+
+impl ScalarOverlay {
+    /// Bands of `scale` colored from `colormap`, if `colored`, and `contours` for scalars
+    /// spread over `range`.
+    pub fn new(
+        colormap: &Colormap,
+        scale: &ColorScale,
+        contours: &Contours,
+        range: [f32; 2],
+        colored: bool,
+    ) -> Self {
+        let band_colors = match scale.bands {
+            Some(bands) if colored => (0..bands)
+                .map(|i| colormap.color((i as f32 + 0.5) / bands as f32))
+                .collect(),
+            _ => Vec::new(),
+        };
+        ScalarOverlay {
+            band_colors,
+            out_of_range: scale.out_of_range_color.is_some(),
+            contour_positions: contours.positions(scale, range),
+            contour_color: contours.color,
+            contour_width: contours.width,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.band_colors.is_empty() && self.contour_positions.is_empty()
+    }
+}
+
+/// `t` clamped to [0, 1], with undefined positions, e.g. curvatures at degenerate poles, in
+/// the neutral middle.
+pub fn clamp_position(t: f32) -> f32 {
+    if t.is_nan() {
+        0.5
+    } else {
+        t.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nan_takes_the_middle_color() {
+        let colormap = Colormap::builtin("greys").unwrap();
+        let middle = colormap.color(0.5);
+        let scale = ColorScale::default();
+        assert_eq!(scale.color(&colormap, f32::NAN), middle);
+        let scale = ColorScale {
+            out_of_range_color: Some([1.0, 0.0, 1.0]),
+            ..ColorScale::default()
+        };
+        assert_eq!(scale.color(&colormap, f32::NAN), middle);
+        assert_eq!(scale.color(&colormap, 1.5), [1.0, 0.0, 1.0]);
+        assert_eq!(clamp_position(f32::NAN), 0.5);
+    }
+
+    #[test]
+    fn band_centers() {
+        assert_eq!(band_center(0.0, 4), 0.125);
+        assert_eq!(band_center(0.3, 4), 0.375);
+        // the high end belongs to the last band rather than one past it
+        assert_eq!(band_center(1.0, 4), 0.875);
+        assert_eq!(band_center(0.5, 1), 0.5);
+        let colormap = Colormap::builtin("greys").unwrap();
+        let scale = ColorScale {
+            bands: Some(2),
+            ..ColorScale::default()
+        };
+        assert_eq!(scale.color(&colormap, 0.1), colormap.color(0.25));
+        assert_eq!(scale.color(&colormap, 7.0), colormap.color(0.75));
+    }
+
+    #[test]
+    fn contour_positions() {
+        let contours = Contours {
+            levels: vec![2.0, 4.0],
+            count: 3,
+            ..Contours::default()
+        };
+        let linear = ColorScale::default();
+        assert_eq!(
+            contours.positions(&linear, [0.0, 8.0]),
+            [0.25, 0.5, 0.25, 0.5, 0.75]
+        );
+        let log = ColorScale {
+            log: true,
+            ..ColorScale::default()
+        };
+        let contours = Contours {
+            levels: vec![10.0, 0.0],
+            ..Contours::default()
+        };
+        assert_eq!(
+            contours.positions(&log, [1.0, 100.0]),
+            [0.5, f32::NEG_INFINITY]
+        );
+    }
+}
@@ -0,0 +1,1480 @@
+use crate::{
+    colorbar::ColorKey,
+    colormap::{is_colormap_path, Colormap},
+    expr::{compile_surface, compile_surface_derivatives},
+    mesh::Mesh,
+    pipeline::{
+        create_perspective_projection, create_projection_ortho, create_transforms, material, Light,
+        Material, LIGHT_DIRECTIONAL, LIGHT_POINT, LIGHT_SPOT, MAX_LIGHTS,
+    },
+    registry::SurfaceRegistry,
+    scalar::{ColorScale, Contours, ScalarOverlay, ScalarSource},
+    surface_data::{check_alpha_ramp, HeightField, ParametricSurface, Seam},
+    texture::{is_builtin_texture, load_texture_image},
+};
+use anyhow::Context;
+use cgmath::{Deg, InnerSpace, Matrix4, SquareMatrix, Vector3};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Everything the engine needs to set up a view: objects, camera, lights, shadows, ground,
+/// background, animation and render settings.
+pub struct Scene {
+    pub objects: Vec<SceneObject>,
+    pub camera: CameraSettings,
+    pub lights: Vec<LightSettings>,
+    pub shadows: ShadowSettings,
+    pub ground: GroundSettings,
+    pub background: [f32; 3],
+    pub animation: AnimationSettings,
+    pub render: RenderSettings,
+    pub colorbar: ColorbarSettings,
+}
+
+impl Scene {
+    /// Creates a scene showing a single surface with the default camera, light and animation.
+    pub fn from_surface(surface: Geometry) -> Self {
+        let desc = SceneDesc::default();
+        Self {
+            objects: vec![SceneObject::new("surface", surface)],
+            camera: desc.camera,
+            lights: desc.lights,
+            shadows: desc.shadows,
+            ground: desc.ground,
+            background: desc.background,
+            animation: desc.animation,
+            render: desc.render,
+            colorbar: desc.colorbar,
+        }
+    }
+
+    /// Loads and validates a scene file. The format is picked from the extension:
+    /// `.toml`, `.ron` or `.json`.
+    pub fn load<P: AsRef<Path>>(path: P, registry: &SurfaceRegistry) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let desc = SceneDesc::load(path)?;
+        desc.into_scene(registry)
+            .with_context(|| format!("invalid scene {}", path.display()))
+    }
+}
+
+/// A surface placed in the scene. Objects nested in a scene file are flattened,
+/// so `transform` is the full model transform and `visible` includes the parents' visibility.
+#[derive(Clone)]
+pub struct SceneObject {
+    pub name: String,
+    pub geometry: Geometry,
+    pub transform: Matrix4<f32>,
+    pub material: MaterialSettings,
+    /// Image loaded from `material.texture`.
+    pub texture: Option<Arc<RgbaImage>>,
+    pub visible: bool,
+}
+
+impl SceneObject {
+    /// A visible object at the origin with the default material.
+    pub fn new(name: &str, geometry: Geometry) -> Self {
+        SceneObject {
+            name: name.to_string(),
+            geometry,
+            transform: Matrix4::identity(),
+            material: MaterialSettings::default(),
+            texture: None,
+            visible: true,
+        }
+    }
+
+    /// Tessellates the geometry for rendering. Textured surfaces keep their seams open,
+    /// since the texture coordinates jump across them.
+    pub fn mesh(&self) -> Mesh {
+        match &self.geometry {
+            Geometry::Parametric(ps) if self.material.texture.is_some() => {
+                Mesh::from_parametric(ParametricSurface {
+                    u_seam: Seam::Open,
+                    v_seam: Seam::Open,
+                    ..ps.clone()
+                })
+            }
+            geometry => geometry.to_mesh(),
+        }
+    }
+
+    /// Wraps the built-in texture or image file `source` around the object.
+    pub fn set_texture(&mut self, source: &str) -> anyhow::Result<()> {
+        self.material.texture = Some(source.to_string());
+        self.texture = self.material.load_texture()?;
+        Ok(())
+    }
+}
+
+/// Shape of a scene surface: a parametric surface or height field, tessellated when the scene
+/// is rendered, or a mesh loaded from a file.
+#[derive(Clone)]
+pub enum Geometry {
+    Parametric(ParametricSurface),
+    HeightField(HeightField),
+    Mesh(Mesh),
+}
+
+impl Geometry {
+    pub fn to_mesh(&self) -> Mesh {
+        match self {
+            Geometry::Parametric(ps) => Mesh::from_parametric(ps.clone()),
+            Geometry::HeightField(hf) => Mesh::from_height_field(hf),
+            Geometry::Mesh(mesh) => mesh.clone(),
+        }
+    }
+}
+
+impl From<ParametricSurface> for Geometry {
+    fn from(ps: ParametricSurface) -> Self {
+        Geometry::Parametric(ps)
+    }
+}
+
+impl From<HeightField> for Geometry {
+    fn from(hf: HeightField) -> Self {
+        Geometry::HeightField(hf)
+    }
+}
+
+impl From<Mesh> for Geometry {
+    fn from(mesh: Mesh) -> Self {
+        Geometry::Mesh(mesh)
+    }
+}
+
+/// Serialized form of a `Scene`. Surfaces refer to registry presets by name.
+/// Entries of `surfaces` are shorthands for objects at the origin with the default material.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SceneDesc {
+    pub surfaces: Vec<SurfaceDesc>,
+    pub objects: Vec<ObjectDesc>,
+    pub camera: CameraSettings,
+    pub lights: Vec<LightSettings>,
+    pub shadows: ShadowSettings,
+    pub ground: GroundSettings,
+    pub background: [f32; 3],
+    pub animation: AnimationSettings,
+    pub render: RenderSettings,
+    pub colorbar: ColorbarSettings,
+}
+
+impl Default for SceneDesc {
+    fn default() -> Self {
+        SceneDesc {
+            surfaces: Vec::new(),
+            objects: Vec::new(),
+            camera: CameraSettings::default(),
+            lights: vec![LightSettings::default()],
+            shadows: ShadowSettings::default(),
+            ground: GroundSettings::default(),
+            background: [0.2, 0.247, 0.314],
+            animation: AnimationSettings::default(),
+            render: RenderSettings::default(),
+            colorbar: ColorbarSettings::default(),
+        }
+    }
+}
+
+impl SceneDesc {
+    /// Parses a scene file without validating it.
+    /// Relative mesh paths are resolved against the directory of the scene file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read scene {}", path.display()))?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let desc = match extension.as_str() {
+            "toml" => toml::from_str(&text).map_err(anyhow::Error::from),
+            // optional fields can be written without wrapping them in Some(..)
+            "ron" => ron::Options::default()
+                .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+                .from_str(&text)
+                .map_err(anyhow::Error::from),
+            "json" => serde_json::from_str(&text).map_err(anyhow::Error::from),
+            _ => anyhow::bail!(
+                "unsupported scene format '{}' for {}, expected .toml, .ron or .json",
+                extension,
+                path.display()
+            ),
+        };
+        let mut desc: SceneDesc =
+            desc.with_context(|| format!("failed to parse scene {}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for surface in &mut desc.surfaces {
+            surface.resolve_paths(dir);
+        }
+        for object in &mut desc.objects {
+            object.resolve_paths(dir);
+        }
+        Ok(desc)
+    }
+
+    /// Resolves the surface presets, flattens the object tree and checks every setting.
+    pub fn into_scene(self, registry: &SurfaceRegistry) -> anyhow::Result<Scene> {
+        if self.surfaces.is_empty() && self.objects.is_empty() {
+            anyhow::bail!("the scene has no surfaces or objects");
+        }
+        if self.lights.len() > MAX_LIGHTS {
+            anyhow::bail!(
+                "at most {} lights are supported, the scene has {}",
+                MAX_LIGHTS,
+                self.lights.len()
+            );
+        }
+        let mut objects = Vec::new();
+        for (i, desc) in self.surfaces.iter().enumerate() {
+            let geometry = desc
+                .build(registry)
+                .with_context(|| format!("surfaces[{}]", i))?;
+            objects.push(SceneObject::new(&desc.label(), geometry));
+        }
+        for (i, desc) in self.objects.iter().enumerate() {
+            desc.flatten_into(registry, Matrix4::identity(), true, &mut objects)
+                .with_context(|| format!("objects[{}]", i))?;
+        }
+        self.camera.validate().context("camera")?;
+        for (i, light) in self.lights.iter().enumerate() {
+            light.validate().with_context(|| format!("lights[{}]", i))?;
+        }
+        self.shadows.validate().context("shadows")?;
+        self.ground.validate().context("ground")?;
+        check_color(self.background).context("background")?;
+        self.animation.validate().context("animation")?;
+        self.render.validate().context("render")?;
+        self.colorbar.validate().context("colorbar")?;
+        if let Some(name) = &self.colorbar.object {
+            if !objects.iter().any(|o| &o.name == name) {
+                anyhow::bail!("colorbar object '{}' is not in the scene", name);
+            }
+        }
+
+        Ok(Scene {
+            objects,
+            camera: self.camera,
+            lights: self.lights,
+            shadows: self.shadows,
+            ground: self.ground,
+            background: self.background,
+            animation: self.animation,
+            render: self.render,
+            colorbar: self.colorbar,
+        })
+    }
+}
+
+/// Serialized form of a `SceneObject`. An object without a surface groups its children,
+/// whose transforms are relative to the parent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectDesc {
+    /// Defaults to the preset or mesh file name.
+    pub name: String,
+    pub surface: Option<SurfaceDesc>,
+    pub transform: TransformSettings,
+    pub material: MaterialSettings,
+    /// Hidden objects hide their children as well.
+    pub visible: bool,
+    pub children: Vec<ObjectDesc>,
+}
+
+impl Default for ObjectDesc {
+    fn default() -> Self {
+        ObjectDesc {
+            name: String::new(),
+            surface: None,
+            transform: TransformSettings::default(),
+            material: MaterialSettings::default(),
+            visible: true,
+            children: Vec::new(),
+        }
+    }
+}
+
+impl ObjectDesc {
+    fn resolve_paths(&mut self, dir: &Path) {
+        if let Some(surface) = &mut self.surface {
+            surface.resolve_paths(dir);
+        }
+        self.material.resolve_texture_path(dir);
+        for child in &mut self.children {
+            child.resolve_paths(dir);
+        }
+    }
+
+    /// Builds this object and its descendants, appending them to `objects` in depth-first order.
+    fn flatten_into(
+        &self,
+        registry: &SurfaceRegistry,
+        parent_transform: Matrix4<f32>,
+        parent_visible: bool,
+        objects: &mut Vec<SceneObject>,
+    ) -> anyhow::Result<()> {
+        self.transform.validate().context("transform")?;
+        let transform = parent_transform * self.transform.matrix();
+        let visible = parent_visible && self.visible;
+        match &self.surface {
+            Some(surface) => {
+                self.material.validate().context("material")?;
+                let texture = self.material.load_texture().context("material")?;
+                let geometry = surface.build(registry).context("surface")?;
+                objects.push(SceneObject {
+                    name: if self.name.is_empty() {
+                        surface.label()
+                    } else {
+                        self.name.clone()
+                    },
+                    geometry,
+                    transform,
+                    material: self.material.clone(),
+                    texture,
+                    visible,
+                });
+            }
+            None if self.children.is_empty() => {
+                anyhow::bail!("an object needs a surface or children")
+            }
+            None => {}
+        }
+        for (i, child) in self.children.iter().enumerate() {
+            child
+                .flatten_into(registry, transform, visible, objects)
+                .with_context(|| format!("children[{}]", i))?;
+        }
+        Ok(())
+    }
+}
+
+/// A registry preset, x, y and z expressions of `u`, `v` and named parameters, or a mesh file,
+/// together with the settings that override it. The `u`/`v` settings and `params` apply to
+/// parametric surfaces, the `x`/`z` domain and segments to height fields.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurfaceDesc {
+    pub preset: String,
+    pub x: Option<String>,
+    pub y: Option<String>,
+    pub z: Option<String>,
+    /// Names the entries of `params` inside the expressions, e.g. `["R", "r"]`.
+    /// Every name needs a value in `params`.
+    pub param_names: Option<Vec<String>>,
+    /// `.obj`, `.stl` or `.ply` file to show instead of a parametric surface.
+    pub mesh: Option<PathBuf>,
+    pub umin: Option<f32>,
+    pub umax: Option<f32>,
+    pub vmin: Option<f32>,
+    pub vmax: Option<f32>,
+    pub u_segments: Option<usize>,
+    pub v_segments: Option<usize>,
+    /// How the surface closes up along u and v, see `Seam`.
+    pub u_seam: Option<Seam>,
+    pub v_seam: Option<Seam>,
+    pub xmin: Option<f32>,
+    pub xmax: Option<f32>,
+    pub zmin: Option<f32>,
+    pub zmax: Option<f32>,
+    pub x_segments: Option<usize>,
+    pub z_segments: Option<usize>,
+    pub scale: Option<f32>,
+    pub aspect: Option<f32>,
+    /// One of `colormap::COLORMAP_NAMES`, or a `.csv` or `.json` file, see `Colormap::load`.
+    pub colormap: Option<String>,
+    /// Runs the colormap from its high end to its low end.
+    pub reverse_colormap: Option<bool>,
+    /// Axis the colormap follows, shorthand for `scalar = "x"`, `"y"` or `"z"`.
+    pub colormap_direction: Option<String>,
+    /// Quantity the colormap follows: one of `scalar::SCALAR_NAMES`, e.g. `"distance"` or
+    /// `"gaussian_curvature"`, or an expression of `u`, `v`, `x`, `y`, `z`, `nx`, `ny` and `nz`.
+    pub scalar: Option<String>,
+    /// Text file with one scalar per vertex, used instead of `scalar`.
+    pub scalar_data: Option<PathBuf>,
+    /// Scalars at the ends of the colormap, by default the extent of the values.
+    pub scalar_range: Option<[f32; 2]>,
+    /// Spreads the colormap logarithmically over the scalars.
+    pub log_scale: Option<bool>,
+    /// Color of scalars outside `scalar_range`, instead of the color of the nearest end.
+    pub out_of_range_color: Option<[f32; 3]>,
+    /// Number of discrete bands the colormap is split into, at most `MAX_BANDS`.
+    pub bands: Option<usize>,
+    /// Scalars at which contour lines are drawn.
+    pub contours: Option<Vec<f32>>,
+    /// Number of contour lines spaced evenly over the colormap, besides `contours`.
+    pub contour_count: Option<usize>,
+    pub contour_color: Option<[f32; 3]>,
+    /// Width of the contour lines in pixels.
+    pub contour_width: Option<f32>,
+    /// Single color used instead of the colormap.
+    pub color: Option<[f32; 3]>,
+    /// Opacity at the low and high end of the colormap, interpolated linearly in between,
+    /// e.g. `[0.2, 1.0]` to let the low end fade out. It follows the colormap direction even
+    /// for a single color.
+    pub alpha_ramp: Option<[f32; 2]>,
+    /// Leading surface parameters, at most five.
+    pub params: Option<Vec<f32>>,
+}
+
+impl SurfaceDesc {
+    pub fn from_preset(preset: &str) -> Self {
+        SurfaceDesc {
+            preset: preset.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Short name for the surface: the preset, the mesh file name or `expression`.
+    pub fn label(&self) -> String {
+        if let Some(stem) = self.mesh.as_ref().and_then(|m| m.file_stem()) {
+            stem.to_string_lossy().into_owned()
+        } else if !self.preset.is_empty() {
+            self.preset.clone()
+        } else {
+            "expression".to_string()
+        }
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        for path in [&mut self.mesh, &mut self.scalar_data]
+            .into_iter()
+            .flatten()
+        {
+            if path.is_relative() {
+                *path = dir.join(&*path);
+            }
+        }
+        if let Some(colormap) = &mut self.colormap {
+            // bare names stay as they are, so an unknown one is reported as such
+            if is_colormap_path(colormap) && Path::new(colormap).is_relative() {
+                *colormap = dir.join(&*colormap).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    /// Returns `true` if any setting besides the preset name is given.
+    pub fn has_overrides(&self) -> bool {
+        *self != SurfaceDesc::from_preset(&self.preset)
+    }
+
+    /// Loads the mesh, looks up the preset or compiles the expressions, then applies the overrides.
+    pub fn build(&self, registry: &SurfaceRegistry) -> anyhow::Result<Geometry> {
+        if let Some(path) = &self.mesh {
+            if !self.preset.is_empty() || self.x.is_some() || self.y.is_some() || self.z.is_some() {
+                anyhow::bail!("a surface is either a preset, expressions or a mesh, not several");
+            }
+            return self.apply_to_mesh(Mesh::load(path)?).map(Geometry::Mesh);
+        }
+        match (&self.x, &self.y, &self.z) {
+            (None, None, None) => {
+                if self.preset.is_empty() {
+                    anyhow::bail!(
+                        "missing surface preset or x, y and z expressions, presets are: {}",
+                        registry.names().join(", ")
+                    );
+                }
+                if self.param_names.is_some() {
+                    anyhow::bail!("param_names can only be used with x, y and z expressions");
+                }
+                match registry.get(&self.preset)? {
+                    Geometry::Parametric(ps) => {
+                        self.apply_to_parametric(ps).map(Geometry::Parametric)
+                    }
+                    Geometry::HeightField(hf) => {
+                        self.apply_to_height_field(hf).map(Geometry::HeightField)
+                    }
+                    Geometry::Mesh(mesh) => self.apply_to_mesh(mesh).map(Geometry::Mesh),
+                }
+            }
+            (Some(x), Some(y), Some(z)) => {
+                if !self.preset.is_empty() {
+                    anyhow::bail!(
+                        "a surface is either a preset or x, y and z expressions, not both"
+                    );
+                }
+                let param_names = self.param_names.clone().unwrap_or_default();
+                // a missing value would silently be zero and flatten the surface
+                let param_count = self.params.as_ref().map_or(0, Vec::len);
+                if param_count != param_names.len() {
+                    anyhow::bail!(
+                        "param_names needs {} params, got {}",
+                        param_names.len(),
+                        param_count
+                    );
+                }
+                let ps = ParametricSurface {
+                    f: compile_surface(x, y, z, &param_names)?,
+                    derivatives: Some(compile_surface_derivatives(x, y, z, &param_names)?),
+                    params: [0.0; 5],
+                    ..Default::default()
+                };
+                self.apply_to_parametric(ps).map(Geometry::Parametric)
+            }
+            _ => anyhow::bail!("x, y and z expressions must be given together"),
+        }
+    }
+
+    fn parametric_overrides(&self) -> [(&'static str, bool); 9] {
+        [
+            ("umin", self.umin.is_some()),
+            ("umax", self.umax.is_some()),
+            ("vmin", self.vmin.is_some()),
+            ("vmax", self.vmax.is_some()),
+            ("u_segments", self.u_segments.is_some()),
+            ("v_segments", self.v_segments.is_some()),
+            ("u_seam", self.u_seam.is_some()),
+            ("v_seam", self.v_seam.is_some()),
+            ("params", self.params.is_some()),
+        ]
+    }
+
+    fn height_field_overrides(&self) -> [(&'static str, bool); 6] {
+        [
+            ("xmin", self.xmin.is_some()),
+            ("xmax", self.xmax.is_some()),
+            ("zmin", self.zmin.is_some()),
+            ("zmax", self.zmax.is_some()),
+            ("x_segments", self.x_segments.is_some()),
+            ("z_segments", self.z_segments.is_some()),
+        ]
+    }
+
+    fn apply_to_mesh(&self, mut mesh: Mesh) -> anyhow::Result<Mesh> {
+        check_unused(&self.parametric_overrides(), "meshes")?;
+        check_unused(&self.height_field_overrides(), "meshes")?;
+        check_unused(&[("aspect", self.aspect.is_some())], "meshes")?;
+        if let Some(scale) = self.scale {
+            if scale <= 0.0 || !scale.is_finite() {
+                anyhow::bail!("scale must be positive, got {}", scale);
+            }
+            mesh.scale(scale);
+        }
+        let source = self.scalar_source()?;
+        let scale = self.color_scale(ColorScale::default());
+        scale.validate()?;
+        let contours = self.contour_lines(Contours::default());
+        contours.validate()?;
+        let colored = self.color.is_none()
+            && (self.colormap.is_some()
+                || self.reverse_colormap.is_some()
+                || source.is_some()
+                || scale != ColorScale::default());
+        let source = source.unwrap_or_default();
+        source.check(mesh.positions.len(), false)?;
+        if matches!(source, ScalarSource::U | ScalarSource::V) && mesh.uvs.is_empty() {
+            anyhow::bail!("scalar u or v needs texture coordinates, which the mesh does not have");
+        }
+        let values = if colored || self.alpha_ramp.is_some() || !contours.is_empty() {
+            mesh.scalar_values(&source, &[])
+        } else {
+            Vec::new()
+        };
+        let range = scale.resolve_range(&values, false);
+        let t: Vec<f32> = values.iter().map(|&x| scale.position(range, x)).collect();
+        let mut colormap = match &self.colormap {
+            Some(source) => Colormap::from_source(source)?,
+            None => Colormap::default(),
+        };
+        if self.reverse_colormap == Some(true) {
+            colormap = colormap.reversed();
+        }
+        if let Some(color) = self.color {
+            check_color(color).context("color")?;
+            mesh.fill_color(color);
+        } else if colored {
+            mesh.apply_scalar_colors(&colormap, &scale, &t);
+            mesh.color_key = Some(ColorKey {
+                colormap: colormap.clone(),
+                scale,
+                range,
+                label: source.label(),
+            });
+        }
+        if let Some(alpha) = self.alpha_ramp {
+            check_alpha_ramp(alpha)?;
+            mesh.apply_alpha_ramp(alpha, &t);
+        }
+        let overlay = ScalarOverlay::new(&colormap, &scale, &contours, range, colored);
+        mesh.apply_overlay(overlay, t);
+        Ok(mesh)
+    }
+
+    /// The scalar given by `colormap_direction`, `scalar` or `scalar_data`, if any.
+    fn scalar_source(&self) -> anyhow::Result<Option<ScalarSource>> {
+        match (&self.colormap_direction, &self.scalar, &self.scalar_data) {
+            (None, None, None) => Ok(None),
+            (Some(direction), None, None) => Ok(Some(ScalarSource::axis(axis_index(direction)?))),
+            (None, Some(scalar), None) => ScalarSource::parse(scalar).map(Some),
+            (None, None, Some(path)) => ScalarSource::load_data(path).map(Some),
+            _ => anyhow::bail!("colormap_direction, scalar and scalar_data exclude each other"),
+        }
+    }
+
+    /// `scale` with the scalar range, log scale and out of range color overridden.
+    fn color_scale(&self, mut scale: ColorScale) -> ColorScale {
+        if let Some(range) = self.scalar_range {
+            scale.range = Some(range);
+        }
+        if let Some(log) = self.log_scale {
+            scale.log = log;
+        }
+        if let Some(color) = self.out_of_range_color {
+            scale.out_of_range_color = Some(color);
+        }
+        if let Some(bands) = self.bands {
+            scale.bands = Some(bands);
+        }
+        scale
+    }
+
+    /// `contours` with the levels, count, color and width overridden.
+    fn contour_lines(&self, mut contours: Contours) -> Contours {
+        if let Some(levels) = &self.contours {
+            contours.levels = levels.clone();
+        }
+        if let Some(count) = self.contour_count {
+            contours.count = count;
+        }
+        if let Some(color) = self.contour_color {
+            contours.color = color;
+        }
+        if let Some(width) = self.contour_width {
+            contours.width = width;
+        }
+        contours
+    }
+
+    fn apply_to_height_field(&self, mut hf: HeightField) -> anyhow::Result<HeightField> {
+        check_unused(&self.parametric_overrides(), "height fields")?;
+        if let Some(xmin) = self.xmin {
+            hf.xmin = xmin;
+        }
+        if let Some(xmax) = self.xmax {
+            hf.xmax = xmax;
+        }
+        if let Some(zmin) = self.zmin {
+            hf.zmin = zmin;
+        }
+        if let Some(zmax) = self.zmax {
+            hf.zmax = zmax;
+        }
+        if let Some(x_segments) = self.x_segments {
+            hf.x_segments = x_segments;
+        }
+        if let Some(z_segments) = self.z_segments {
+            hf.z_segments = z_segments;
+        }
+        if let Some(scale) = self.scale {
+            hf.scale = scale;
+        }
+        if let Some(aspect) = self.aspect {
+            hf.aspect = aspect;
+        }
+        if let Some(source) = &self.colormap {
+            hf.colormap = Colormap::from_source(source)?;
+        }
+        if self.reverse_colormap == Some(true) {
+            hf.colormap = hf.colormap.reversed();
+        }
+        if let Some(scalar) = self.scalar_source()? {
+            hf.scalar = scalar;
+        }
+        hf.color_scale = self.color_scale(hf.color_scale);
+        hf.contours = self.contour_lines(hf.contours.clone());
+        if let Some(color) = self.color {
+            check_color(color).context("color")?;
+            hf.use_colormap = false;
+            hf.color = color;
+        }
+        if let Some(alpha) = self.alpha_ramp {
+            hf.alpha_ramp = alpha;
+        }
+        hf.validate()?;
+        Ok(hf)
+    }
+
+    fn apply_to_parametric(&self, mut ps: ParametricSurface) -> anyhow::Result<ParametricSurface> {
+        check_unused(&self.height_field_overrides(), "parametric surfaces")?;
+        if let Some(umin) = self.umin {
+            ps.umin = umin;
+        }
+        if let Some(umax) = self.umax {
+            ps.umax = umax;
+        }
+        if let Some(vmin) = self.vmin {
+            ps.vmin = vmin;
+        }
+        if let Some(vmax) = self.vmax {
+            ps.vmax = vmax;
+        }
+        if let Some(u_segments) = self.u_segments {
+            ps.u_segments = u_segments;
+        }
+        if let Some(v_segments) = self.v_segments {
+            ps.v_segments = v_segments;
+        }
+        if let Some(u_seam) = self.u_seam {
+            ps.u_seam = u_seam;
+        }
+        if let Some(v_seam) = self.v_seam {
+            ps.v_seam = v_seam;
+        }
+        if let Some(scale) = self.scale {
+            ps.scale = scale;
+        }
+        if let Some(aspect) = self.aspect {
+            ps.aspect = aspect;
+        }
+        if let Some(source) = &self.colormap {
+            ps.colormap = Colormap::from_source(source)?;
+        }
+        if self.reverse_colormap == Some(true) {
+            ps.colormap = ps.colormap.reversed();
+        }
+        if let Some(scalar) = self.scalar_source()? {
+            ps.scalar = scalar;
+        }
+        ps.color_scale = self.color_scale(ps.color_scale);
+        ps.contours = self.contour_lines(ps.contours.clone());
+        if let Some(color) = self.color {
+            check_color(color).context("color")?;
+            ps.use_colormap = false;
+            ps.color = color;
+        }
+        if let Some(alpha) = self.alpha_ramp {
+            ps.alpha_ramp = alpha;
+        }
+        if let Some(params) = &self.params {
+            if params.len() > ps.params.len() {
+                anyhow::bail!("at most {} params are supported", ps.params.len());
+            }
+            ps.params[..params.len()].copy_from_slice(params);
+        }
+        ps.validate()?;
+        Ok(ps)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    pub perspective: bool,
+    /// Vertical field of view in degrees, used for perspective projection.
+    pub fovy: f32,
+    /// Height of the view volume, used for orthographic projection.
+    pub ortho_height: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            position: [3.0, 1.5, 3.0],
+            target: [0.0, 0.0, 0.0],
+            perspective: true,
+            fovy: 72.0,
+            ortho_height: 6.0,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.position == self.target {
+            anyhow::bail!("position and target must differ");
+        }
+        if !(self.fovy > 0.0 && self.fovy < 180.0) {
+            anyhow::bail!("fovy must be between 0 and 180 degrees, got {}", self.fovy);
+        }
+        if self.ortho_height <= 0.0 {
+            anyhow::bail!("ortho_height must be positive, got {}", self.ortho_height);
+        }
+        if self.perspective && self.near <= 0.0 {
+            anyhow::bail!(
+                "near must be positive for a perspective camera, got {}",
+                self.near
+            );
+        }
+        if self.near >= self.far {
+            anyhow::bail!(
+                "near ({}) must be smaller than far ({})",
+                self.near,
+                self.far
+            );
+        }
+        Ok(())
+    }
+
+    pub fn projection_mat(&self, aspect: f32) -> Matrix4<f32> {
+        if self.perspective {
+            create_perspective_projection(Deg(self.fovy).into(), aspect, self.near, self.far)
+        } else {
+            let half_height = 0.5 * self.ortho_height;
+            let half_width = half_height * aspect;
+            create_projection_ortho(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                self.near,
+                self.far,
+            )
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LightKind {
+    /// Parallel rays along `direction`, e.g. sunlight.
+    Directional,
+    /// Shines in all directions from `position`.
+    Point,
+    /// Shines from `position` along `direction` within a cone.
+    Spot,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightSettings {
+    #[serde(rename = "type")]
+    pub kind: LightKind,
+    /// Switched off lights stay in the scene but do not shine.
+    pub enabled: bool,
+    /// Position of point and spot lights. When omitted the light sits at the camera and moves with it.
+    pub position: Option<[f32; 3]>,
+    /// Direction the light shines in, for directional and spot lights.
+    /// A spot light at the camera shines along the view direction instead.
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    pub specular_color: [f32; 3],
+    /// Scales both colors.
+    pub intensity: f32,
+    /// Constant, linear and quadratic coefficients of the distance attenuation
+    /// `1 / (c + l * d + q * d^2)` of point and spot lights.
+    pub attenuation: [f32; 3],
+    /// Half angle of the spot cone in degrees within which the light has full intensity.
+    pub inner_angle: f32,
+    /// Half angle of the spot cone in degrees outside of which there is no light.
+    pub outer_angle: f32,
+    /// Only the first enabled light casting shadows gets a shadow map.
+    pub cast_shadows: bool,
+}
+
+impl Default for LightSettings {
+    fn default() -> Self {
+        LightSettings {
+            kind: LightKind::Point,
+            enabled: true,
+            position: None,
+            direction: [0.0, -1.0, 0.0],
+            color: [1.0, 1.0, 1.0],
+            specular_color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            attenuation: [1.0, 0.0, 0.0],
+            inner_angle: 20.0,
+            outer_angle: 30.0,
+            cast_shadows: true,
+        }
+    }
+}
+
+impl LightSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        check_color(self.color).context("color")?;
+        check_color(self.specular_color).context("specular_color")?;
+        if let Some(position) = self.position {
+            if position.iter().any(|c| !c.is_finite()) {
+                anyhow::bail!("position must be finite, got {:?}", position);
+            }
+        }
+        if self.kind == LightKind::Directional && self.position.is_some() {
+            anyhow::bail!("directional lights have a direction but no position");
+        }
+        let direction = Vector3::from(self.direction);
+        if !(direction.magnitude2() > 0.0 && direction.magnitude2().is_finite()) {
+            anyhow::bail!(
+                "direction must be a non-zero vector, got {:?}",
+                self.direction
+            );
+        }
+        if self.intensity < 0.0 || !self.intensity.is_finite() {
+            anyhow::bail!(
+                "intensity must be a non-negative number, got {}",
+                self.intensity
+            );
+        }
+        let [c, l, q] = self.attenuation;
+        if [c, l, q].iter().any(|a| *a < 0.0 || !a.is_finite()) || c + l + q == 0.0 {
+            anyhow::bail!(
+                "attenuation coefficients must be non-negative and not all zero, got {:?}",
+                self.attenuation
+            );
+        }
+        if !(0.0 <= self.inner_angle
+            && self.inner_angle <= self.outer_angle
+            && self.outer_angle < 90.0)
+        {
+            anyhow::bail!(
+                "spot angles must satisfy 0 <= inner_angle <= outer_angle < 90, got {} and {}",
+                self.inner_angle,
+                self.outer_angle
+            );
+        }
+        Ok(())
+    }
+
+    /// Shader data for the light. Lights without a position use the eye position,
+    /// spot lights among them the view direction as well.
+    pub fn light(&self, eye_position: [f32; 3], view_direction: [f32; 3]) -> Light {
+        let (kind, position) = match self.kind {
+            LightKind::Directional => (LIGHT_DIRECTIONAL, [0.0; 3]),
+            LightKind::Point => (LIGHT_POINT, self.position.unwrap_or(eye_position)),
+            LightKind::Spot => (LIGHT_SPOT, self.position.unwrap_or(eye_position)),
+        };
+        let direction = match (self.kind, self.position) {
+            (LightKind::Spot, None) => view_direction,
+            _ => self.direction,
+        };
+        let direction = Vector3::from(direction).normalize();
+        let scaled = |c: [f32; 3]| {
+            [
+                c[0] * self.intensity,
+                c[1] * self.intensity,
+                c[2] * self.intensity,
+                1.0,
+            ]
+        };
+        let [c, l, q] = self.attenuation;
+        // a spot cone with equal angles would divide by zero in smoothstep
+        let outer_angle = self.outer_angle.max(self.inner_angle + 0.01);
+        Light {
+            position: [position[0], position[1], position[2], 1.0],
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: scaled(self.color),
+            specular_color: scaled(self.specular_color),
+            attenuation: [c, l, q, 0.0],
+            kind,
+            cos_inner: self.inner_angle.to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+            _padding: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Width and height of the shadow map in texels.
+    pub resolution: u32,
+    /// Depth offset against self-shadowing, in the normalized depth of the shadow map.
+    pub depth_bias: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            enabled: true,
+            resolution: 2048,
+            depth_bias: 0.002,
+        }
+    }
+}
+
+impl ShadowSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(64..=8192).contains(&self.resolution) {
+            anyhow::bail!(
+                "resolution must be within [64, 8192], got {}",
+                self.resolution
+            );
+        }
+        if !(0.0..0.1).contains(&self.depth_bias) {
+            anyhow::bail!(
+                "depth_bias must be within [0, 0.1), got {}",
+                self.depth_bias
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A square plane under the objects that receives their shadows.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct GroundSettings {
+    pub enabled: bool,
+    /// Height of the plane; by default it sits just below the lowest point the objects reach.
+    pub height: Option<f32>,
+    pub color: [f32; 3],
+}
+
+impl Default for GroundSettings {
+    fn default() -> Self {
+        GroundSettings {
+            enabled: false,
+            height: None,
+            color: [0.75, 0.75, 0.75],
+        }
+    }
+}
+
+impl GroundSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(height) = self.height {
+            if !height.is_finite() {
+                anyhow::bail!("height must be finite, got {}", height);
+            }
+        }
+        check_color(self.color).context("color")
+    }
+}
+
+/// Reflection model of a material.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShadingModel {
+    /// Blinn-Phong with ambient, diffuse and specular terms.
+    Phong,
+    /// Metallic/roughness model with a Cook-Torrance GGX specular term.
+    Pbr,
+}
+
+/// How an object reflects the light. The vertex colors are the diffuse color for Phong
+/// and the base color for PBR; `ambient` and `two_side` apply to both models.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialSettings {
+    pub model: ShadingModel,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    /// 0 for dielectrics, 1 for metals; PBR only.
+    pub metallic: f32,
+    /// From mirror-like 0 to fully rough 1; PBR only.
+    pub roughness: f32,
+    /// Light the back faces as well as the front faces.
+    pub two_side: bool,
+    /// `checker`, `uv_grid` or an image file wrapped around the surface along its
+    /// texture coordinates. It replaces the vertex colors.
+    pub texture: Option<String>,
+    /// How often the texture repeats along u and v.
+    pub texture_repeat: [f32; 2],
+    /// From invisible 0 to opaque 1. It multiplies the opacity of the vertex colors and texture.
+    pub opacity: f32,
+}
+
+impl Default for MaterialSettings {
+    fn default() -> Self {
+        MaterialSettings {
+            model: ShadingModel::Phong,
+            ambient: 0.1,
+            diffuse: 0.8,
+            specular: 0.4,
+            shininess: 30.0,
+            metallic: 0.0,
+            roughness: 0.5,
+            two_side: true,
+            texture: None,
+            texture_repeat: [1.0, 1.0],
+            opacity: 1.0,
+        }
+    }
+}
+
+impl MaterialSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("ambient", self.ambient),
+            ("diffuse", self.diffuse),
+            ("specular", self.specular),
+            ("shininess", self.shininess),
+        ] {
+            if value < 0.0 || !value.is_finite() {
+                anyhow::bail!("{} must be a non-negative number, got {}", name, value);
+            }
+        }
+        for (name, value) in [
+            ("metallic", self.metallic),
+            ("roughness", self.roughness),
+            ("opacity", self.opacity),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                anyhow::bail!("{} must be within [0, 1], got {}", name, value);
+            }
+        }
+        if self
+            .texture_repeat
+            .iter()
+            .any(|r| !(*r > 0.0 && r.is_finite()))
+        {
+            anyhow::bail!(
+                "texture_repeat must be positive, got {:?}",
+                self.texture_repeat
+            );
+        }
+        Ok(())
+    }
+
+    fn resolve_texture_path(&mut self, dir: &Path) {
+        if let Some(texture) = &mut self.texture {
+            if !is_builtin_texture(texture) && Path::new(texture).is_relative() {
+                *texture = dir.join(&*texture).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    pub fn load_texture(&self) -> anyhow::Result<Option<Arc<RgbaImage>>> {
+        match &self.texture {
+            Some(source) => Ok(Some(Arc::new(load_texture_image(source)?))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn material(&self) -> Material {
+        let material = material(
+            self.ambient,
+            self.diffuse,
+            self.specular,
+            self.shininess,
+            self.two_side as i32,
+        );
+        let material = match self.model {
+            ShadingModel::Phong => material,
+            ShadingModel::Pbr => material.pbr(self.metallic, self.roughness),
+        };
+        let material = match self.texture {
+            Some(_) => material.textured(self.texture_repeat),
+            None => material,
+        };
+        material.translucent(self.opacity)
+    }
+}
+
+/// Placement of an object: scaled first, then rotated about x, y and z, then translated.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformSettings {
+    pub translation: [f32; 3],
+    /// Rotation angles about the x, y and z axes in degrees.
+    pub rotation: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformSettings {
+    fn default() -> Self {
+        TransformSettings {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0],
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl TransformSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let values = self.translation.iter().chain(&self.rotation);
+        if values.chain(&self.scale).any(|c| !c.is_finite()) {
+            anyhow::bail!("translation, rotation and scale must be finite");
+        }
+        // a zero scale flattens the object and leaves no normal matrix
+        if self.scale.contains(&0.0) {
+            anyhow::bail!("scale must not be zero, got {:?}", self.scale);
+        }
+        Ok(())
+    }
+
+    pub fn matrix(&self) -> Matrix4<f32> {
+        create_transforms(
+            self.translation,
+            self.rotation.map(f32::to_radians),
+            self.scale,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationSettings {
+    pub auto_rotate: bool,
+    /// Rotation speed in radians per second.
+    pub speed: f32,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        AnimationSettings {
+            auto_rotate: true,
+            speed: 1.0,
+        }
+    }
+}
+
+impl AnimationSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.speed.is_finite() {
+            anyhow::bail!("speed must be finite, got {}", self.speed);
+        }
+        Ok(())
+    }
+}
+
+/// How the objects are drawn; the W key cycles through the modes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderMode {
+    /// Lit surfaces.
+    Shaded,
+    /// Only the triangle edges, including the ones at the back.
+    Wireframe,
+    /// The triangle edges drawn over the lit surfaces, which hide the edges behind them.
+    HiddenLine,
+}
+
+impl RenderMode {
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::Shaded => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::HiddenLine,
+            RenderMode::HiddenLine => RenderMode::Shaded,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub mode: RenderMode,
+    /// Color of the triangle edges in the wireframe and hidden-line modes.
+    pub wire_color: [f32; 3],
+    /// Samples per pixel for anti-aliasing: 1 or 4, the only counts wgpu renders with.
+    pub samples: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            mode: RenderMode::Shaded,
+            wire_color: [0.0, 0.0, 0.0],
+            samples: 4,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        check_color(self.wire_color).context("wire_color")?;
+        if ![1, 4].contains(&self.samples) {
+            anyhow::bail!("samples must be 1 or 4, got {}", self.samples);
+        }
+        Ok(())
+    }
+}
+
+/// Legend of the colors of one object, drawn over the frame.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorbarSettings {
+    pub enabled: bool,
+    /// Name of the object the colorbar explains, by default the first one colored by a colormap.
+    pub object: Option<String>,
+    /// Text above the bar, by default the name of the scalar. Empty for none.
+    pub title: Option<String>,
+    pub position: ColorbarPosition,
+    /// Number of tick labels aimed for; the ticks fall on round numbers.
+    pub ticks: usize,
+    /// Length of the bar as a fraction of the window height, or width when horizontal.
+    pub length: f32,
+    /// Color of the text, ticks and outline; by default black or white to stand out
+    /// against the background.
+    pub text_color: Option<[f32; 3]>,
+    /// Screen pixels per pixel of the 5x7 font.
+    pub text_scale: u32,
+}
+
+impl Default for ColorbarSettings {
+    fn default() -> Self {
+        ColorbarSettings {
+            enabled: false,
+            object: None,
+            title: None,
+            position: ColorbarPosition::Right,
+            ticks: 5,
+            length: 0.5,
+            text_color: None,
+            text_scale: 2,
+        }
+    }
+}
+
+impl ColorbarSettings {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(2..=20).contains(&self.ticks) {
+            anyhow::bail!("ticks must be within [2, 20], got {}", self.ticks);
+        }
+        if !(self.length > 0.0 && self.length <= 1.0) {
+            anyhow::bail!("length must be within (0, 1], got {}", self.length);
+        }
+        if let Some(color) = self.text_color {
+            check_color(color).context("text_color")?;
+        }
+        if !(1..=8).contains(&self.text_scale) {
+            anyhow::bail!("text_scale must be within [1, 8], got {}", self.text_scale);
+        }
+        Ok(())
+    }
+}
+
+/// Window edge the colorbar sits at, centered along it. The bar stands upright at the left
+/// and right edges and lies flat at the top and bottom.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorbarPosition {
+    Right,
+    Left,
+    Top,
+    Bottom,
+}
+
+/// Fails on the first override that is set although it does not apply to `kind`.
+fn check_unused(overrides: &[(&str, bool)], kind: &str) -> anyhow::Result<()> {
+    match overrides.iter().find(|(_, is_set)| *is_set) {
+        Some((name, _)) => anyhow::bail!("{} does not apply to {}", name, kind),
+        None => Ok(()),
+    }
+}
+
+fn axis_index(direction: &str) -> anyhow::Result<usize> {
+    match direction {
+        "x" => Ok(0),
+        "y" => Ok(1),
+        "z" => Ok(2),
+        _ => anyhow::bail!("colormap direction must be x, y or z, got '{}'", direction),
+    }
+}
+
+fn check_color(color: [f32; 3]) -> anyhow::Result<()> {
+    if color.iter().any(|c| !(0.0..=1.0).contains(c)) {
+        anyhow::bail!("color components must be within [0, 1], got {:?}", color);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `text` as a scene file with the given extension.
+    fn load(text: &str, extension: &str) -> anyhow::Result<Scene> {
+        static COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "ice-scene-{}-{}.{}",
+            std::process::id(),
+            count,
+            extension
+        ));
+        std::fs::write(&path, text).unwrap();
+        let scene = Scene::load(&path, &SurfaceRegistry::default());
+        std::fs::remove_file(&path).unwrap();
+        scene
+    }
+
+    /// The error of loading `text` as TOML, without the file name in front.
+    fn error(text: &str) -> String {
+        match load(text, "toml") {
+            Ok(_) => panic!("expected an error for {}", text),
+            Err(e) => e
+                .chain()
+                .skip(1)
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join(": "),
+        }
+    }
+
+    #[test]
+    fn shipped_scenes_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("toml" | "ron")
+            ) {
+                let scene = Scene::load(&path, &SurfaceRegistry::default());
+                assert!(
+                    scene.is_ok(),
+                    "{}: {:#}",
+                    path.display(),
+                    scene.err().unwrap()
+                );
+                count += 1;
+            }
+        }
+        assert!(count >= 14);
+    }
+
+    #[test]
+    fn misspelled_keys_are_rejected() {
+        let message = error("[[surfaces]]\npreset = \"torus\"\ncolourmap = \"hot\"\n");
+        assert!(message.contains("unknown field `colourmap`"), "{}", message);
+        let message = error("[[surfaces]]\npreset = \"torus\"\n[camera]\nfov = 60.0\n");
+        assert!(message.contains("unknown field `fov`"), "{}", message);
+    }
+
+    #[test]
+    fn errors_name_the_field() {
+        let torus = "[[surfaces]]\npreset = \"torus\"\n";
+        assert_eq!(
+            error(&format!("{}[camera]\nfovy = 180.0\n", torus)),
+            "camera: fovy must be between 0 and 180 degrees, got 180"
+        );
+        assert_eq!(
+            error(&format!("{}[[lights]]\ncolor = [1.0, 2.0, 0.0]\n", torus)),
+            "lights[0]: color: color components must be within [0, 1], got [1.0, 2.0, 0.0]"
+        );
+        assert_eq!(
+            error(
+                "[[objects]]\nsurface = { preset = \"sphere\" }\n\
+                 [objects.material]\nroughness = 1.5\n"
+            ),
+            "objects[0]: material: roughness must be within [0, 1], got 1.5"
+        );
+        assert_eq!(
+            error(&format!("{}[render]\nsamples = 2\n", torus)),
+            "render: samples must be 1 or 4, got 2"
+        );
+        assert_eq!(error(""), "the scene has no surfaces or objects");
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        let json = r#"{ "surfaces": [{ "preset": "sphere" }], "render": { "samples": 1 } }"#;
+        let scene = load(json, "json").unwrap();
+        assert_eq!(scene.objects[0].name, "sphere");
+        assert_eq!(scene.render.samples, 1);
+        // the same text is not TOML
+        assert!(load(json, "toml").is_err());
+        let message = format!("{:#}", load(json, "yaml").err().unwrap());
+        assert!(
+            message.starts_with("unsupported scene format 'yaml'"),
+            "{}",
+            message
+        );
+    }
+}
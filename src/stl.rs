@@ -0,0 +1,218 @@
+use crate::mesh::Mesh;
+use anyhow::Context;
+use cgmath::{InnerSpace, Vector3};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StlFormat {
+    Binary,
+    Ascii,
+}
+
+/// Writes the triangles as STL. Facet normals are recomputed from the triangle winding,
+/// since STL has no per-vertex normals or colors.
+pub fn write_stl(mesh: &Mesh, path: &Path, format: StlFormat) -> anyhow::Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut w = BufWriter::new(file);
+    let result = match format {
+        StlFormat::Binary => write_binary(mesh, &mut w),
+        StlFormat::Ascii => write_ascii(mesh, &mut w),
+    };
+    result
+        .and_then(|_| w.flush())
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn write_binary<W: Write>(mesh: &Mesh, w: &mut W) -> std::io::Result<()> {
+    let mut header = [0u8; 80];
+    let text = b"binary STL exported by Immersion's Control Engine";
+    header[..text.len()].copy_from_slice(text);
+    w.write_all(&header)?;
+    w.write_all(&(mesh.triangle_count() as u32).to_le_bytes())?;
+    for t in mesh.indices.chunks_exact(3) {
+        let (a, b, c) = triangle(mesh, t);
+        for value in facet_normal(a, b, c).iter().chain(&a).chain(&b).chain(&c) {
+            w.write_all(&value.to_le_bytes())?;
+        }
+        // attribute byte count, unused
+        w.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+fn write_ascii<W: Write>(mesh: &Mesh, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "solid surface")?;
+    for t in mesh.indices.chunks_exact(3) {
+        let (a, b, c) = triangle(mesh, t);
+        let n = facet_normal(a, b, c);
+        writeln!(w, "  facet normal {:e} {:e} {:e}", n[0], n[1], n[2])?;
+        writeln!(w, "    outer loop")?;
+        for p in [a, b, c] {
+            writeln!(w, "      vertex {:e} {:e} {:e}", p[0], p[1], p[2])?;
+        }
+        writeln!(w, "    endloop")?;
+        writeln!(w, "  endfacet")?;
+    }
+    writeln!(w, "endsolid surface")
+}
+
+fn triangle(mesh: &Mesh, t: &[u32]) -> ([f32; 3], [f32; 3], [f32; 3]) {
+    (
+        mesh.positions[t[0] as usize],
+        mesh.positions[t[1] as usize],
+        mesh.positions[t[2] as usize],
+    )
+}
+
+/// Unit normal of a counter-clockwise triangle, zero for degenerate triangles.
+pub fn facet_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let a = Vector3::from(a);
+    let n = (Vector3::from(b) - a).cross(Vector3::from(c) - a);
+    let len = n.magnitude();
+    if len > 0.0 && len.is_finite() {
+        (n / len).into()
+    } else {
+        [0.0, 0.0, 0.0]
+    }
+}
+
+/// Reads a binary or ASCII STL file. Every facet gets its own three vertices and its stored
+/// normal, which is recomputed from the winding when the file leaves it zero.
+pub(crate) fn read_stl(path: &Path) -> anyhow::Result<Mesh> {
+    let data = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_stl(&data).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+fn parse_stl(data: &[u8]) -> anyhow::Result<Mesh> {
+    // binary files can start with "solid" too, so trust the size computed from the facet count
+    let is_binary = data.len() >= 84 && {
+        let count = u32::from_le_bytes(data[80..84].try_into().unwrap()) as usize;
+        data.len() == 84 + 50 * count
+    };
+    let mut mesh = Mesh::default();
+    if is_binary {
+        for facet in data[84..].chunks_exact(50) {
+            let mut values = facet[..48]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()));
+            let mut next = || [(); 3].map(|_| values.next().unwrap());
+            let n = next();
+            let (a, b, c) = (next(), next(), next());
+            push_facet(&mut mesh, n, [a, b, c]);
+        }
+    } else {
+        let text = std::str::from_utf8(data).context("neither binary nor ASCII STL")?;
+        let mut tokens = text.split_whitespace();
+        if tokens.next() != Some("solid") {
+            anyhow::bail!("neither binary nor ASCII STL");
+        }
+        let mut normal = [0.0; 3];
+        let mut corners = Vec::with_capacity(3);
+        while let Some(token) = tokens.next() {
+            match token {
+                "normal" => normal = read_vector(&mut tokens)?,
+                "vertex" => corners.push(read_vector(&mut tokens)?),
+                "endloop" => {
+                    let facet: [[f32; 3]; 3] = corners.as_slice().try_into().map_err(|_| {
+                        anyhow::anyhow!("facet has {} vertices, expected 3", corners.len())
+                    })?;
+                    push_facet(&mut mesh, normal, facet);
+                    corners.clear();
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(mesh)
+}
+
+fn read_vector<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> anyhow::Result<[f32; 3]> {
+    let mut v = [0.0; 3];
+    for c in &mut v {
+        let token = tokens.next().context("unexpected end of file")?;
+        *c = token
+            .parse()
+            .with_context(|| format!("invalid number '{}'", token))?;
+    }
+    Ok(v)
+}
+
+fn push_facet(mesh: &mut Mesh, normal: [f32; 3], [a, b, c]: [[f32; 3]; 3]) {
+    let normal = if normal == [0.0; 3] || normal.iter().any(|c| !c.is_finite()) {
+        facet_normal(a, b, c)
+    } else {
+        normal
+    };
+    let first = mesh.positions.len() as u32;
+    mesh.positions.extend([a, b, c]);
+    mesh.normals.extend([normal; 3]);
+    mesh.indices.extend([first, first + 1, first + 2]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::tests::{tetrahedron, triangles};
+
+    #[test]
+    fn binary_round_trip() {
+        let mesh = tetrahedron();
+        let mut data = Vec::new();
+        write_binary(&mesh, &mut data).unwrap();
+        assert_eq!(data.len(), 84 + 50 * 4);
+        let read = parse_stl(&data).unwrap();
+        assert_eq!(triangles(&read), triangles(&mesh));
+        assert_eq!(read.normals[0], [0.0, 0.0, -1.0]);
+        assert_eq!(read.normals[9], [1.0 / 3f32.sqrt(); 3]);
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let mesh = tetrahedron();
+        let mut data = Vec::new();
+        write_ascii(&mesh, &mut data).unwrap();
+        let read = parse_stl(&data).unwrap();
+        assert_eq!(triangles(&read), triangles(&mesh));
+        assert_eq!(read.normals[3], [0.0, -1.0, 0.0]);
+    }
+
+    #[test]
+    fn zero_normals_are_recomputed() {
+        let text = "solid t facet normal 0 0 0 outer loop vertex 0 0 0 vertex 1 0 0 \
+                    vertex 0 1 0 endloop endfacet endsolid t";
+        let read = parse_stl(text.as_bytes()).unwrap();
+        assert_eq!(read.normals, vec![[0.0, 0.0, 1.0]; 3]);
+    }
+
+    #[test]
+    fn malformed() {
+        let mut data = Vec::new();
+        write_binary(&tetrahedron(), &mut data).unwrap();
+        // the size no longer matches the facet count, and binary data is not ASCII STL
+        data.truncate(data.len() - 10);
+        assert!(parse_stl(&data).is_err());
+        assert!(parse_stl(&data[..40]).is_err());
+
+        let two_vertices = "solid t facet normal 0 0 1 outer loop vertex 0 0 0 vertex 1 0 0 \
+                            endloop endfacet endsolid t";
+        assert_eq!(
+            parse_stl(two_vertices.as_bytes()).unwrap_err().to_string(),
+            "facet has 2 vertices, expected 3"
+        );
+        let cut = "solid t facet normal 0 0 1 outer loop vertex 0 0";
+        assert_eq!(
+            parse_stl(cut.as_bytes()).unwrap_err().to_string(),
+            "unexpected end of file"
+        );
+        let bad = "solid t facet normal 0 0 x";
+        assert_eq!(
+            parse_stl(bad.as_bytes()).unwrap_err().to_string(),
+            "invalid number 'x'"
+        );
+    }
+}
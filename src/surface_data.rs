@@ -1,50 +1,135 @@
 #![allow(dead_code)]
+use crate::colormap::{self, Colormap};
+use crate::curvature::Curvatures;
+use crate::math_func;
+use crate::scalar::{ColorScale, Contours, ScalarPoint, ScalarSource};
 use cgmath::*;
-mod colormap;
+use serde::{Deserialize, Serialize};
 use std::f32::consts::PI;
-#[path = "./math_func.rs"]
-mod math_func;
+use std::sync::Arc;
+
+/// Parametric function mapping `(u, v, params)` to a point. Shared so surfaces stay cheap to clone.
+pub type SurfaceFn = Arc<dyn Fn(f32, f32, [f32; 5]) -> [f32; 3] + Send + Sync>;
+
+/// Partial derivatives `[df/du, df/dv]` of a `SurfaceFn` at `(u, v, params)`.
+pub type DerivativeFn = Arc<dyn Fn(f64, f64, [f32; 5]) -> [[f64; 3]; 2] + Send + Sync>;
+
+/// How the edges of the parameter domain along one parameter meet.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Seam {
+    /// The edges are boundaries of the surface.
+    #[default]
+    Open,
+    /// The surface closes up: `f(umax, v) = f(umin, v)` for a seam along u.
+    Periodic,
+    /// The surface closes up with the other parameter reversed, as in a Moebius strip:
+    /// `f(umax, v) = f(umin, vmin + vmax - v)` for a seam along u.
+    Flipped,
+}
 
+#[derive(Clone)]
 pub struct ParametricSurface {
-    pub f: fn(f32, f32, [f32; 5]) -> [f32; 3],
+    pub f: SurfaceFn,
+    /// Exact partial derivatives of `f`. Without them the normals come from central differences.
+    pub derivatives: Option<DerivativeFn>,
     pub umin: f32,
     pub umax: f32,
     pub vmin: f32,
     pub vmax: f32,
     pub u_segments: usize,
     pub v_segments: usize,
+    /// Where `u = umax` meets `u = umin`; the tessellation welds the seam into shared vertices.
+    pub u_seam: Seam,
+    /// Where `v = vmax` meets `v = vmin`.
+    pub v_seam: Seam,
     pub scale: f32,
     pub aspect: f32,
     pub use_colormap: bool,
-    pub colormap_name: &'static str,
-    pub colormap_direction: &'static str,
+    pub colormap: Colormap,
+    /// Quantity the colormap follows.
+    pub scalar: ScalarSource,
+    pub color_scale: ColorScale,
+    /// Lines drawn where the scalar crosses chosen levels.
+    pub contours: Contours,
     pub color: [f32; 3],
+    /// Opacity at the low and high end of the colormap range, interpolated linearly in between.
+    pub alpha_ramp: [f32; 2],
     pub params: [f32; 5],
 }
 
 impl Default for ParametricSurface {
     fn default() -> Self {
         ParametricSurface {
-            f: math_func::torus,
+            f: Arc::new(math_func::torus),
+            derivatives: None,
             umin: 0.0,
             umax: 2.0 * PI,
             vmin: 0.0,
             vmax: 2.0 * PI,
             u_segments: 32,
             v_segments: 24,
+            u_seam: Seam::Open,
+            v_seam: Seam::Open,
             scale: 1.5,
             aspect: 1.0,
             use_colormap: true,
-            colormap_name: "jet",
-            colormap_direction: "y",
+            colormap: Colormap::default(),
+            scalar: ScalarSource::Y,
+            color_scale: ColorScale::default(),
+            contours: Contours::default(),
             color: [1.0, 0.0, 0.0],
+            alpha_ramp: [1.0, 1.0],
             params: [1.0, 0.3, 0.0, 0.0, 0.0],
         }
     }
 }
 
 impl ParametricSurface {
-    pub fn new(ps: ParametricSurface) -> (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<u32>) {
+    /// Checks that the domain, tessellation and colormap settings can produce a mesh.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.umin >= self.umax || !self.umin.is_finite() || !self.umax.is_finite() {
+            anyhow::bail!(
+                "umin ({}) must be finite and smaller than umax ({})",
+                self.umin,
+                self.umax
+            );
+        }
+        if self.vmin >= self.vmax || !self.vmin.is_finite() || !self.vmax.is_finite() {
+            anyhow::bail!(
+                "vmin ({}) must be finite and smaller than vmax ({})",
+                self.vmin,
+                self.vmax
+            );
+        }
+        if self.u_segments == 0 || self.v_segments == 0 {
+            anyhow::bail!(
+                "segment counts must be at least 1, got {}x{}",
+                self.u_segments,
+                self.v_segments
+            );
+        }
+        if self.scale <= 0.0 || !self.scale.is_finite() || !self.aspect.is_finite() {
+            anyhow::bail!(
+                "scale must be positive and aspect finite, got scale {} and aspect {}",
+                self.scale,
+                self.aspect
+            );
+        }
+        self.scalar
+            .check((self.u_segments + 1) * (self.v_segments + 1), true)?;
+        self.color_scale.validate()?;
+        self.contours.validate()?;
+        check_alpha_ramp(self.alpha_ramp)
+    }
+
+    /// Tessellates the surface into positions, normals, colors and triangle indices. With a
+    /// colormap the vertices are colored at their `positions_on_colormap`, see
+    /// `ColorScale::normalize`.
+    pub fn new(
+        ps: ParametricSurface,
+        positions_on_colormap: &[f32],
+    ) -> (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<u32>) {
         let n_vertices = (ps.u_segments + 1) * (ps.v_segments + 1);
         let mut positions: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
         let mut normals: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
@@ -53,34 +138,9 @@ impl ParametricSurface {
         let du = (ps.umax - ps.umin) / ps.u_segments as f32;
         let dv = (ps.vmax - ps.vmin) / ps.v_segments as f32;
 
-        let eps = 0.00001;
         let mut p0: Vector3<f32>;
-        let mut p1: Vector3<f32>;
-        let mut p2: Vector3<f32>;
-        let mut p3: Vector3<f32>;
         let mut pa: [f32; 3];
 
-        let mut cd = 1;
-        if ps.colormap_direction == "x" {
-            cd = 0;
-        } else if ps.colormap_direction == "z" {
-            cd = 2;
-        }
-
-        let (min, max) = parametric_surface_range(
-            ps.f,
-            ps.umin,
-            ps.umax,
-            ps.vmin,
-            ps.vmax,
-            ps.u_segments,
-            ps.v_segments,
-            ps.scale,
-            ps.aspect,
-            ps.params,
-            cd,
-        );
-
         for i in 0..=ps.u_segments {
             let u = ps.umin + i as f32 * du;
             for j in 0..=ps.v_segments {
@@ -92,48 +152,13 @@ impl ParametricSurface {
                     ps.scale * pa[2],
                 );
 
-                // calculate normals
-                if u - eps >= 0.0 {
-                    pa = (ps.f)(u - eps, v, ps.params);
-                    p1 = Vector3::new(
-                        pa[0] * ps.scale,
-                        ps.scale * ps.aspect * pa[1],
-                        ps.scale * pa[2],
-                    );
-                    p2 = p0 - p1;
-                } else {
-                    pa = (ps.f)(u + eps, v, ps.params);
-                    p1 = Vector3::new(
-                        pa[0] * ps.scale,
-                        ps.scale * ps.aspect * pa[1],
-                        ps.scale * pa[2],
-                    );
-                    p2 = p1 - p0;
-                }
-
-                if v - eps >= 0.0 {
-                    pa = (ps.f)(u, v - eps, ps.params);
-                    p1 = Vector3::new(
-                        pa[0] * ps.scale,
-                        ps.scale * ps.aspect * pa[1],
-                        ps.scale * pa[2],
-                    );
-                    p3 = p0 - p1;
-                } else {
-                    pa = (ps.f)(u, v + eps, ps.params);
-                    p1 = Vector3::new(
-                        pa[0] * ps.scale,
-                        ps.scale * ps.aspect * pa[1],
-                        ps.scale * pa[2],
-                    );
-                    p3 = p1 - p0;
-                }
-                let normal = p3.cross(p2).normalize();
+                let normal = ps.normal(u, v).unwrap_or(Vector3::zero());
 
                 // calculate colrmap
                 let mut color = ps.color;
                 if ps.use_colormap {
-                    color = colormap::color_interp(ps.colormap_name, min, max, p0[cd]);
+                    let t = positions_on_colormap[positions.len()];
+                    color = ps.color_scale.color(&ps.colormap, t);
                 }
 
                 positions.push(p0.into());
@@ -168,44 +193,288 @@ impl ParametricSurface {
         }
         (positions, normals, colors, indices)
     }
+
+    /// Scalar of every vertex of the tessellation, row by row along u like the vertices.
+    pub fn scalar_values(&self) -> Vec<f32> {
+        let du = (self.umax - self.umin) / self.u_segments as f32;
+        let dv = (self.vmax - self.vmin) / self.v_segments as f32;
+        let curvature = self.scalar.is_curvature();
+        (0..=self.u_segments)
+            .flat_map(|i| (0..=self.v_segments).map(move |j| (i, j)))
+            .enumerate()
+            .map(|(index, (i, j))| {
+                let (u, v) = (self.umin + i as f32 * du, self.vmin + j as f32 * dv);
+                let p = (self.f)(u, v, self.params);
+                let point = ScalarPoint {
+                    u,
+                    v,
+                    position: [
+                        p[0] * self.scale,
+                        p[1] * self.scale * self.aspect,
+                        p[2] * self.scale,
+                    ],
+                    normal: self.normal(u, v).unwrap_or(Vector3::zero()).into(),
+                    curvatures: if curvature {
+                        self.curvatures(u, v)
+                    } else {
+                        None
+                    },
+                };
+                self.scalar.value(index, &point)
+            })
+            .collect()
+    }
+
+    /// Unit normal at `(u, v)`, oriented along `df/dv x df/du`. Where the partial derivatives
+    /// vanish or are parallel, e.g. at the poles of a sphere, the normal is taken a little further
+    /// inside the domain. `None` if there is no usable normal nearby.
+    pub fn normal(&self, u: f32, v: f32) -> Option<Vector3<f32>> {
+        for (u, v) in self.nearby(u, v) {
+            let [fu, fv] = self.partials(u, v);
+            if let Some(n) = unit_normal(fu, fv) {
+                return Some(n.cast().unwrap());
+            }
+        }
+        None
+    }
+
+    /// Curvatures at `(u, v)` from the first and second fundamental forms, taken a little further
+    /// inside the domain where the normal is undefined. `None` if there is no usable point nearby.
+    pub fn curvatures(&self, u: f32, v: f32) -> Option<Curvatures> {
+        self.nearby(u, v).into_iter().find_map(|(u, v)| {
+            let first = self.partials(u, v);
+            // a parameter line shrinking to a point, as at a pole, leaves only rounding noise in
+            // its derivative, which the normal tolerates but the second derivatives do not
+            let lengths = [
+                first[0].magnitude() * (self.umax - self.umin) as f64,
+                first[1].magnitude() * (self.vmax - self.vmin) as f64,
+            ];
+            if lengths[0].min(lengths[1]) < 1e-4 * lengths[0].max(lengths[1]) {
+                return None;
+            }
+            let n = unit_normal(first[0], first[1])?;
+            Curvatures::from_derivatives(first, self.second_partials(u, v), n)
+        })
+    }
+
+    /// `(u, v)` followed by two points successively further towards the center of the domain.
+    fn nearby(&self, u: f32, v: f32) -> [(f64, f64); 3] {
+        let (u, v) = (u as f64, v as f64);
+        let (umin, umax) = (self.umin as f64, self.umax as f64);
+        let (vmin, vmax) = (self.vmin as f64, self.vmax as f64);
+        let step_u = 1e-3 * (umax - umin) * (0.5 * (umin + umax) - u).signum();
+        let step_v = 1e-3 * (vmax - vmin) * (0.5 * (vmin + vmax) - v).signum();
+        [0.0, 1.0, 10.0].map(|k| (u + k * step_u, v + k * step_v))
+    }
+
+    /// Partial derivatives of the scaled surface, from `derivatives` if given,
+    /// otherwise from central differences computed in f64.
+    fn partials(&self, u: f64, v: f64) -> [Vector3<f64>; 2] {
+        let [fu, fv] = match &self.derivatives {
+            Some(derivatives) => derivatives(u, v, self.params),
+            None => {
+                let eval =
+                    |u: f64, v: f64| (self.f)(u as f32, v as f32, self.params).map(f64::from);
+                // the step balances the truncation error against the rounding of f32 evaluations
+                let hu = 1e-3 * (self.umax - self.umin) as f64;
+                let hv = 1e-3 * (self.vmax - self.vmin) as f64;
+                [
+                    difference(|t| eval(t, v), u, hu),
+                    difference(|t| eval(u, t), v, hv),
+                ]
+            }
+        };
+        let s = [
+            self.scale as f64,
+            (self.scale * self.aspect) as f64,
+            self.scale as f64,
+        ];
+        [
+            Vector3::new(fu[0] * s[0], fu[1] * s[1], fu[2] * s[2]),
+            Vector3::new(fv[0] * s[0], fv[1] * s[1], fv[2] * s[2]),
+        ]
+    }
+
+    /// Second partial derivatives `[fuu, fuv, fvv]` of the scaled surface, from central
+    /// differences of `partials`.
+    fn second_partials(&self, u: f64, v: f64) -> [Vector3<f64>; 3] {
+        // differences of differences lose more precision, so they need a wider step
+        let relative = if self.derivatives.is_some() {
+            1e-4
+        } else {
+            1e-2
+        };
+        let hu = relative * (self.umax - self.umin) as f64;
+        let hv = relative * (self.vmax - self.vmin) as f64;
+        let fuu = difference(|t| self.partials(t, v)[0].into(), u, hu);
+        let fvv = difference(|t| self.partials(u, t)[1].into(), v, hv);
+        // both mixed derivatives, averaged to cancel part of the error
+        let fuv = difference(|t| self.partials(u, t)[0].into(), v, hv);
+        let fvu = difference(|t| self.partials(t, v)[1].into(), u, hu);
+        [
+            fuu.into(),
+            (Vector3::from(fuv) + Vector3::from(fvu)) * 0.5,
+            fvv.into(),
+        ]
+    }
 }
 
-fn parametric_surface_range(
-    f: fn(f32, f32, [f32; 5]) -> [f32; 3],
-    umin: f32,
-    umax: f32,
-    vmin: f32,
-    vmax: f32,
-    nu: usize,
-    nv: usize,
-    scale: f32,
-    aspect: f32,
-    params: [f32; 5],
-    dir: usize,
-) -> (f32, f32) {
-    let du = (umax - umin) / nu as f32;
-    let dv = (vmax - vmin) / nv as f32;
-    let mut min: f32 = std::f32::MAX;
-    let mut max: f32 = std::f32::MIN;
-
-    for i in 0..=nu {
-        let u = umin + i as f32 * du;
-        for j in 0..=nv {
-            let v = vmin + j as f32 * dv;
-            let mut pt = f(u, v, params);
-            pt = [pt[0] * scale, scale * aspect * pt[1], scale * pt[2]];
-            min = if pt[dir] < min { pt[dir] } else { min };
-            max = if pt[dir] > max { pt[dir] } else { max };
+/// Unit vector along `fv x fu`, or `None` where the partial derivatives vanish or are parallel.
+fn unit_normal(fu: Vector3<f64>, fv: Vector3<f64>) -> Option<Vector3<f64>> {
+    let n = fv.cross(fu);
+    let length = n.magnitude();
+    (length.is_finite() && length > 1e-9 * fu.magnitude() * fv.magnitude()).then(|| n / length)
+}
+
+/// Derivative of `g` at `t` by central differences, falling back to one-sided differences
+/// where `g` is not finite on one side, e.g. at the edge of its domain.
+fn difference(g: impl Fn(f64) -> [f64; 3], t: f64, h: f64) -> [f64; 3] {
+    for (a, b) in [(t - h, t + h), (t, t + h), (t - h, t)] {
+        // divide by the step actually taken once the parameters are rounded to f32
+        let (a, b) = (a as f32 as f64, b as f32 as f64);
+        let (ga, gb) = (g(a), g(b));
+        let d = [0, 1, 2].map(|k| (gb[k] - ga[k]) / (b - a));
+        if d.iter().all(|c| c.is_finite()) {
+            return d;
+        }
+    }
+    [f64::NAN; 3]
+}
+
+/// Height function mapping `(x, z)` to the point `[x, y, z]`.
+pub type HeightFn = Arc<dyn Fn(f32, f32) -> [f32; 3] + Send + Sync>;
+
+/// Height field `y = f(x, z)` sampled on a regular grid over the x/z domain.
+/// The samples are normalized into a cube of half size `scale`; an `aspect` below 1 flattens the heights.
+#[derive(Clone)]
+pub struct HeightField {
+    pub f: HeightFn,
+    pub xmin: f32,
+    pub xmax: f32,
+    pub zmin: f32,
+    pub zmax: f32,
+    pub x_segments: usize,
+    pub z_segments: usize,
+    pub scale: f32,
+    pub aspect: f32,
+    pub use_colormap: bool,
+    pub colormap: Colormap,
+    /// Quantity the colormap follows; `u` and `v` are `x` and `z` before normalization.
+    pub scalar: ScalarSource,
+    pub color_scale: ColorScale,
+    /// Lines drawn where the scalar crosses chosen levels.
+    pub contours: Contours,
+    pub color: [f32; 3],
+    /// Opacity at the low and high end of the colormap range, interpolated linearly in between.
+    pub alpha_ramp: [f32; 2],
+}
+
+impl Default for HeightField {
+    fn default() -> Self {
+        HeightField {
+            f: Arc::new(math_func::peaks),
+            xmin: -3.0,
+            xmax: 3.0,
+            zmin: -3.0,
+            zmax: 3.0,
+            x_segments: 48,
+            z_segments: 48,
+            scale: 1.5,
+            aspect: 0.5,
+            use_colormap: true,
+            colormap: Colormap::default(),
+            scalar: ScalarSource::Y,
+            color_scale: ColorScale::default(),
+            contours: Contours::default(),
+            color: [1.0, 0.0, 0.0],
+            alpha_ramp: [1.0, 1.0],
+        }
+    }
+}
+
+impl HeightField {
+    /// Checks that the domain, tessellation and colormap settings can produce a mesh.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.xmin >= self.xmax || !self.xmin.is_finite() || !self.xmax.is_finite() {
+            anyhow::bail!(
+                "xmin ({}) must be finite and smaller than xmax ({})",
+                self.xmin,
+                self.xmax
+            );
+        }
+        if self.zmin >= self.zmax || !self.zmin.is_finite() || !self.zmax.is_finite() {
+            anyhow::bail!(
+                "zmin ({}) must be finite and smaller than zmax ({})",
+                self.zmin,
+                self.zmax
+            );
         }
+        if self.x_segments == 0 || self.z_segments == 0 {
+            anyhow::bail!(
+                "segment counts must be at least 1, got {}x{}",
+                self.x_segments,
+                self.z_segments
+            );
+        }
+        if self.scale <= 0.0 || !self.scale.is_finite() {
+            anyhow::bail!("scale must be positive, got {}", self.scale);
+        }
+        // larger values shrink the normalized height range to nothing
+        if !(self.aspect > 0.0 && self.aspect <= 1.0) {
+            anyhow::bail!(
+                "aspect must be within (0, 1] for height fields, got {}",
+                self.aspect
+            );
+        }
+        self.scalar
+            .check((self.x_segments + 1) * (self.z_segments + 1), false)?;
+        self.color_scale.validate()?;
+        self.contours.validate()?;
+        check_alpha_ramp(self.alpha_ramp)
+    }
+
+    /// Samples the grid points, indexed as `points[i][j]` for the `i`-th x and `j`-th z value.
+    pub fn points(&self) -> Vec<Vec<[f32; 3]>> {
+        let (pts, _) = simple_surface_points(
+            &*self.f,
+            self.xmin,
+            self.xmax,
+            self.zmin,
+            self.zmax,
+            self.x_segments + 1,
+            self.z_segments + 1,
+            self.scale,
+            self.aspect,
+        );
+        pts
+    }
+
+    /// Domain coordinates `[x, z]` of every grid vertex, row by row along x like `points`.
+    pub fn grid_parameters(&self) -> Vec<[f32; 2]> {
+        let dx = (self.xmax - self.xmin) / self.x_segments as f32;
+        let dz = (self.zmax - self.zmin) / self.z_segments as f32;
+        (0..=self.x_segments)
+            .flat_map(|i| {
+                (0..=self.z_segments)
+                    .map(move |j| [self.xmin + i as f32 * dx, self.zmin + j as f32 * dz])
+            })
+            .collect()
     }
-    return (min, max);
 }
+
+pub fn check_alpha_ramp(alpha: [f32; 2]) -> anyhow::Result<()> {
+    if alpha.iter().any(|a| !(0.0..=1.0).contains(a)) {
+        anyhow::bail!("alpha ramp must be within [0, 1], got {:?}", alpha);
+    }
+    Ok(())
+}
+
 pub fn simple_surface_colors(
     pts: &Vec<Vec<[f32; 3]>>,
     nx: usize,
     nz: usize,
     yrange: [f32; 2],
-    colormap_name: &str,
+    colormap: &Colormap,
 ) -> Vec<[f32; 3]> {
     let mut colors: Vec<[f32; 3]> = Vec::with_capacity((4 * (nx - 1) * (nz - 1)) as usize);
     for i in 0..nx - 1 {
@@ -215,10 +484,10 @@ pub fn simple_surface_colors(
             let p2 = pts[i + 1][j + 1];
             let p3 = pts[i + 1][j];
 
-            let c0 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p0[1]);
-            let c1 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p1[1]);
-            let c2 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p2[1]);
-            let c3 = colormap::color_interp(colormap_name, yrange[0], yrange[1], p3[1]);
+            let c0 = colormap::color_interp(colormap, yrange[0], yrange[1], p0[1]);
+            let c1 = colormap::color_interp(colormap, yrange[0], yrange[1], p1[1]);
+            let c2 = colormap::color_interp(colormap, yrange[0], yrange[1], p2[1]);
+            let c3 = colormap::color_interp(colormap, yrange[0], yrange[1], p3[1]);
 
             colors.push(c0);
             colors.push(c1);
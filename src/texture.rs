@@ -0,0 +1,135 @@
+use anyhow::Context;
+use image::{Rgba, RgbaImage};
+use std::path::Path;
+use wgpu::{self, util::DeviceExt, *};
+
+/// Built-in textures, usable by name wherever an image file is expected.
+pub const TEXTURE_NAMES: [&str; 2] = ["checker", "uv_grid"];
+
+/// Returns `true` if `source` names a built-in texture rather than an image file.
+pub fn is_builtin_texture(source: &str) -> bool {
+    TEXTURE_NAMES.contains(&source)
+}
+
+/// Loads one of the `TEXTURE_NAMES` or an image file in any format the `image` crate reads.
+pub fn load_texture_image(source: &str) -> anyhow::Result<RgbaImage> {
+    match source {
+        "checker" => Ok(checker_image(512, 8)),
+        "uv_grid" => Ok(uv_grid_image(512, 8)),
+        _ => {
+            let path = Path::new(source);
+            let image = image::open(path)
+                .with_context(|| format!("failed to read texture {}", path.display()))?;
+            Ok(image.to_rgba8())
+        }
+    }
+}
+
+/// Black and white squares, `cells` along each side of the image.
+pub fn checker_image(size: u32, cells: u32) -> RgbaImage {
+    let cell = (size / cells).max(1);
+    RgbaImage::from_fn(size, size, |x, y| {
+        if (x / cell + y / cell).is_multiple_of(2) {
+            Rgba([235, 235, 235, 255])
+        } else {
+            Rgba([40, 40, 40, 255])
+        }
+    })
+}
+
+/// Red growing with u and green with v, crossed by dark grid lines, so stretched or sheared
+/// cells show where the parametrization distorts the surface.
+pub fn uv_grid_image(size: u32, cells: u32) -> RgbaImage {
+    let cell = (size / cells).max(1);
+    let line = (cell / 16).max(1);
+    RgbaImage::from_fn(size, size, |x, y| {
+        if x % cell < line || y % cell < line {
+            return Rgba([20, 20, 20, 255]);
+        }
+        // texture coordinates start at the top left corner, like the image rows
+        let u = x as f32 / (size - 1).max(1) as f32;
+        let v = y as f32 / (size - 1).max(1) as f32;
+        Rgba([
+            (40.0 + 215.0 * u) as u8,
+            (40.0 + 215.0 * v) as u8,
+            (200.0 * (1.0 - 0.5 * (u + v))) as u8,
+            255,
+        ])
+    })
+}
+
+/// Layout of the texture bind group (group 2): the surface texture and its sampler.
+pub fn create_texture_bind_group_layout(device: &Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: Some("Texture Bind Group Layout"),
+    })
+}
+
+/// Uploads `image` as an sRGB texture and binds it with a repeating, linearly filtering sampler.
+pub fn create_texture_bind_group(
+    device: &Device,
+    queue: &Queue,
+    layout: &BindGroupLayout,
+    image: &RgbaImage,
+) -> BindGroup {
+    let texture = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: image.width(),
+                height: image.height(),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            label: Some("Surface Texture"),
+        },
+        image.as_raw(),
+    );
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Surface Sampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+        ],
+        label: Some("Texture Bind Group"),
+    })
+}
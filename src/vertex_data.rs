@@ -1,8 +1,7 @@
 #![allow(dead_code)]
 
+use crate::math_func;
 use cgmath::*;
-#[path = "../src/math_func.rs"]
-mod math_func;
 
 /// Shape function for a torus.
 pub fn torus_data(
@@ -55,7 +54,7 @@ pub fn torus_data(
 pub fn sphere_data(r: f32, u: usize, v: usize) -> (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<[f32; 2]>) {
     let mut positions: Vec<[f32; 3]> = Vec::with_capacity((4 * (u - 1) * (v - 1)) as usize);
     let mut normals: Vec<[f32; 3]> = Vec::with_capacity((4 * (u - 1) * (v - 1)) as usize);
-    let uvs: Vec<[f32; 2]> = Vec::with_capacity((4 * (u - 1) * (v - 1)) as usize);
+    let mut uvs: Vec<[f32; 2]> = Vec::with_capacity((4 * (u - 1) * (v - 1)) as usize);
 
     for i in 0..u - 1 {
         for j in 0..v - 1 {
@@ -83,6 +82,18 @@ pub fn sphere_data(r: f32, u: usize, v: usize) -> (Vec<[f32; 3]>, Vec<[f32; 3]>,
             normals.push([p1[0] / r, p1[1] / r, p1[2] / r]);
             normals.push([p2[0] / r, p2[1] / r, p2[2] / r]);
             normals.push([p3[0] / r, p3[1] / r, p3[2] / r]);
+
+            // uvs: u follows phi around the axis, v follows theta from pole to pole
+            let uv0 = [phi / 360.0, theta / 180.0];
+            let uv1 = [phi / 360.0, theta1 / 180.0];
+            let uv2 = [phi1 / 360.0, theta1 / 180.0];
+            let uv3 = [phi1 / 360.0, theta / 180.0];
+            uvs.push(uv0);
+            uvs.push(uv1);
+            uvs.push(uv3);
+            uvs.push(uv1);
+            uvs.push(uv2);
+            uvs.push(uv3);
         }
     }
     (positions, normals, uvs)